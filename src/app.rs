@@ -1,5 +1,6 @@
 use std::future::Future;
 use std::io::Cursor;
+use std::path::PathBuf;
 use std::sync::{Arc, Condvar, Mutex};
 
 use eframe::Frame;
@@ -8,12 +9,97 @@ use log::{debug, error, info};
 use rfd::AsyncFileDialog;
 
 use crate::hw::Nes;
-use crate::rom::Cartridge;
+use crate::audio::{AudioBackend, AudioPipeline, AudioSettings, AudioSink, FilterOptions, NullBackend, SampleRing};
+use crate::hw::apu::{ExpansionOptions, NATIVE_SAMPLE_RATE};
+use crate::record::{Recorder, RecordingFormat};
+use crate::hw::ppu::{Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::rom::{Cartridge, PpuModel};
+use crate::video::{FilterChain, HdPack, Image, NtscFilter, NtscFilterOptions, NtscPaletteOptions, Palette, Scaler, NTSC_OUTPUT_WIDTH};
+
+mod battery;
+mod capture;
+use capture::{AudioRecording, RecordingFile, RECORDING_SAMPLE_RATE};
+mod debug;
+use debug::PpuViewer;
+mod display;
+use display::*;
+mod events;
+use events::EventViewer;
+mod hd_pack;
+mod input;
+mod mixer;
+use mixer::MixerWindow;
+mod nsf;
+use nsf::NsfWindow;
+mod sound;
+use sound::AudioWindow;
+
+/// Approximate height of the menu bar, used to size the window before the menu has been laid out
+const DEFAULT_MENU_HEIGHT: f32 = 24.0;
+/// The app's name and the ID eframe stores its settings under
+#[cfg(not(target_arch = "wasm32"))]
+pub const APP_ID: &str = "ners";
+const SCREENSHOT_KEY: egui::Key = egui::Key::F12;
+const AUDIO_SETTINGS_KEY: &str = "audio";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaletteSource {
+    /// The built-in palette for the loaded game's PPU
+    Builtin,
+    /// Generated from the 2C02's NTSC signal
+    Ntsc,
+    /// Loaded from a .pal file
+    File,
+}
 
-// TODO: add serialization for app
 pub struct NersApp {
     console: Arc<Mutex<Nes>>,
     frame_signal: Arc<Condvar>,
+    /// file name of the loaded ROM, without the extension
+    rom_name: Arc<Mutex<Option<String>>>,
+    /// Save file for the battery-backed RAM of the game that's loaded
+    save_path: Arc<Mutex<Option<PathBuf>>>,
+    palette: Palette,
+    palette_source: PaletteSource,
+    palette_model: PpuModel,
+    /// palette most recently loaded from a file
+    file_palette: Arc<Mutex<Option<Palette>>>,
+    /// composite video filter, when enabled
+    ntsc_filter: Option<NtscFilter>,
+    /// scaling and CRT effects applied after the palette or NTSC filter
+    filters: FilterChain,
+    /// replacement graphics drawn instead of the palette or NTSC filter, when loaded
+    hd_pack: Option<HdPack>,
+    /// HD pack that has been loaded but not yet enabled
+    pending_hd_pack: Arc<Mutex<Option<HdPack>>>,
+    display: Option<egui::TextureHandle>,
+    display_settings: DisplaySettings,
+    menu_height: f32,
+    /// whether the window should be resized to fit the display settings
+    is_resize_pending: bool,
+    ppu_viewer: PpuViewer,
+    event_viewer: EventViewer,
+    is_screenshot_requested: bool,
+    /// whether screenshots are taken after the video filters rather than at the PPU's native resolution
+    filter_screenshots: bool,
+    recording_format: RecordingFormat,
+    recorder: Option<Recorder<RecordingFile>>,
+    /// recorder that has been created but not yet started
+    pending_recorder: Arc<Mutex<Option<Recorder<RecordingFile>>>>,
+    /// resamples the APU's output for the recording
+    recording_audio: AudioPipeline,
+    audio_recording: Option<AudioRecording>,
+    /// audio recording that has been created but not yet started
+    pending_audio_recording: Arc<Mutex<Option<AudioRecording>>>,
+    /// whether audio recordings include a file for each channel
+    record_channels: bool,
+    audio_backend: Box<dyn AudioBackend>,
+    /// receives the APU's output on the emulation thread and queues it for the backend
+    audio_sink: Arc<Mutex<AudioSink>>,
+    audio_settings: AudioSettings,
+    audio_window: AudioWindow,
+    mixer_window: MixerWindow,
+    nsf_window: NsfWindow,
 }
 
 impl Default for NersApp {
@@ -21,20 +107,102 @@ impl Default for NersApp {
         Self {
             console: Arc::new(Mutex::new(Nes::new())),
             frame_signal: Arc::new(Condvar::new()),
+            rom_name: Arc::new(Mutex::new(None)),
+            save_path: Arc::new(Mutex::new(None)),
+            palette: Palette::default(),
+            palette_source: PaletteSource::Builtin,
+            palette_model: PpuModel::Rp2c02,
+            file_palette: Arc::new(Mutex::new(None)),
+            ntsc_filter: None,
+            filters: FilterChain::default(),
+            hd_pack: None,
+            pending_hd_pack: Arc::new(Mutex::new(None)),
+            display: None,
+            display_settings: DisplaySettings::default(),
+            menu_height: DEFAULT_MENU_HEIGHT,
+            is_resize_pending: false,
+            ppu_viewer: PpuViewer::default(),
+            event_viewer: EventViewer::default(),
+            is_screenshot_requested: false,
+            filter_screenshots: false,
+            recording_format: RecordingFormat::Avi,
+            recorder: None,
+            pending_recorder: Arc::new(Mutex::new(None)),
+            recording_audio: Self::recording_audio_pipeline(),
+            audio_recording: None,
+            pending_audio_recording: Arc::new(Mutex::new(None)),
+            record_channels: false,
+            audio_backend: Box::new(NullBackend::default()),
+            audio_sink: Arc::new(Mutex::new(AudioSink::new(
+                NATIVE_SAMPLE_RATE,
+                NullBackend::default().sample_rate(),
+                SampleRing::new(),
+                AudioSettings::default(),
+            ))),
+            audio_settings: AudioSettings::default(),
+            audio_window: AudioWindow::default(),
+            mixer_window: MixerWindow::default(),
+            nsf_window: NsfWindow::default(),
         }
     }
 }
 
 impl NersApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        // TODO: persistence
-        let app = Self::default();
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self::default();
+        if let Some(settings) = cc.storage.and_then(|s| eframe::get_value(s, eframe::APP_KEY)) {
+            app.display_settings = settings;
+            // the window was created at the default size, before the saved settings could be read
+            app.is_resize_pending = true;
+        }
+        if let Some(settings) = cc.storage.and_then(|s| eframe::get_value(s, AUDIO_SETTINGS_KEY)) {
+            app.audio_settings = settings;
+        }
+        app.open_audio();
+        Self::set_sprite_limit_removed(&app.console.lock().unwrap(), app.display_settings.remove_sprite_limit);
+        Self::set_expansion_options(&app.console.lock().unwrap(), app.audio_settings);
         // start the console execution task
-        Self::spawn_async(Self::run_game(Arc::clone(&app.console), Arc::clone(&app.frame_signal)));
+        Self::spawn_async(Self::run_game(
+            Arc::clone(&app.console),
+            Arc::clone(&app.frame_signal),
+            Arc::clone(&app.audio_sink),
+        ));
         app
     }
     
-    async fn run_game(console: Arc<Mutex<Nes>>, frame_signal: Arc<Condvar>) {
+    /// Initial size of the window with the default display settings
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn default_window_size() -> egui::Vec2 {
+        DisplaySettings::default().window_size() + egui::vec2(0.0, DEFAULT_MENU_HEIGHT)
+    }
+
+    fn open_audio(&mut self) {
+        let ring = SampleRing::new();
+        self.audio_backend = crate::audio::open_audio_backend(ring.clone());
+        let mut sink = self.audio_sink.lock().unwrap();
+        sink.set_output(self.audio_backend.sample_rate(), ring);
+        sink.set_settings(self.audio_settings);
+    }
+
+    fn set_sprite_limit_removed(console: &Nes, is_removed: bool) {
+        match console.ppu_mut() {
+            Ok(mut ppu) => ppu.set_sprite_limit_removed(is_removed),
+            Err(e) => error!("Failed to update sprite limit: {}", e),
+        }
+    }
+
+    /// Apply the audio settings that change how the cartridge's sound chips are emulated
+    fn set_expansion_options(console: &Nes, settings: AudioSettings) {
+        let options = ExpansionOptions {
+            is_n163_multiplexed: settings.is_n163_multiplexed,
+        };
+        match console.apu_mut() {
+            Ok(mut apu) => apu.set_expansion_options(options),
+            Err(e) => error!("Failed to update expansion audio options: {}", e),
+        }
+    }
+
+    async fn run_game(console: Arc<Mutex<Nes>>, frame_signal: Arc<Condvar>, audio_sink: Arc<Mutex<AudioSink>>) {
         loop {
             // wait until we receive the frame signal while the console is running
             let mut console = frame_signal.wait(console.lock().unwrap()).unwrap();
@@ -46,9 +214,292 @@ impl NersApp {
                 error!("Console execution failed: {}", e);
                 console.pause();
             }
+
+            let samples = match console.apu_mut() {
+                Ok(mut apu) => apu.take_samples(),
+                Err(e) => {
+                    error!("Failed to collect audio: {}", e);
+                    continue;
+                }
+            };
+            audio_sink.lock().unwrap().push(&samples);
+        }
+    }
+
+    fn open_palette(&self) {
+        let file_palette = Arc::clone(&self.file_palette);
+        Self::spawn_async(async move {
+            let file = AsyncFileDialog::new()
+                .add_filter("Palettes", &["pal"])
+                .pick_file()
+                .await;
+
+            let Some(file) = file else {
+                debug!("User cancelled palette selection");
+                return;
+            };
+
+            match Palette::from_pal(&file.read().await) {
+                Ok(palette) => *file_palette.lock().unwrap() = Some(palette),
+                Err(e) => error!("Failed to load palette: {}", e),
+            }
+        });
+    }
+
+    fn update_palette(&mut self, model: PpuModel) {
+        let has_model_changed = model != self.palette_model;
+        self.palette_model = model;
+
+        match self.palette_source {
+            PaletteSource::Builtin if has_model_changed => self.palette = Palette::for_model(model),
+            PaletteSource::File => {
+                if let Some(palette) = self.file_palette.lock().unwrap().take() {
+                    self.palette = palette;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn set_palette_source(&mut self, source: PaletteSource) {
+        self.palette_source = source;
+        match source {
+            PaletteSource::Builtin => self.palette = Palette::for_model(self.palette_model),
+            PaletteSource::Ntsc => self.palette = Palette::generate_ntsc(NtscPaletteOptions::default()),
+            // the palette will be updated once the user selects a file
+            PaletteSource::File => self.open_palette(),
+        }
+    }
+
+    fn display_menu(&mut self, ui: &mut egui::Ui) {
+        let old_settings = self.display_settings;
+        let settings = &mut self.display_settings;
+
+        ui.label("Pixel aspect ratio");
+        ui.radio_value(&mut settings.aspect, PixelAspect::Ntsc, "8:7 (NTSC)");
+        ui.radio_value(&mut settings.aspect, PixelAspect::Square, "Square");
+        ui.separator();
+
+        ui.label("Scaling");
+        ui.radio_value(&mut settings.scaling, ScalingMode::Integer, "Integer");
+        ui.radio_value(&mut settings.scaling, ScalingMode::Stretch, "Stretch to fit");
+        ui.separator();
+
+        ui.label("Window size");
+        ui.horizontal(|ui| {
+            for scale in 1..=4 {
+                if ui.selectable_label(settings.window_scale == scale, format!("{}x", scale)).clicked() {
+                    settings.window_scale = scale;
+                    // resize even if the scale hasn't changed in case the user has resized the window manually
+                    self.is_resize_pending = true;
+                }
+            }
+        });
+        ui.separator();
+
+        ui.label("Overscan");
+        ui.add(egui::Slider::new(&mut settings.overscan.top, 0..=MAX_OVERSCAN).text("Top"));
+        ui.add(egui::Slider::new(&mut settings.overscan.bottom, 0..=MAX_OVERSCAN).text("Bottom"));
+        ui.add(egui::Slider::new(&mut settings.overscan.left, 0..=MAX_OVERSCAN).text("Left"));
+        ui.add(egui::Slider::new(&mut settings.overscan.right, 0..=MAX_OVERSCAN).text("Right"));
+        if ui.button("Reset").clicked() {
+            settings.overscan = Overscan::default();
+        }
+
+        // the window only needs to change size if the frame's size has
+        if settings.aspect != old_settings.aspect || settings.overscan != old_settings.overscan {
+            self.is_resize_pending = true;
+        }
+    }
+
+    /// Convert the PPU's most recent frame to RGBA with the current palette and, optionally, the HD pack and video filters
+    fn render_frame(&mut self, ppu: &Ppu, is_filtered: bool) -> Image {
+        let hd_frame = self.hd_pack.as_ref().filter(|_| is_filtered).zip(ppu.pixel_sources());
+        let image = match (hd_frame, &mut self.ntsc_filter) {
+            (Some((pack, sources)), _) => pack.render(ppu.frame(), sources, &self.palette, ppu.frame_number()),
+            (None, Some(filter)) if is_filtered => {
+                Image::from_rgba(NTSC_OUTPUT_WIDTH, FRAME_HEIGHT, filter.filter(ppu.frame(), ppu.frame_phase()))
+            }
+            _ => Image::from_rgba(FRAME_WIDTH, FRAME_HEIGHT, &self.palette.to_rgba(ppu.frame())),
+        };
+
+        if is_filtered && self.filters.is_enabled() {
+            self.filters.apply(image)
+        } else {
+            image
+        }
+    }
+
+    fn save_screenshot(&mut self, ppu: &Ppu) {
+        let image = self.render_frame(ppu, self.filter_screenshots);
+        let data = match image.to_png() {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to encode screenshot: {}", e);
+                return;
+            }
+        };
+
+        let file_name = capture::capture_file_name(self.rom_name.lock().unwrap().as_deref(), "png");
+        Self::spawn_async(capture::save_file(file_name, "PNG images", "png", data));
+    }
+
+    fn load_hd_pack(&self) {
+        let pending_hd_pack = Arc::clone(&self.pending_hd_pack);
+        Self::spawn_async(async move {
+            match hd_pack::load_hd_pack().await {
+                Ok(pack) => *pending_hd_pack.lock().unwrap() = pack,
+                Err(e) => error!("Failed to load HD pack: {}", e),
+            }
+        });
+    }
+
+    /// Switch to a newly loaded HD pack, or back to the standard graphics if `pack` is `None`
+    fn set_hd_pack(&mut self, console: &Nes, pack: Option<HdPack>) {
+        match console.ppu_mut() {
+            Ok(mut ppu) => {
+                // tracking is already running if a pack was loaded before, and restarting it would lose a frame
+                if pack.is_none() || self.hd_pack.is_none() {
+                    ppu.set_tile_tracking(pack.is_some());
+                }
+                self.hd_pack = pack;
+            }
+            Err(e) => error!("Failed to update tile tracking: {}", e),
+        }
+    }
+
+    fn start_recording(&self) {
+        let rom_name = self.rom_name.lock().unwrap().clone();
+        let format = self.recording_format;
+        let pending_recorder = Arc::clone(&self.pending_recorder);
+        Self::spawn_async(async move {
+            match capture::create_recorder(rom_name, format).await {
+                Ok(recorder) => *pending_recorder.lock().unwrap() = recorder,
+                Err(e) => error!("Failed to start recording: {}", e),
+            }
+        });
+    }
+
+    fn stop_recording(&mut self, console: &Nes) {
+        if let Some(recorder) = self.recorder.take() {
+            capture::finish_recording(recorder, self.rom_name.lock().unwrap().as_deref(), self.recording_format);
+        }
+
+        match console.ppu_mut() {
+            Ok(mut ppu) => ppu.set_frame_capture(false),
+            Err(e) => error!("Failed to stop frame capture: {}", e),
+        }
+        self.audio_sink.lock().unwrap().set_recording(self.audio_recording.is_some());
+    }
+
+    /// Start a recording once it's ready and write any frames and audio completed since the last update. `samples`
+    /// is the audio recorded by the sink since then.
+    fn update_recording(&mut self, console: &Nes, samples: &[f32]) {
+        if let Some(recorder) = self.pending_recorder.lock().unwrap().take() {
+            match console.ppu_mut() {
+                Ok(mut ppu) => {
+                    ppu.set_frame_capture(true);
+                    self.recorder = Some(recorder);
+                    self.recording_audio = Self::recording_audio_pipeline();
+                    self.audio_sink.lock().unwrap().set_recording(true);
+                }
+                Err(e) => error!("Failed to start frame capture: {}", e),
+            }
+        }
+
+        let Some(ref mut recorder) = self.recorder else {
+            return;
+        };
+
+        let frames = match console.ppu_mut() {
+            Ok(mut ppu) => ppu.take_captured_frames(),
+            Err(e) => {
+                error!("Failed to collect recorded frames: {}", e);
+                return;
+            }
+        };
+
+        let mut audio = Vec::new();
+        self.recording_audio.process(samples, &mut audio);
+
+        let result = frames.iter()
+            .try_for_each(|frame| {
+                recorder.write_frame(&Image::from_rgba(FRAME_WIDTH, FRAME_HEIGHT, &self.palette.to_rgba(frame)))
+            })
+            .and_then(|_| recorder.write_samples(&audio));
+        if let Err(e) = result {
+            error!("Recording failed: {}", e);
+            self.stop_recording(console);
+        }
+    }
+
+    fn start_audio_recording(&self, console: &Nes) {
+        let channels = match console.apu() {
+            Ok(apu) if self.record_channels => apu.channels(),
+            Ok(_) => Vec::new(),
+            Err(e) => {
+                error!("Failed to read APU channels: {}", e);
+                return;
+            }
+        };
+        let rom_name = self.rom_name.lock().unwrap().clone();
+        let sample_rate = self.audio_backend.sample_rate();
+        let pending_audio_recording = Arc::clone(&self.pending_audio_recording);
+        Self::spawn_async(async move {
+            match capture::create_audio_recording(rom_name, channels, sample_rate).await {
+                Ok(recording) => *pending_audio_recording.lock().unwrap() = recording,
+                Err(e) => error!("Failed to start audio recording: {}", e),
+            }
+        });
+    }
+
+    fn stop_audio_recording(&mut self, console: &Nes) {
+        if let Some(recording) = self.audio_recording.take() {
+            capture::finish_audio_recording(recording, self.rom_name.lock().unwrap().as_deref());
+        }
+
+        match console.apu_mut() {
+            Ok(mut apu) => apu.set_channel_recording(false),
+            Err(e) => error!("Failed to stop channel recording: {}", e),
+        }
+        self.audio_sink.lock().unwrap().set_recording(self.recorder.is_some());
+    }
+
+    /// Start an audio recording once it's ready and write any audio completed since the last update
+    fn update_audio_recording(&mut self, console: &Nes, samples: &[f32]) {
+        if let Some(recording) = self.pending_audio_recording.lock().unwrap().take() {
+            match console.apu_mut() {
+                Ok(mut apu) => {
+                    apu.set_channel_recording(recording.has_channels());
+                    self.audio_recording = Some(recording);
+                    self.audio_sink.lock().unwrap().set_recording(true);
+                }
+                Err(e) => error!("Failed to start channel recording: {}", e),
+            }
+        }
+
+        let Some(ref mut recording) = self.audio_recording else {
+            return;
+        };
+
+        let channel_samples = match console.apu_mut() {
+            Ok(mut apu) => apu.take_channel_samples(),
+            Err(e) => {
+                error!("Failed to collect recorded channels: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = recording.write_samples(samples, &channel_samples) {
+            error!("Audio recording failed: {}", e);
+            self.stop_audio_recording(console);
         }
     }
 
+    fn recording_audio_pipeline() -> AudioPipeline {
+        AudioPipeline::new(NATIVE_SAMPLE_RATE, RECORDING_SAMPLE_RATE, &FilterOptions::default())
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     fn spawn_async<F: Future<Output = ()> + Send + 'static>(future: F) {
         tokio::task::spawn(future);
@@ -62,14 +513,18 @@ impl NersApp {
 
 impl eframe::App for NersApp {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+        let menu = egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
+                        // opening a game is the first chance to start audio in browsers, which require a user gesture
+                        self.audio_backend.resume();
                         let console = self.console.clone();
+                        let rom_name = self.rom_name.clone();
+                        let save_path = self.save_path.clone();
                         Self::spawn_async(async move {
                             let file = AsyncFileDialog::new()
-                                .add_filter("NES ROMs", &["nes"/*, "unf", "unif"*/])
+                                .add_filter("NES ROMs and NSFs", &["nes", "nsf", "nsfe"/*, "unf", "unif"*/])
                                 .pick_file()
                                 .await;
                             
@@ -82,28 +537,206 @@ impl eframe::App for NersApp {
                             let data = file.read().await;
                             
                             match (console.lock(), Cartridge::from_rom(Cursor::new(data))) {
-                                (Ok(mut console_ref), Ok(cartridge)) => console_ref.load_cartridge(cartridge),
+                                (Ok(mut console_ref), Ok(mut cartridge)) => {
+                                    // save the outgoing game first, in case it's the same one being reopened
+                                    let mut save_path = save_path.lock().unwrap();
+                                    battery::eject_cartridge(&mut console_ref, save_path.as_deref());
+                                    *save_path = battery::save_path(&file);
+                                    if let Some(path) = save_path.as_deref() {
+                                        battery::load_save(&mut cartridge, path);
+                                    }
+                                    if let Err(e) = console_ref.load_cartridge(cartridge) {
+                                        error!("Failed to insert cartridge: {}", e);
+                                        return;
+                                    }
+                                    console_ref.resume();
+                                    let file_name = file.file_name();
+                                    let stem = file_name.rsplit_once('.').map_or(file_name.as_str(), |(stem, _)| stem);
+                                    *rom_name.lock().unwrap() = Some(stem.to_owned());
+                                }
                                 (Err(e), _) => error!("Failed to lock ROM path for update: {}", e),
                                 (_, Err(e)) => error!("Failed to load ROM: {}", e),
                             }
                         });
                     }
+                    let shortcut = egui::KeyboardShortcut::new(egui::Modifiers::NONE, SCREENSHOT_KEY);
+                    let screenshot_button = egui::Button::new("Screenshot").shortcut_text(ctx.format_shortcut(&shortcut));
+                    if ui.add(screenshot_button).clicked() {
+                        self.is_screenshot_requested = true;
+                        ui.close_menu();
+                    }
+                    ui.checkbox(&mut self.filter_screenshots, "Apply filters to screenshots");
+                    ui.separator();
+                    if self.recorder.is_some() {
+                        if ui.button("Stop recording").clicked() {
+                            self.stop_recording(&self.console.clone().lock().unwrap());
+                            ui.close_menu();
+                        }
+                    } else {
+                        ui.menu_button("Record", |ui| {
+                            ui.radio_value(&mut self.recording_format, RecordingFormat::Avi, "Uncompressed AVI");
+                            ui.radio_value(&mut self.recording_format, RecordingFormat::Y4mWav, "Y4M + WAV");
+                            if ui.button("Start recording").clicked() {
+                                self.start_recording();
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                });
+                ui.menu_button("Video", |ui| {
+                    ui.menu_button("Palette", |ui| {
+                        let mut source = self.palette_source;
+                        ui.radio_value(&mut source, PaletteSource::Builtin, "Built-in");
+                        ui.radio_value(&mut source, PaletteSource::Ntsc, "NTSC (generated)");
+                        if source != self.palette_source {
+                            self.set_palette_source(source);
+                        }
+                        if ui.radio(self.palette_source == PaletteSource::File, "From file...").clicked() {
+                            self.set_palette_source(PaletteSource::File);
+                            ui.close_menu();
+                        }
+                    });
+                    let mut use_ntsc_filter = self.ntsc_filter.is_some();
+                    if ui.checkbox(&mut use_ntsc_filter, "NTSC filter").changed() {
+                        self.ntsc_filter = use_ntsc_filter.then(|| NtscFilter::new(NtscFilterOptions::default()));
+                    }
+                    ui.menu_button("Display", |ui| self.display_menu(ui));
+                    if ui.button("Load HD pack...").clicked() {
+                        self.load_hd_pack();
+                        ui.close_menu();
+                    }
+                    if self.hd_pack.is_some() && ui.button("Unload HD pack").clicked() {
+                        self.set_hd_pack(&self.console.clone().lock().unwrap(), None);
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Scaler", |ui| {
+                        for scaler in Scaler::ALL {
+                            ui.radio_value(&mut self.filters.scaler, scaler, scaler.name());
+                        }
+                    });
+                    ui.menu_button("CRT effect", |ui| {
+                        ui.add(egui::Slider::new(&mut self.filters.crt.scanlines, 0.0..=1.0).text("Scanlines"));
+                        ui.add(egui::Slider::new(&mut self.filters.crt.mask, 0.0..=1.0).text("Aperture grille"));
+                    });
+                    let remove_sprite_limit = &mut self.display_settings.remove_sprite_limit;
+                    if ui.checkbox(remove_sprite_limit, "Remove sprite limit").changed() {
+                        Self::set_sprite_limit_removed(&self.console.lock().unwrap(), *remove_sprite_limit);
+                    }
+                });
+                ui.menu_button("Audio", |ui| {
+                    self.audio_window.menu(ui);
+                    self.mixer_window.menu(ui);
+                    ui.separator();
+                    if self.audio_recording.is_some() {
+                        if ui.button("Stop recording audio").clicked() {
+                            self.stop_audio_recording(&self.console.clone().lock().unwrap());
+                            ui.close_menu();
+                        }
+                    } else {
+                        ui.menu_button("Record audio", |ui| {
+                            ui.checkbox(&mut self.record_channels, "Each channel to its own file");
+                            if ui.button("Start recording").clicked() {
+                                self.start_audio_recording(&self.console.clone().lock().unwrap());
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                });
+                ui.menu_button("Debug", |ui| {
+                    self.ppu_viewer.menu(ui);
+                    self.event_viewer.menu(ui);
                 });
             });
         });
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let texture = ctx.load_texture("display", egui::ColorImage::example(), Default::default());
-            ui.image(egui::load::SizedTexture::new(texture.id(), texture.size_vec2()));
+        self.menu_height = menu.response.rect.height();
+
+        if self.is_resize_pending {
+            self.is_resize_pending = false;
+            let size = self.display_settings.window_size() + egui::vec2(0.0, self.menu_height);
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        }
+
+        if ctx.input(|i| i.key_pressed(SCREENSHOT_KEY)) {
+            self.is_screenshot_requested = true;
+        }
+
+        let model = self.console.lock().unwrap().ppu_model();
+        self.event_viewer.update_logging(&self.console.lock().unwrap());
+        self.mixer_window.update_apu(&self.console.lock().unwrap());
+        let recorded_audio = self.audio_sink.lock().unwrap().take_recorded();
+        self.update_recording(&self.console.clone().lock().unwrap(), &recorded_audio);
+        self.update_audio_recording(&self.console.clone().lock().unwrap(), &recorded_audio);
+        self.update_palette(model);
+        let pending_hd_pack = self.pending_hd_pack.lock().unwrap().take();
+        if let Some(pack) = pending_hd_pack {
+            self.set_hd_pack(&self.console.clone().lock().unwrap(), Some(pack));
+        }
+
+        let console_ref = Arc::clone(&self.console);
+        let console = console_ref.lock().unwrap();
+        match console.ppu() {
+            Ok(ppu) => {
+                if self.is_screenshot_requested {
+                    self.is_screenshot_requested = false;
+                    self.save_screenshot(&ppu);
+                }
+
+                let image = self.render_frame(&ppu, true);
+                let image = egui::ColorImage::from_rgba_unmultiplied([image.width, image.height], image.as_rgba());
+                match self.display {
+                    Some(ref mut texture) => texture.set(image, egui::TextureOptions::NEAREST),
+                    None => self.display = Some(ctx.load_texture("display", image, egui::TextureOptions::NEAREST)),
+                }
+
+                self.ppu_viewer.show(ctx, &ppu, &self.palette);
+                self.event_viewer.show(ctx, &ppu, self.display.as_ref());
+            }
+            Err(e) => error!("Failed to read PPU frame: {}", e),
+        }
+        match console.apu() {
+            Ok(apu) => self.mixer_window.show(ctx, &apu),
+            Err(e) => error!("Failed to read APU state: {}", e),
+        }
+        match console.nsf_player_mut() {
+            Ok(mut player) => self.nsf_window.show(ctx, &mut player),
+            Err(e) => error!("Failed to update NSF player: {}", e),
+        }
+
+        if self.audio_window.show(ctx, &mut self.audio_settings) {
+            self.audio_sink.lock().unwrap().set_settings(self.audio_settings);
+            Self::set_expansion_options(&console, self.audio_settings);
+        }
+
+        let panel_frame = egui::Frame::none().fill(egui::Color32::BLACK);
+        egui::CentralPanel::default().frame(panel_frame).show(ctx, |ui| {
+            if let Some(ref texture) = self.display {
+                // filtered output may have a different resolution, but covers the same area of the frame
+                let available = ui.available_rect_before_wrap();
+                let rect = egui::Rect::from_center_size(available.center(), self.display_settings.display_size(available.size()));
+                ui.painter().image(texture.id(), rect, self.display_settings.uv(), egui::Color32::WHITE);
+            }
         });
 
         // run the active game, if any
-        let console = self.console.lock().unwrap();
         if console.is_running() {
+            input::update_controller(ctx, &console);
             // while a game is running, redraw the UI regularly
             ctx.request_repaint();
             // signal the console thread to run for another frame
             self.frame_signal.notify_one();
         }
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.display_settings);
+        eframe::set_value(storage, AUDIO_SETTINGS_KEY, &self.audio_settings);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        match self.console.lock() {
+            Ok(mut console) => battery::eject_cartridge(&mut console, self.save_path.lock().unwrap().as_deref()),
+            Err(e) => error!("Failed to lock console to save the game: {}", e),
+        }
+    }
 }
\ No newline at end of file
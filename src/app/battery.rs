@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+use rfd::FileHandle;
+
+use crate::hw::Nes;
+use crate::rom::Cartridge;
+
+/// Where to keep the battery-backed RAM of the game in a ROM file: a .sav file next to it
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_path(rom_file: &FileHandle) -> Option<PathBuf> {
+    Some(rom_file.path().with_extension("sav"))
+}
+
+/// Browsers don't tell us where the ROM came from, so there's nowhere to keep saves
+#[cfg(target_arch = "wasm32")]
+pub fn save_path(_rom_file: &FileHandle) -> Option<PathBuf> {
+    None
+}
+
+/// Restore a cartridge's battery-backed RAM from its save file, if it has both
+pub fn load_save(cartridge: &mut Cartridge, path: &Path) {
+    if cartridge.battery_ram().is_none() {
+        return;
+    }
+
+    match std::fs::read(path) {
+        Ok(data) => {
+            info!("Loading save from {}", path.display());
+            cartridge.load_battery_ram(&data);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => error!("Failed to read save file {}: {}", path.display(), e),
+    }
+}
+
+/// Write a cartridge's battery-backed RAM to its save file
+pub fn write_save(cartridge: &Cartridge, path: &Path) {
+    let Some(data) = cartridge.battery_ram() else {
+        return;
+    };
+
+    info!("Writing save to {}", path.display());
+    if let Err(e) = std::fs::write(path, data) {
+        error!("Failed to write save file {}: {}", path.display(), e);
+    }
+}
+
+/// Remove the cartridge from the console, saving its battery-backed RAM on the way out
+pub fn eject_cartridge(console: &mut Nes, save_path: Option<&Path>) {
+    match console.eject_cartridge() {
+        Ok(Some(cartridge)) => {
+            if let Some(path) = save_path {
+                write_save(&cartridge, path);
+            }
+        }
+        Ok(None) => (),
+        Err(e) => error!("Failed to eject cartridge: {}", e),
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use rfd::AsyncFileDialog;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::audio::{AudioPipeline, FilterOptions};
+use crate::hw::apu::{AudioChannel, NATIVE_SAMPLE_RATE, NUM_CHANNELS};
+use crate::hw::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::record::{AudioRecorder, Recorder, RecordingFormat};
+
+/// Recordings are written straight to disk on desktop, but have to be kept in memory until they're downloaded in the
+/// browser
+#[cfg(not(target_arch = "wasm32"))]
+pub type RecordingFile = std::io::BufWriter<std::fs::File>;
+#[cfg(target_arch = "wasm32")]
+pub type RecordingFile = std::io::Cursor<Vec<u8>>;
+
+/// Sample rate of recorded audio
+pub const RECORDING_SAMPLE_RATE: u32 = 48000;
+
+/// Current UTC time as YYYYMMDD-HHMMSS
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+
+    // convert days since the epoch to a date in the proleptic Gregorian calendar
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year, month, day, time / 3600, (time / 60) % 60, time % 60,
+    )
+}
+
+/// Name for a capture of the given game, made unique by the current time
+pub fn capture_file_name(rom_name: Option<&str>, extension: &str) -> String {
+    format!("{}_{}.{}", rom_name.unwrap_or("ners"), timestamp(), extension)
+}
+
+/// Ask the user where to save a file and write it there. In the browser, this downloads the file instead.
+pub async fn save_file(file_name: String, filter_name: &str, extension: &str, data: Vec<u8>) {
+    let file = AsyncFileDialog::new()
+        .set_file_name(file_name)
+        .add_filter(filter_name, &[extension])
+        .save_file()
+        .await;
+
+    let Some(file) = file else {
+        debug!("User cancelled save");
+        return;
+    };
+
+    if let Err(e) = file.write(&data).await {
+        error!("Failed to save file: {}", e);
+    }
+}
+
+/// Extensions of the files making up a recording in the given format
+const fn recording_extensions(format: RecordingFormat) -> &'static [&'static str] {
+    match format {
+        RecordingFormat::Y4mWav => &["y4m", "wav"],
+        RecordingFormat::Avi => &["avi"],
+    }
+}
+
+/// Start a recording, asking the user where to save it. Returns `None` if the user cancelled.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn create_recorder(rom_name: Option<String>, format: RecordingFormat) -> Result<Option<Recorder<RecordingFile>>> {
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+
+    let extension = recording_extensions(format)[0];
+    let file = AsyncFileDialog::new()
+        .set_file_name(capture_file_name(rom_name.as_deref(), extension))
+        .add_filter("Recordings", &[extension])
+        .save_file()
+        .await;
+
+    let Some(file) = file else {
+        debug!("User cancelled recording");
+        return Ok(None);
+    };
+
+    let create = |path: &Path| -> Result<RecordingFile> { Ok(BufWriter::new(File::create(path)?)) };
+    let path = file.path();
+    info!("Recording to {}", path.display());
+    let recorder = match format {
+        RecordingFormat::Y4mWav => {
+            // the audio track is saved alongside the video with the same name
+            let audio = create(&path.with_extension("wav"))?;
+            Recorder::y4m_wav(create(path)?, audio, FRAME_WIDTH, FRAME_HEIGHT, RECORDING_SAMPLE_RATE)?
+        }
+        RecordingFormat::Avi => Recorder::avi(create(path)?, FRAME_WIDTH, FRAME_HEIGHT, RECORDING_SAMPLE_RATE)?,
+    };
+    Ok(Some(recorder))
+}
+
+/// Start a recording in memory, to be downloaded when it's finished
+#[cfg(target_arch = "wasm32")]
+pub async fn create_recorder(_rom_name: Option<String>, format: RecordingFormat) -> Result<Option<Recorder<RecordingFile>>> {
+    use std::io::Cursor;
+
+    let recorder = match format {
+        RecordingFormat::Y4mWav => Recorder::y4m_wav(
+            Cursor::new(Vec::new()),
+            Cursor::new(Vec::new()),
+            FRAME_WIDTH,
+            FRAME_HEIGHT,
+            RECORDING_SAMPLE_RATE,
+        )?,
+        RecordingFormat::Avi => Recorder::avi(Cursor::new(Vec::new()), FRAME_WIDTH, FRAME_HEIGHT, RECORDING_SAMPLE_RATE)?,
+    };
+    Ok(Some(recorder))
+}
+
+/// Finish writing a recording to disk
+#[cfg(not(target_arch = "wasm32"))]
+pub fn finish_recording(recorder: Recorder<RecordingFile>, _rom_name: Option<&str>, _format: RecordingFormat) {
+    match recorder.finish() {
+        Ok(_) => info!("Recording finished"),
+        Err(e) => error!("Failed to finish recording: {}", e),
+    }
+}
+
+/// Finish a recording and download the recorded files
+#[cfg(target_arch = "wasm32")]
+pub fn finish_recording(recorder: Recorder<RecordingFile>, rom_name: Option<&str>, format: RecordingFormat) {
+    let files = match recorder.finish() {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to finish recording: {}", e);
+            return;
+        }
+    };
+
+    for (file, &extension) in files.into_iter().zip(recording_extensions(format)) {
+        let file_name = capture_file_name(rom_name, extension);
+        wasm_bindgen_futures::spawn_local(save_file(file_name, "Recordings", extension, file.into_inner()));
+    }
+}
+
+/// An audio-only recording of the mix and, optionally, each channel separately
+pub struct AudioRecording {
+    recorder: AudioRecorder<RecordingFile>,
+    /// the channels with their own files, which follow the mix's
+    channels: Vec<AudioChannel>,
+    /// resample each file's audio from the APU's native rate
+    pipelines: Vec<AudioPipeline>,
+    resampled: Vec<i16>,
+}
+
+impl AudioRecording {
+    fn new(files: Vec<RecordingFile>, channels: Vec<AudioChannel>, sample_rate: u32) -> Result<Self> {
+        let pipelines = (0..files.len())
+            .map(|_| AudioPipeline::new(NATIVE_SAMPLE_RATE, sample_rate, &FilterOptions::default()))
+            .collect();
+        Ok(Self {
+            recorder: AudioRecorder::new(files, sample_rate)?,
+            channels,
+            pipelines,
+            resampled: Vec::new(),
+        })
+    }
+
+    /// Whether the channels are recorded separately as well as the mix
+    pub fn has_channels(&self) -> bool {
+        !self.channels.is_empty()
+    }
+
+    /// Write the mix and each channel's output on its own, both at the APU's native rate
+    pub fn write_samples(&mut self, mix: &[f32], channel_samples: &[[f32; NUM_CHANNELS]]) -> Result<()> {
+        let mut channel_track = Vec::with_capacity(channel_samples.len());
+        for (track, pipeline) in self.pipelines.iter_mut().enumerate() {
+            let samples = match track.checked_sub(1) {
+                None => mix,
+                Some(i) => {
+                    let index = self.channels[i].index();
+                    channel_track.clear();
+                    channel_track.extend(channel_samples.iter().map(|samples| samples[index]));
+                    &channel_track
+                }
+            };
+            self.resampled.clear();
+            pipeline.process(samples, &mut self.resampled);
+            self.recorder.write_samples(track, &self.resampled)?;
+        }
+        Ok(())
+    }
+}
+
+/// Suffix of the file a channel is recorded to, e.g. "vrc6_pulse_1"
+fn channel_file_suffix(channel: AudioChannel) -> String {
+    channel.name().to_lowercase().replace(' ', "_")
+}
+
+/// Start recording audio, asking the user where to save the mix. Channels are saved alongside it, with their names
+/// added to its. Returns `None` if the user cancelled.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn create_audio_recording(
+    rom_name: Option<String>,
+    channels: Vec<AudioChannel>,
+    sample_rate: u32,
+) -> Result<Option<AudioRecording>> {
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::path::Path;
+
+    let file = AsyncFileDialog::new()
+        .set_file_name(capture_file_name(rom_name.as_deref(), "wav"))
+        .add_filter("WAV audio", &["wav"])
+        .save_file()
+        .await;
+
+    let Some(file) = file else {
+        debug!("User cancelled audio recording");
+        return Ok(None);
+    };
+
+    let create = |path: &Path| -> Result<RecordingFile> { Ok(BufWriter::new(File::create(path)?)) };
+    let path = file.path();
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    info!("Recording audio to {}", path.display());
+    let mut files = vec![create(path)?];
+    for &channel in &channels {
+        files.push(create(&path.with_file_name(format!("{}_{}.wav", stem, channel_file_suffix(channel))))?);
+    }
+    Ok(Some(AudioRecording::new(files, channels, sample_rate)?))
+}
+
+/// Start recording audio in memory, to be downloaded when it's finished
+#[cfg(target_arch = "wasm32")]
+pub async fn create_audio_recording(
+    _rom_name: Option<String>,
+    channels: Vec<AudioChannel>,
+    sample_rate: u32,
+) -> Result<Option<AudioRecording>> {
+    use std::io::Cursor;
+
+    let files = (0..=channels.len()).map(|_| Cursor::new(Vec::new())).collect();
+    Ok(Some(AudioRecording::new(files, channels, sample_rate)?))
+}
+
+/// Finish writing an audio recording to disk
+#[cfg(not(target_arch = "wasm32"))]
+pub fn finish_audio_recording(recording: AudioRecording, _rom_name: Option<&str>) {
+    match recording.recorder.finish() {
+        Ok(_) => info!("Audio recording finished"),
+        Err(e) => error!("Failed to finish audio recording: {}", e),
+    }
+}
+
+/// Finish an audio recording and download the mix and any channels
+#[cfg(target_arch = "wasm32")]
+pub fn finish_audio_recording(recording: AudioRecording, rom_name: Option<&str>) {
+    let files = match recording.recorder.finish() {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to finish audio recording: {}", e);
+            return;
+        }
+    };
+
+    let file_name = capture_file_name(rom_name, "wav");
+    let stem = file_name.trim_end_matches(".wav");
+    let file_names = std::iter::once(file_name.clone())
+        .chain(recording.channels.iter().map(|&channel| format!("{}_{}.wav", stem, channel_file_suffix(channel))));
+    for (file, file_name) in files.into_iter().zip(file_names) {
+        wasm_bindgen_futures::spawn_local(save_file(file_name, "WAV audio", "wav", file.into_inner()));
+    }
+}
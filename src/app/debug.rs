@@ -0,0 +1,257 @@
+use egui::{Color32, ColorImage, Context, Rect, Sense, Stroke, TextureHandle, TextureOptions, Ui, Vec2};
+
+use crate::hw::ppu::{Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::video::Palette;
+
+const NAMETABLE_WIDTH: usize = 32;
+const NAMETABLE_HEIGHT: usize = 30;
+const PATTERN_TABLE_SIZE: usize = 128;
+const OAM_COLUMNS: usize = 8;
+const OAM_CELL_WIDTH: usize = 8;
+const OAM_CELL_HEIGHT: usize = 16;
+
+const PALETTE_NAMES: [&str; 8] = ["BG 0", "BG 1", "BG 2", "BG 3", "Sprite 0", "Sprite 1", "Sprite 2", "Sprite 3"];
+
+/// Read one row of a tile from the pattern tables as 2-bit pattern values, leftmost pixel first
+fn tile_row(ppu: &Ppu, addr: u16, row: u16) -> [u8; 8] {
+    let low = ppu.peek(addr + row);
+    let high = ppu.peek(addr + row + 8);
+    std::array::from_fn(|x| ((low >> (7 - x)) & 1) | (((high >> (7 - x)) & 1) << 1))
+}
+
+/// Color of a pattern value in one of the eight palettes
+fn palette_color(ppu: &Ppu, palette: &Palette, palette_number: u8, pattern: u8) -> Color32 {
+    let addr = if pattern == 0 { 0x3f00 } else { 0x3f00 + palette_number as u16 * 4 + pattern as u16 };
+    let [r, g, b] = palette.rgb(ppu.peek(addr) as u16);
+    Color32::from_rgb(r, g, b)
+}
+
+fn set_texture(ctx: &Context, texture: &mut Option<TextureHandle>, name: &str, image: ColorImage) {
+    match texture {
+        Some(texture) => texture.set(image, TextureOptions::NEAREST),
+        None => *texture = Some(ctx.load_texture(name, image, TextureOptions::NEAREST)),
+    }
+}
+
+/// Debug windows showing the state of the PPU's memory
+#[derive(Default)]
+pub struct PpuViewer {
+    show_nametables: bool,
+    show_pattern_tables: bool,
+    show_oam: bool,
+    show_palettes: bool,
+    /// palette used to draw the pattern tables
+    pattern_palette: u8,
+    nametable_texture: Option<TextureHandle>,
+    pattern_table_texture: Option<TextureHandle>,
+    oam_texture: Option<TextureHandle>,
+}
+
+impl PpuViewer {
+    pub fn menu(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.show_nametables, "Nametables");
+        ui.checkbox(&mut self.show_pattern_tables, "Pattern tables");
+        ui.checkbox(&mut self.show_oam, "OAM");
+        ui.checkbox(&mut self.show_palettes, "Palettes");
+    }
+
+    pub fn show(&mut self, ctx: &Context, ppu: &Ppu, palette: &Palette) {
+        if self.show_nametables {
+            set_texture(ctx, &mut self.nametable_texture, "nametables", Self::nametable_image(ppu, palette));
+            let texture = self.nametable_texture.as_ref().unwrap();
+            egui::Window::new("Nametables").open(&mut self.show_nametables).show(ctx, |ui| {
+                Self::nametable_window(ui, ppu, texture);
+            });
+        }
+
+        if self.show_pattern_tables {
+            let image = Self::pattern_table_image(ppu, palette, self.pattern_palette);
+            set_texture(ctx, &mut self.pattern_table_texture, "pattern_tables", image);
+            let texture = self.pattern_table_texture.as_ref().unwrap();
+            let pattern_palette = &mut self.pattern_palette;
+            egui::Window::new("Pattern tables").open(&mut self.show_pattern_tables).show(ctx, |ui| {
+                egui::ComboBox::from_label("Palette")
+                    .selected_text(PALETTE_NAMES[*pattern_palette as usize])
+                    .show_ui(ui, |ui| {
+                        for (i, name) in PALETTE_NAMES.iter().enumerate() {
+                            ui.selectable_value(pattern_palette, i as u8, *name);
+                        }
+                    });
+                ui.image(egui::load::SizedTexture::new(texture.id(), texture.size_vec2() * 2.0));
+            });
+        }
+
+        if self.show_oam {
+            set_texture(ctx, &mut self.oam_texture, "oam", Self::oam_image(ppu, palette));
+            let texture = self.oam_texture.as_ref().unwrap();
+            egui::Window::new("OAM").open(&mut self.show_oam).show(ctx, |ui| {
+                Self::oam_window(ui, ppu, texture);
+            });
+        }
+
+        if self.show_palettes {
+            egui::Window::new("Palettes").open(&mut self.show_palettes).show(ctx, |ui| {
+                Self::palette_window(ui, ppu, palette);
+            });
+        }
+    }
+
+    /// All four nametables as one 512x480 image
+    fn nametable_image(ppu: &Ppu, palette: &Palette) -> ColorImage {
+        let width = FRAME_WIDTH * 2;
+        let mut image = ColorImage::new([width, FRAME_HEIGHT * 2], Color32::BLACK);
+        let pattern_table = ppu.background_pattern_table();
+
+        for nametable in 0..4 {
+            let base = 0x2000 + nametable as u16 * 0x400;
+            let origin_x = (nametable & 1) * FRAME_WIDTH;
+            let origin_y = (nametable >> 1) * FRAME_HEIGHT;
+            for tile_y in 0..NAMETABLE_HEIGHT {
+                for tile_x in 0..NAMETABLE_WIDTH {
+                    let tile = ppu.peek(base + (tile_y * NAMETABLE_WIDTH + tile_x) as u16);
+                    let attribute = ppu.peek(base + 0x3c0 + ((tile_y / 4) * 8 + tile_x / 4) as u16);
+                    let shift = ((tile_y & 2) << 1) | (tile_x & 2);
+                    let palette_number = (attribute >> shift) & 3;
+
+                    for row in 0..8 {
+                        let pixels = tile_row(ppu, pattern_table + tile as u16 * 16, row);
+                        let y = origin_y + tile_y * 8 + row as usize;
+                        for (col, pattern) in pixels.into_iter().enumerate() {
+                            let x = origin_x + tile_x * 8 + col;
+                            image.pixels[y * width + x] = palette_color(ppu, palette, palette_number, pattern);
+                        }
+                    }
+                }
+            }
+        }
+
+        image
+    }
+
+    fn nametable_window(ui: &mut Ui, ppu: &Ppu, texture: &TextureHandle) {
+        let response = ui.image(egui::load::SizedTexture::new(texture.id(), texture.size_vec2()));
+        let rect = response.rect;
+        let scale = rect.width() / (FRAME_WIDTH * 2) as f32;
+
+        // outline the visible area, wrapping around the edges of the nametables
+        let (scroll_x, scroll_y) = ppu.scroll();
+        let view_size = Vec2::new(FRAME_WIDTH as f32, FRAME_HEIGHT as f32) * scale;
+        let painter = ui.painter_at(rect);
+        let stroke = Stroke::new(1.0, Color32::WHITE);
+        for offset_x in [0.0, -rect.width()] {
+            for offset_y in [0.0, -rect.height()] {
+                let min = rect.min + Vec2::new(scroll_x as f32 * scale + offset_x, scroll_y as f32 * scale + offset_y);
+                painter.rect_stroke(Rect::from_min_size(min, view_size), 0.0, stroke);
+            }
+        }
+
+        ui.label(format!("Scroll: ({}, {})", scroll_x, scroll_y));
+    }
+
+    /// Both pattern tables side by side as one 256x128 image
+    fn pattern_table_image(ppu: &Ppu, palette: &Palette, palette_number: u8) -> ColorImage {
+        let width = PATTERN_TABLE_SIZE * 2;
+        let mut image = ColorImage::new([width, PATTERN_TABLE_SIZE], Color32::BLACK);
+
+        for table in 0..2 {
+            for tile in 0..256 {
+                let addr = (table << 12) | (tile << 4);
+                let origin_x = table as usize * PATTERN_TABLE_SIZE + (tile as usize % 16) * 8;
+                let origin_y = (tile as usize / 16) * 8;
+                for row in 0..8 {
+                    for (col, pattern) in tile_row(ppu, addr, row).into_iter().enumerate() {
+                        let index = (origin_y + row as usize) * width + origin_x + col;
+                        image.pixels[index] = palette_color(ppu, palette, palette_number, pattern);
+                    }
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Previews of the 64 sprites in an 8x8 grid of 8x16 cells
+    fn oam_image(ppu: &Ppu, palette: &Palette) -> ColorImage {
+        let width = OAM_COLUMNS * OAM_CELL_WIDTH;
+        let rows = 64 / OAM_COLUMNS;
+        let mut image = ColorImage::new([width, rows * OAM_CELL_HEIGHT], Color32::from_gray(0x40));
+        let is_tall = ppu.has_tall_sprites();
+        let height = if is_tall { 16 } else { 8 };
+
+        for (i, sprite) in ppu.oam().chunks_exact(4).enumerate() {
+            let tile = sprite[1];
+            let attributes = sprite[2];
+            let palette_number = 4 + (attributes & 3);
+            let is_flipped_horizontally = attributes & 0x40 != 0;
+            let is_flipped_vertically = attributes & 0x80 != 0;
+            let origin_x = (i % OAM_COLUMNS) * OAM_CELL_WIDTH;
+            let origin_y = (i / OAM_COLUMNS) * OAM_CELL_HEIGHT;
+
+            for y in 0..height {
+                let row = if is_flipped_vertically { height - 1 - y } else { y };
+                let addr = if is_tall {
+                    ((tile as u16 & 1) << 12) | (((tile & 0xfe) as u16 + (row >> 3)) << 4)
+                } else {
+                    ppu.sprite_pattern_table() | ((tile as u16) << 4)
+                };
+                let pixels = tile_row(ppu, addr, row & 7);
+                for x in 0..8 {
+                    let pattern = pixels[if is_flipped_horizontally { 7 - x } else { x }];
+                    let index = (origin_y + y as usize) * width + origin_x + x;
+                    image.pixels[index] = palette_color(ppu, palette, palette_number, pattern);
+                }
+            }
+        }
+
+        image
+    }
+
+    fn oam_window(ui: &mut Ui, ppu: &Ppu, texture: &TextureHandle) {
+        const SCALE: f32 = 4.0;
+
+        let response = ui.image(egui::load::SizedTexture::new(texture.id(), texture.size_vec2() * SCALE));
+        let rect = response.rect;
+        let Some(pointer) = response.hover_pos() else {
+            return;
+        };
+
+        let cell = (pointer - rect.min) / (Vec2::new(OAM_CELL_WIDTH as f32, OAM_CELL_HEIGHT as f32) * SCALE);
+        let index = (cell.y as usize).min(7) * OAM_COLUMNS + (cell.x as usize).min(OAM_COLUMNS - 1);
+        let sprite = &ppu.oam()[index * 4..index * 4 + 4];
+        response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("Sprite {}", index));
+            ui.label(format!("Position: ({}, {})", sprite[3], sprite[0]));
+            ui.label(format!("Tile: ${:02X}", sprite[1]));
+            ui.label(format!("Attributes: ${:02X}", sprite[2]));
+            ui.label(format!("Palette: {}", sprite[2] & 3));
+            ui.label(format!(
+                "Flip: {}{}",
+                if sprite[2] & 0x40 != 0 { "H" } else { "-" },
+                if sprite[2] & 0x80 != 0 { "V" } else { "-" },
+            ));
+            ui.label(if sprite[2] & 0x20 != 0 { "Behind background" } else { "In front of background" });
+        });
+    }
+
+    fn palette_window(ui: &mut Ui, ppu: &Ppu, palette: &Palette) {
+        const SWATCH_SIZE: f32 = 24.0;
+
+        for (row, name) in ["Background", "Sprites"].into_iter().enumerate() {
+            ui.label(name);
+            ui.horizontal(|ui| {
+                for i in 0..16 {
+                    let addr = 0x3f00 + (row * 16 + i) as u16;
+                    let value = ppu.peek(addr);
+                    let [r, g, b] = palette.rgb(value as u16);
+                    let (rect, response) = ui.allocate_exact_size(Vec2::splat(SWATCH_SIZE), Sense::hover());
+                    ui.painter().rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
+                    response.on_hover_text(format!("${:04X}: ${:02X}", addr, value));
+                    // leave a gap between each palette
+                    if i % 4 == 3 {
+                        ui.add_space(4.0);
+                    }
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use egui::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::hw::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+/// Width of an NTSC NES pixel relative to its height
+const NTSC_PIXEL_ASPECT: f32 = 8.0 / 7.0;
+/// Most edge cropping that can be configured, in pixels
+pub const MAX_OVERSCAN: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PixelAspect {
+    Square,
+    /// The 8:7 pixels of an NTSC TV
+    Ntsc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalingMode {
+    /// Only scale by whole multiples of the frame size so every pixel is the same size
+    Integer,
+    /// Fill as much of the window as possible while keeping the aspect ratio
+    Stretch,
+}
+
+/// Number of pixels cropped from each edge of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Overscan {
+    pub top: u8,
+    pub bottom: u8,
+    pub left: u8,
+    pub right: u8,
+}
+
+impl Default for Overscan {
+    fn default() -> Self {
+        // NTSC TVs typically hid about 8 lines at the top and bottom of the picture
+        Self {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub aspect: PixelAspect,
+    pub overscan: Overscan,
+    pub scaling: ScalingMode,
+    /// Multiple of the frame size the window is resized to
+    pub window_scale: u8,
+    /// whether the PPU draws every sprite on a scanline rather than stopping at eight
+    pub remove_sprite_limit: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            aspect: PixelAspect::Ntsc,
+            overscan: Overscan::default(),
+            scaling: ScalingMode::Integer,
+            window_scale: 2,
+            remove_sprite_limit: false,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Portion of the frame that remains visible after cropping, in texture coordinates
+    pub fn uv(&self) -> Rect {
+        let width = FRAME_WIDTH as f32;
+        let height = FRAME_HEIGHT as f32;
+        Rect::from_min_max(
+            egui::pos2(self.overscan.left as f32 / width, self.overscan.top as f32 / height),
+            egui::pos2(1.0 - self.overscan.right as f32 / width, 1.0 - self.overscan.bottom as f32 / height),
+        )
+    }
+
+    /// Size of the visible portion of the frame at 1x scale, with aspect correction applied
+    pub fn base_size(&self) -> Vec2 {
+        let width = FRAME_WIDTH.saturating_sub((self.overscan.left + self.overscan.right) as usize).max(1) as f32;
+        let height = FRAME_HEIGHT.saturating_sub((self.overscan.top + self.overscan.bottom) as usize).max(1) as f32;
+        let pixel_aspect = match self.aspect {
+            PixelAspect::Square => 1.0,
+            PixelAspect::Ntsc => NTSC_PIXEL_ASPECT,
+        };
+        Vec2::new((width * pixel_aspect).round(), height)
+    }
+
+    /// Size at which to draw the frame in a region of the given size
+    pub fn display_size(&self, available: Vec2) -> Vec2 {
+        let base = self.base_size();
+        let scale = (available.x / base.x).min(available.y / base.y);
+        let scale = match self.scaling {
+            // always show at least 1x, even if the window is too small
+            ScalingMode::Integer => scale.floor().max(1.0),
+            ScalingMode::Stretch => scale.max(0.0),
+        };
+        base * scale
+    }
+
+    /// Size the display region should have to show the frame at the configured window scale
+    pub fn window_size(&self) -> Vec2 {
+        self.base_size() * self.window_scale.max(1) as f32
+    }
+}
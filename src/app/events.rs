@@ -0,0 +1,136 @@
+use egui::{Color32, Context, Pos2, Rect, Sense, TextureHandle, Ui, Vec2};
+use log::error;
+
+use crate::hw::Nes;
+use crate::hw::ppu::{Ppu, PpuEvent, PpuEventKind, DOTS_PER_SCANLINE, FRAME_HEIGHT, FRAME_WIDTH, SCANLINES_PER_FRAME};
+
+const SCALE: f32 = 2.0;
+/// Distance in dots from the pointer within which events are listed on hover
+const HOVER_RADIUS: f32 = 3.0;
+
+const REGISTER_NAMES: [&str; 8] = [
+    "PPUCTRL", "PPUMASK", "PPUSTATUS", "OAMADDR", "OAMDATA", "PPUSCROLL", "PPUADDR", "PPUDATA",
+];
+const REGISTER_COLORS: [Color32; 8] = [
+    Color32::from_rgb(0xff, 0x55, 0x55),
+    Color32::from_rgb(0xff, 0xaa, 0x33),
+    Color32::from_rgb(0xff, 0xff, 0x55),
+    Color32::from_rgb(0x55, 0xff, 0x55),
+    Color32::from_rgb(0x33, 0xcc, 0x99),
+    Color32::from_rgb(0x55, 0xff, 0xff),
+    Color32::from_rgb(0x55, 0x88, 0xff),
+    Color32::from_rgb(0xcc, 0x66, 0xff),
+];
+const MAPPER_IRQ_COLOR: Color32 = Color32::from_rgb(0xff, 0x55, 0xcc);
+const SPRITE_ZERO_HIT_COLOR: Color32 = Color32::WHITE;
+const NMI_COLOR: Color32 = Color32::from_rgb(0xaa, 0xaa, 0xaa);
+
+fn event_color(kind: PpuEventKind) -> Color32 {
+    match kind {
+        PpuEventKind::RegisterWrite { addr, .. } => REGISTER_COLORS[(addr & 7) as usize],
+        PpuEventKind::MapperIrq => MAPPER_IRQ_COLOR,
+        PpuEventKind::SpriteZeroHit => SPRITE_ZERO_HIT_COLOR,
+        PpuEventKind::Nmi => NMI_COLOR,
+    }
+}
+
+fn describe_event(event: &PpuEvent) -> String {
+    let description = match event.kind {
+        PpuEventKind::RegisterWrite { addr, value } => {
+            format!("{} (${:04X}) = ${:02X}", REGISTER_NAMES[(addr & 7) as usize], addr, value)
+        }
+        PpuEventKind::MapperIrq => String::from("Mapper IRQ"),
+        PpuEventKind::SpriteZeroHit => String::from("Sprite 0 hit"),
+        PpuEventKind::Nmi => String::from("NMI"),
+    };
+    format!("{:3}, {:3}: {}", event.scanline, event.dot, description)
+}
+
+/// Debug window plotting the PPU events of the last frame by the scanline and dot at which they occurred
+#[derive(Default)]
+pub struct EventViewer {
+    is_open: bool,
+    /// whether the PPU has been told to record events
+    is_logging: bool,
+}
+
+impl EventViewer {
+    pub fn menu(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.is_open, "Event viewer");
+    }
+
+    /// Turn event recording in the PPU on or off to match whether the viewer is open
+    pub fn update_logging(&mut self, console: &Nes) {
+        if self.is_open == self.is_logging {
+            return;
+        }
+
+        match console.ppu_mut() {
+            Ok(mut ppu) => {
+                ppu.set_event_logging(self.is_open);
+                self.is_logging = self.is_open;
+            }
+            Err(e) => error!("Failed to update event logging: {}", e),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, ppu: &Ppu, frame: Option<&TextureHandle>) {
+        egui::Window::new("Event viewer").open(&mut self.is_open).show(ctx, |ui| {
+            Self::event_grid(ui, ppu.events(), frame);
+            Self::legend(ui);
+        });
+    }
+
+    fn event_grid(ui: &mut Ui, events: &[PpuEvent], frame: Option<&TextureHandle>) {
+        let size = Vec2::new(DOTS_PER_SCANLINE as f32, SCANLINES_PER_FRAME as f32) * SCALE;
+        let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter_at(rect);
+        let to_screen = |dot: f32, scanline: f32| rect.min + Vec2::new(dot, scanline) * SCALE;
+
+        painter.rect_filled(rect, 0.0, Color32::from_gray(0x20));
+        // the picture is output on dots 1-256 of the visible scanlines
+        let picture = Rect::from_min_max(to_screen(1.0, 0.0), to_screen(1.0 + FRAME_WIDTH as f32, FRAME_HEIGHT as f32));
+        match frame {
+            Some(texture) => {
+                let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                // dim the picture so events stand out
+                painter.image(texture.id(), picture, uv, Color32::from_gray(0x80));
+            }
+            None => {
+                painter.rect_filled(picture, 0.0, Color32::BLACK);
+            }
+        }
+
+        for event in events {
+            let min = to_screen(event.dot as f32, event.scanline as f32);
+            painter.rect_filled(Rect::from_min_size(min, Vec2::splat(SCALE)), 0.0, event_color(event.kind));
+        }
+
+        let Some(pointer) = response.hover_pos() else {
+            return;
+        };
+        let position = (pointer - rect.min) / SCALE;
+        let nearby: Vec<_> = events.iter()
+            .filter(|e| (e.dot as f32 - position.x).abs() <= HOVER_RADIUS && (e.scanline as f32 - position.y).abs() <= HOVER_RADIUS)
+            .collect();
+        response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("Scanline {}, dot {}", position.y as u16, position.x as u16));
+            for event in nearby {
+                ui.colored_label(event_color(event.kind), describe_event(event));
+            }
+        });
+    }
+
+    fn legend(ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            let entries = REGISTER_NAMES.iter().copied().zip(REGISTER_COLORS).chain([
+                ("Mapper IRQ", MAPPER_IRQ_COLOR),
+                ("Sprite 0 hit", SPRITE_ZERO_HIT_COLOR),
+                ("NMI", NMI_COLOR),
+            ]);
+            for (name, color) in entries {
+                ui.colored_label(color, name);
+            }
+        });
+    }
+}
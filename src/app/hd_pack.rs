@@ -0,0 +1,48 @@
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use rfd::AsyncFileDialog;
+
+use crate::video::{HdPack, HD_PACK_DEFINITION_FILE};
+
+/// Ask the user for an HD pack folder and load it. Returns `None` if the user cancelled.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn load_hd_pack() -> Result<Option<HdPack>> {
+    let Some(folder) = AsyncFileDialog::new().pick_folder().await else {
+        debug!("User cancelled HD pack selection");
+        return Ok(None);
+    };
+
+    let path = folder.path();
+    info!("Loading HD pack from {}", path.display());
+    let definition = std::fs::read_to_string(path.join(HD_PACK_DEFINITION_FILE))?;
+    let pack = HdPack::parse(&definition, |name| {
+        std::fs::read(path.join(name)).map_err(|e| anyhow!("Failed to read {}: {}", name, e))
+    })?;
+    Ok(Some(pack))
+}
+
+/// Ask the user for the files of an HD pack and load it. Browsers can't open folders, so the definition and all of
+/// its images have to be selected together.
+#[cfg(target_arch = "wasm32")]
+pub async fn load_hd_pack() -> Result<Option<HdPack>> {
+    use std::collections::HashMap;
+
+    let Some(handles) = AsyncFileDialog::new().add_filter("HD packs", &["txt", "png"]).pick_files().await else {
+        debug!("User cancelled HD pack selection");
+        return Ok(None);
+    };
+
+    let mut files = HashMap::new();
+    for handle in handles {
+        files.insert(handle.file_name(), handle.read().await);
+    }
+
+    let definition = files.get(HD_PACK_DEFINITION_FILE).ok_or_else(|| anyhow!("{} was not selected", HD_PACK_DEFINITION_FILE))?;
+    let definition = String::from_utf8_lossy(definition);
+    let pack = HdPack::parse(&definition, |name| {
+        // only the file name matters, since the selected files have no folder structure
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        files.get(name).cloned().ok_or_else(|| anyhow!("{} was not selected", name))
+    })?;
+    Ok(Some(pack))
+}
@@ -0,0 +1,30 @@
+use egui::{Context, Key};
+use log::error;
+
+use crate::hw::controller::Button;
+use crate::hw::Nes;
+
+/// Keys for each button of the controller in the first port
+const KEY_MAP: [(Key, Button); 8] = [
+    (Key::X, Button::A),
+    (Key::Z, Button::B),
+    (Key::Space, Button::Select),
+    (Key::Enter, Button::Start),
+    (Key::ArrowUp, Button::Up),
+    (Key::ArrowDown, Button::Down),
+    (Key::ArrowLeft, Button::Left),
+    (Key::ArrowRight, Button::Right),
+];
+
+/// Press the buttons of the first controller whose keys are held down
+pub fn update_controller(ctx: &Context, console: &Nes) {
+    // keys typed into a text field aren't meant for the game
+    let is_typing = ctx.wants_keyboard_input();
+    for (key, button) in KEY_MAP {
+        let is_pressed = !is_typing && ctx.input(|i| i.key_down(key));
+        if let Err(e) = console.set_button(0, button, is_pressed) {
+            error!("Failed to update controller: {}", e);
+            return;
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use egui::{Color32, Context, Pos2, Sense, Shape, Stroke, Ui, Vec2};
+use log::error;
+
+use crate::hw::Nes;
+use crate::hw::apu::{Apu, AudioChannel, ChannelScope, NUM_CHANNELS, SCOPE_LENGTH};
+
+const SCOPE_SIZE: Vec2 = Vec2::new(256.0, 40.0);
+/// Points shown in each scope. The rest of the buffer leaves room to line the wave up on a rising edge.
+const SCOPE_WINDOW: usize = SCOPE_LENGTH / 2;
+const SCOPE_COLOR: Color32 = Color32::from_rgb(0x55, 0xff, 0x55);
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChannelMix {
+    volume: f32,
+    is_muted: bool,
+    is_soloed: bool,
+}
+
+impl Default for ChannelMix {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            is_muted: false,
+            is_soloed: false,
+        }
+    }
+}
+
+/// Name and octave of the note closest to a frequency, e.g. A4 for 440 Hz
+fn note_name(frequency: f32) -> String {
+    let note = (69.0 + 12.0 * (frequency / 440.0).log2()).round() as i32;
+    format!("{}{}", NOTE_NAMES[note.rem_euclid(12) as usize], note.div_euclid(12) - 1)
+}
+
+/// Window for isolating the channels of the APU and any expansion chips, with an oscilloscope of each
+#[derive(Default)]
+pub struct MixerWindow {
+    is_open: bool,
+    channels: [ChannelMix; NUM_CHANNELS],
+    /// whether the APU has been told to record its channels for the scopes
+    is_scope_enabled: bool,
+    /// whether the mix has changed since it was last sent to the APU
+    is_mix_changed: bool,
+}
+
+impl MixerWindow {
+    pub fn menu(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.is_open, "Channel mixer");
+    }
+
+    /// Volume of each channel in the APU's mix. If any channel is soloed, only soloed channels are heard.
+    fn gains(&self) -> [f32; NUM_CHANNELS] {
+        let is_any_soloed = self.channels.iter().any(|c| c.is_soloed);
+        self.channels.map(|c| {
+            if c.is_muted || (is_any_soloed && !c.is_soloed) {
+                0.0
+            } else {
+                c.volume
+            }
+        })
+    }
+
+    /// Send the mix to the APU if it changed, and turn the scopes on or off to match whether the window is open
+    pub fn update_apu(&mut self, console: &Nes) {
+        if self.is_open == self.is_scope_enabled && !self.is_mix_changed {
+            return;
+        }
+
+        match console.apu_mut() {
+            Ok(mut apu) => {
+                apu.set_scope_enabled(self.is_open);
+                apu.set_channel_gains(self.gains());
+                self.is_scope_enabled = self.is_open;
+                self.is_mix_changed = false;
+            }
+            Err(e) => error!("Failed to update channel mix: {}", e),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, apu: &Apu) {
+        let old_channels = self.channels;
+        let channels = &mut self.channels;
+        egui::Window::new("Channel mixer").open(&mut self.is_open).show(ctx, |ui| {
+            egui::Grid::new("mixer_channels").num_columns(6).show(ui, |ui| {
+                for channel in apu.channels() {
+                    let mix = &mut channels[channel.index()];
+                    ui.label(channel.name());
+                    ui.toggle_value(&mut mix.is_muted, "M").on_hover_text("Mute");
+                    ui.toggle_value(&mut mix.is_soloed, "S").on_hover_text("Solo");
+                    ui.add(egui::Slider::new(&mut mix.volume, 0.0..=1.0).show_value(false));
+                    match apu.channel_frequency(channel) {
+                        Some(frequency) => ui.monospace(format!("{:<3} {:7.1} Hz", note_name(frequency), frequency)),
+                        None => ui.monospace(format!("{:14}", "")),
+                    };
+                    match apu.scope() {
+                        Some(scope) => Self::scope(ui, scope, channel),
+                        None => {
+                            ui.allocate_exact_size(SCOPE_SIZE, Sense::hover());
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        self.is_mix_changed |= self.channels != old_channels;
+    }
+
+    fn scope(ui: &mut Ui, scope: &ChannelScope, channel: AudioChannel) {
+        let (rect, _) = ui.allocate_exact_size(SCOPE_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::from_gray(0x20));
+
+        let points: Vec<f32> = scope.points(channel).collect();
+        // start on a rising edge so periodic waves hold still from frame to frame
+        let (min, max) = points.iter().fold((f32::MAX, f32::MIN), |(min, max), &p| (min.min(p), max.max(p)));
+        let middle = (min + max) / 2.0;
+        let start = (1..=SCOPE_LENGTH - SCOPE_WINDOW)
+            .find(|&i| points[i - 1] < middle && points[i] >= middle)
+            .unwrap_or(SCOPE_LENGTH - SCOPE_WINDOW);
+
+        let line = points[start..start + SCOPE_WINDOW]
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                let x = rect.left() + rect.width() * i as f32 / (SCOPE_WINDOW - 1) as f32;
+                let y = rect.bottom() - 1.0 - (rect.height() - 2.0) * p;
+                Pos2::new(x, y)
+            })
+            .collect();
+        painter.add(Shape::line(line, Stroke::new(1.0, SCOPE_COLOR)));
+    }
+}
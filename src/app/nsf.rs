@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use egui::{Context, Ui};
+use log::error;
+
+use crate::hw::nsf::{NsfPlayer, DEFAULT_FADE, DEFAULT_TRACK_LENGTH};
+
+/// Format a duration as minutes and seconds
+fn format_time(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Window for choosing and following the tracks of a loaded NSF
+pub struct NsfWindow {
+    /// length and fade-out in seconds for tracks which don't specify their own
+    default_length: u64,
+    default_fade: u64,
+    is_auto_advance_enabled: bool,
+}
+
+impl Default for NsfWindow {
+    fn default() -> Self {
+        Self {
+            default_length: DEFAULT_TRACK_LENGTH.as_secs(),
+            default_fade: DEFAULT_FADE.as_secs(),
+            is_auto_advance_enabled: true,
+        }
+    }
+}
+
+impl NsfWindow {
+    /// Show the player if an NSF is loaded
+    pub fn show(&mut self, ctx: &Context, player: &mut NsfPlayer) {
+        let Some(nsf) = player.nsf() else {
+            return;
+        };
+        let num_tracks = nsf.tracks.len();
+        let mut selected_track = None;
+
+        egui::Window::new("NSF player").show(ctx, |ui| {
+            ui.heading(if nsf.title.is_empty() { "<untitled>" } else { nsf.title.as_str() });
+            ui.label(&nsf.artist);
+            ui.label(&nsf.copyright);
+            let expansion_audio = nsf.expansion_audio.names();
+            if !expansion_audio.is_empty() {
+                ui.label(format!("Expansion audio: {}", expansion_audio.join(", ")));
+            }
+            ui.separator();
+
+            let track = player.track();
+            let (length, fade) = player.track_length();
+            let elapsed = player.elapsed().min(length + fade);
+            ui.label(format!("Track {}/{}", track + 1, num_tracks));
+            ui.add(
+                egui::ProgressBar::new(elapsed.as_secs_f32() / (length + fade).as_secs_f32().max(1.0))
+                    .text(format!("{} / {}", format_time(elapsed), format_time(length))),
+            );
+            ui.horizontal(|ui| {
+                if ui.add_enabled(track > 0, egui::Button::new("Previous")).clicked() {
+                    selected_track = Some(track - 1);
+                }
+                if ui.button("Restart").clicked() {
+                    selected_track = Some(track);
+                }
+                if ui.add_enabled(track + 1 < num_tracks, egui::Button::new("Next")).clicked() {
+                    selected_track = Some(track + 1);
+                }
+            });
+            ui.separator();
+
+            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                for (i, info) in nsf.tracks.iter().enumerate() {
+                    let title = info.title.clone().unwrap_or_else(|| format!("Track {}", i + 1));
+                    let text = match info.length {
+                        Some(length) => format!("{}. {} ({})", i + 1, title, format_time(length)),
+                        None => format!("{}. {}", i + 1, title),
+                    };
+                    if ui.selectable_label(i == track, text).clicked() {
+                        selected_track = Some(i);
+                    }
+                }
+            });
+            ui.separator();
+
+            self.settings(ui);
+        });
+
+        player.set_default_length(Duration::from_secs(self.default_length), Duration::from_secs(self.default_fade));
+        if self.is_auto_advance_enabled && player.is_finished() && player.track() + 1 < num_tracks {
+            selected_track = Some(player.track() + 1);
+        }
+        if let Some(track) = selected_track {
+            if let Err(e) = player.start_track(track) {
+                error!("Failed to start NSF track: {}", e);
+            }
+        }
+    }
+
+    fn settings(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Default length");
+            ui.add(egui::DragValue::new(&mut self.default_length).range(1..=3600).suffix(" s"));
+            ui.label("Fade-out");
+            ui.add(egui::DragValue::new(&mut self.default_fade).range(0..=60).suffix(" s"));
+        })
+        .response
+        .on_hover_text("Used for tracks whose length isn't given in the file");
+        ui.checkbox(&mut self.is_auto_advance_enabled, "Play the next track when one finishes");
+    }
+}
@@ -0,0 +1,36 @@
+use egui::{Context, Ui};
+
+use crate::audio::{AudioSettings, MAX_LATENCY_MS};
+
+/// Window with the audio output settings
+#[derive(Default)]
+pub struct AudioWindow {
+    is_open: bool,
+}
+
+impl AudioWindow {
+    pub fn menu(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.is_open, "Audio settings");
+    }
+
+    /// Show the window if it's open. Returns whether any settings were changed.
+    pub fn show(&mut self, ctx: &Context, settings: &mut AudioSettings) -> bool {
+        let old_settings = *settings;
+        egui::Window::new("Audio settings").open(&mut self.is_open).show(ctx, |ui| {
+            ui.checkbox(&mut settings.is_muted, "Mute");
+            ui.add_enabled(
+                !settings.is_muted,
+                egui::Slider::new(&mut settings.volume, 0.0..=1.0).text("Volume").custom_formatter(|v, _| {
+                    format!("{:.0}%", v * 100.0)
+                }),
+            );
+            ui.add(egui::Slider::new(&mut settings.latency_ms, 10..=MAX_LATENCY_MS).text("Latency").suffix(" ms"))
+                .on_hover_text("How far audio can be buffered ahead. Lower values respond faster, but may crackle.");
+            ui.checkbox(&mut settings.is_n163_multiplexed, "Namco 163 multiplexing").on_hover_text(
+                "Switch between the chip's channels like the hardware, which whines when many are in use. Otherwise \
+                they're mixed cleanly.",
+            );
+        });
+        *settings != old_settings
+    }
+}
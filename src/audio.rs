@@ -0,0 +1,12 @@
+mod backend;
+mod blip;
+mod filter;
+mod pipeline;
+mod ring;
+mod sink;
+
+pub use backend::{open_default as open_audio_backend, AudioBackend, NullBackend};
+pub use filter::FilterOptions;
+pub use pipeline::AudioPipeline;
+pub use ring::SampleRing;
+pub use sink::{AudioSettings, AudioSink, MAX_LATENCY_MS};
@@ -0,0 +1,34 @@
+use log::warn;
+
+use super::ring::SampleRing;
+
+mod null;
+pub use null::NullBackend;
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+#[cfg(target_arch = "wasm32")]
+mod web;
+
+/// An audio device playing whatever is pushed into its ring buffer
+pub trait AudioBackend {
+    fn sample_rate(&self) -> u32;
+
+    /// Start playback if the platform requires a user interaction first
+    fn resume(&self) {}
+}
+
+/// Open the platform's default audio device, falling back to no output if that fails
+pub fn open_default(ring: SampleRing) -> Box<dyn AudioBackend> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let result = native::NativeBackend::new(ring);
+    #[cfg(target_arch = "wasm32")]
+    let result = web::WebBackend::new(ring);
+
+    match result {
+        Ok(backend) => Box::new(backend),
+        Err(e) => {
+            warn!("Failed to open audio device, continuing without sound: {}", e);
+            Box::new(NullBackend::default())
+        }
+    }
+}
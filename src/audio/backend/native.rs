@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use log::{error, info};
+
+use super::AudioBackend;
+use crate::audio::ring::SampleRing;
+
+/// Plays audio through the host's default output device
+pub struct NativeBackend {
+    sample_rate: u32,
+    _stream: Stream,
+}
+
+impl NativeBackend {
+    pub fn new(ring: SampleRing) -> Result<Self> {
+        let device = cpal::default_host().default_output_device().ok_or_else(|| anyhow!("No output device"))?;
+        let supported = device.default_output_config()?;
+        let config = supported.config();
+        info!("Opening audio device {} at {} Hz", device.name().unwrap_or_default(), config.sample_rate.0);
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => Self::build_stream::<f32>(&device, &config, ring)?,
+            SampleFormat::I16 => Self::build_stream::<i16>(&device, &config, ring)?,
+            SampleFormat::U16 => Self::build_stream::<u16>(&device, &config, ring)?,
+            format => return Err(anyhow!("Unsupported sample format {}", format)),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            sample_rate: config.sample_rate.0,
+            _stream: stream,
+        })
+    }
+
+    fn build_stream<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        ring: SampleRing,
+    ) -> Result<Stream> {
+        let channels = config.channels as usize;
+        let mut mono = Vec::new();
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                // the emulator's output is mono, so copy it to every channel
+                mono.resize(data.len() / channels, 0.0);
+                ring.pop(&mut mono);
+                for (frame, &sample) in data.chunks_exact_mut(channels).zip(&mono) {
+                    frame.fill(T::from_sample(sample));
+                }
+            },
+            |e| error!("Audio stream error: {}", e),
+            None,
+        )?;
+        Ok(stream)
+    }
+}
+
+impl AudioBackend for NativeBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
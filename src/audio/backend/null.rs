@@ -0,0 +1,19 @@
+use super::AudioBackend;
+
+/// Discards all audio, for when there's no device or no need for one
+#[derive(Debug, Clone, Copy)]
+pub struct NullBackend {
+    sample_rate: u32,
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self { sample_rate: 48000 }
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Result};
+use eframe::wasm_bindgen::closure::Closure;
+use eframe::wasm_bindgen::JsCast;
+use log::error;
+use web_sys::{AudioContext, AudioProcessingEvent, ScriptProcessorNode};
+
+use super::AudioBackend;
+use crate::audio::ring::SampleRing;
+
+/// Samples per callback. Browsers accept powers of two from 256 to 16384.
+const BUFFER_SIZE: u32 = 1024;
+
+/// Plays audio through the Web Audio API
+pub struct WebBackend {
+    context: AudioContext,
+    _processor: ScriptProcessorNode,
+    _callback: Closure<dyn FnMut(AudioProcessingEvent)>,
+}
+
+impl WebBackend {
+    pub fn new(ring: SampleRing) -> Result<Self> {
+        let js_error = |e| anyhow!("{:?}", e);
+        let context = AudioContext::new().map_err(js_error)?;
+        let processor = context
+            .create_script_processor_with_buffer_size_and_number_of_input_channels_and_number_of_output_channels(
+                BUFFER_SIZE, 0, 1,
+            )
+            .map_err(js_error)?;
+
+        let mut samples = vec![0.0; BUFFER_SIZE as usize];
+        let callback = Closure::<dyn FnMut(AudioProcessingEvent)>::new(move |event: AudioProcessingEvent| {
+            let Ok(buffer) = event.output_buffer() else {
+                return;
+            };
+            samples.resize(buffer.length() as usize, 0.0);
+            ring.pop(&mut samples);
+            if let Err(e) = buffer.copy_to_channel(&samples, 0) {
+                error!("Failed to output audio: {:?}", e);
+            }
+        });
+        processor.set_onaudioprocess(Some(callback.as_ref().unchecked_ref()));
+        processor.connect_with_audio_node(&context.destination()).map_err(js_error)?;
+
+        Ok(Self {
+            context,
+            _processor: processor,
+            _callback: callback,
+        })
+    }
+}
+
+impl AudioBackend for WebBackend {
+    fn sample_rate(&self) -> u32 {
+        self.context.sample_rate() as u32
+    }
+
+    /// Browsers keep audio suspended until the user interacts with the page
+    fn resume(&self) {
+        if let Err(e) = self.context.resume() {
+            error!("Failed to resume audio: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::f64::consts::PI;
+
+/// Number of output samples each step is spread over
+const KERNEL_WIDTH: usize = 16;
+/// Number of fractional positions between output samples that steps can be placed at
+const PHASES: usize = 64;
+/// Cutoff of the band-limiting filter, as a fraction of the output sample rate. Slightly below Nyquist, so that the
+/// transition band doesn't alias.
+const CUTOFF: f64 = 0.45;
+
+/// Band-limited step synthesis: turns amplitude changes at a high clock rate into samples at a lower rate without
+/// aliasing, by drawing each change as a windowed-sinc step rather than an instant jump
+pub struct BlipBuffer {
+    /// output samples per input clock
+    factor: f64,
+    /// position of the start of the current frame, in output samples
+    offset: f64,
+    /// the derivative of the output: each step is added as a band-limited impulse and integrated on the way out
+    buffer: Vec<f32>,
+    integrator: f32,
+    kernels: Box<[[f32; KERNEL_WIDTH]; PHASES + 1]>,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            factor: sample_rate / clock_rate,
+            offset: 0.0,
+            buffer: Vec::new(),
+            integrator: 0.0,
+            kernels: Self::build_kernels(),
+        }
+    }
+
+    /// Blackman-windowed sinc impulses for each phase, each normalized to sum to 1 so that steps are exact at DC
+    fn build_kernels() -> Box<[[f32; KERNEL_WIDTH]; PHASES + 1]> {
+        let mut kernels = Box::new([[0.0; KERNEL_WIDTH]; PHASES + 1]);
+        let half = (KERNEL_WIDTH / 2) as f64;
+        for (phase, kernel) in kernels.iter_mut().enumerate() {
+            let fraction = phase as f64 / PHASES as f64;
+            let mut values = [0.0; KERNEL_WIDTH];
+            for (i, value) in values.iter_mut().enumerate() {
+                // distance from the impulse, which sits between taps half - 1 and half
+                let x = i as f64 - (half - 1.0) - fraction;
+                let sinc = if x == 0.0 { 1.0 } else { (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x) };
+                let t = (x + half) / (2.0 * half);
+                let window = if (0.0..=1.0).contains(&t) {
+                    0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos()
+                } else {
+                    0.0
+                };
+                *value = sinc * window;
+            }
+
+            let sum: f64 = values.iter().sum();
+            for (out, value) in kernel.iter_mut().zip(values) {
+                *out = (value / sum) as f32;
+            }
+        }
+        kernels
+    }
+
+    /// Add a change in amplitude `clock` input clocks after the start of the current frame
+    pub fn add_delta(&mut self, clock: u64, delta: f32) {
+        let position = self.offset + clock as f64 * self.factor;
+        let index = position as usize;
+        let phase = ((position - index as f64) * PHASES as f64).round() as usize;
+
+        if self.buffer.len() < index + KERNEL_WIDTH {
+            self.buffer.resize(index + KERNEL_WIDTH, 0.0);
+        }
+        for (sample, weight) in self.buffer[index..].iter_mut().zip(&self.kernels[phase]) {
+            *sample += delta * weight;
+        }
+    }
+
+    /// End the current frame after `clocks` input clocks, making the samples before that point available
+    pub fn end_frame(&mut self, clocks: u64) {
+        self.offset += clocks as f64 * self.factor;
+    }
+
+    /// Number of output samples that can be read. Output lags the input by half the kernel width.
+    pub fn samples_available(&self) -> usize {
+        self.offset as usize
+    }
+
+    /// Move every available sample to `out`
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) {
+        let count = self.samples_available();
+        if self.buffer.len() < count {
+            self.buffer.resize(count, 0.0);
+        }
+
+        for delta in self.buffer.drain(..count) {
+            self.integrator += delta;
+            out.push(self.integrator);
+        }
+        self.offset -= count as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK_RATE: f64 = 1_789_773.0;
+    const SAMPLE_RATE: f64 = 48000.0;
+
+    #[test]
+    fn frames_produce_samples_at_output_rate() {
+        let mut blip = BlipBuffer::new(CLOCK_RATE, SAMPLE_RATE);
+        let mut out = Vec::new();
+        for _ in 0..60 {
+            blip.end_frame(29830);
+            blip.read_samples(&mut out);
+        }
+        let expected = 60.0 * 29830.0 * SAMPLE_RATE / CLOCK_RATE;
+        assert_eq!(out.len(), expected as usize);
+    }
+
+    #[test]
+    fn step_settles_at_its_height() {
+        for clock in [0, 1, 17, 20] {
+            let mut blip = BlipBuffer::new(CLOCK_RATE, SAMPLE_RATE);
+            blip.add_delta(clock, 0.5);
+            blip.end_frame(2000);
+            let mut out = Vec::new();
+            blip.read_samples(&mut out);
+            assert!(out.iter().skip(KERNEL_WIDTH).all(|&sample| (sample - 0.5).abs() < 1e-6), "step at clock {}", clock);
+        }
+    }
+
+    #[test]
+    fn kernels_have_unity_gain() {
+        for kernel in BlipBuffer::build_kernels().iter() {
+            let sum: f32 = kernel.iter().sum();
+            assert!((sum - 1.0).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::f32::consts::PI;
+
+/// Filters applied after resampling, by default matching the first-order filters in the NES's output stage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterOptions {
+    /// High-pass cutoffs in Hz, or `None` to bypass them. The NES has two, at 90 Hz and 440 Hz.
+    pub high_pass: [Option<f32>; 2],
+    /// Low-pass cutoff in Hz, or `None` to bypass it. The NES's is at 14 kHz.
+    pub low_pass: Option<f32>,
+}
+
+impl Default for FilterOptions {
+    fn default() -> Self {
+        Self {
+            high_pass: [Some(90.0), Some(440.0)],
+            low_pass: Some(14000.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    HighPass,
+    LowPass,
+}
+
+/// A first-order RC filter
+#[derive(Debug, Clone, Copy)]
+pub struct OnePole {
+    kind: Kind,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl OnePole {
+    fn new(kind: Kind, cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        let alpha = match kind {
+            Kind::HighPass => rc / (rc + dt),
+            Kind::LowPass => dt / (rc + dt),
+        };
+        Self { kind, alpha, previous_input: 0.0, previous_output: 0.0 }
+    }
+
+    pub fn high_pass(cutoff: f32, sample_rate: f32) -> Self {
+        Self::new(Kind::HighPass, cutoff, sample_rate)
+    }
+
+    pub fn low_pass(cutoff: f32, sample_rate: f32) -> Self {
+        Self::new(Kind::LowPass, cutoff, sample_rate)
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = match self.kind {
+            Kind::HighPass => self.alpha * (self.previous_output + input - self.previous_input),
+            Kind::LowPass => self.previous_output + self.alpha * (input - self.previous_output),
+        };
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+/// Builds the chain of filters described by `FilterOptions`
+pub fn build_chain(options: &FilterOptions, sample_rate: f32) -> Vec<OnePole> {
+    let high_pass = options.high_pass.iter().flatten().map(|&cutoff| OnePole::high_pass(cutoff, sample_rate));
+    let low_pass = options.low_pass.map(|cutoff| OnePole::low_pass(cutoff, sample_rate));
+    high_pass.chain(low_pass).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// Gain of a filter on a sine wave, once it has settled
+    fn gain(filter: &mut OnePole, frequency: f32) -> f32 {
+        let samples = SAMPLE_RATE as usize;
+        (0..samples)
+            .map(|i| filter.process((2.0 * PI * frequency * i as f32 / SAMPLE_RATE).sin()))
+            .skip(samples / 2)
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn high_pass_blocks_dc() {
+        let mut filter = OnePole::high_pass(90.0, SAMPLE_RATE);
+        let last = (0..SAMPLE_RATE as usize).map(|_| filter.process(1.0)).last().unwrap();
+        assert!(last.abs() < 1e-3);
+    }
+
+    #[test]
+    fn low_pass_passes_dc() {
+        let mut filter = OnePole::low_pass(14000.0, SAMPLE_RATE);
+        let last = (0..SAMPLE_RATE as usize).map(|_| filter.process(1.0)).last().unwrap();
+        assert!((last - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cutoff_is_half_power() {
+        let high_pass = gain(&mut OnePole::high_pass(440.0, SAMPLE_RATE), 440.0);
+        let low_pass = gain(&mut OnePole::low_pass(1000.0, SAMPLE_RATE), 1000.0);
+        // the discrete filters only approximate the analog ones, so they're a little off at the cutoff
+        for gain in [high_pass, low_pass] {
+            assert!((gain - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.05, "gain of {}", gain);
+        }
+    }
+
+    #[test]
+    fn chain_skips_bypassed_filters() {
+        assert_eq!(build_chain(&FilterOptions::default(), SAMPLE_RATE).len(), 3);
+        let options = FilterOptions { high_pass: [None, Some(440.0)], low_pass: None };
+        assert_eq!(build_chain(&options, SAMPLE_RATE).len(), 1);
+    }
+}
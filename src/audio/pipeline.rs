@@ -0,0 +1,117 @@
+use super::blip::BlipBuffer;
+use super::filter::{self, FilterOptions, OnePole};
+
+/// Converts the APU's output at its native rate to filtered 16-bit samples at the host's sample rate
+pub struct AudioPipeline {
+    blip: BlipBuffer,
+    filters: Vec<OnePole>,
+    /// the last input amplitude, which steps are measured from
+    level: f32,
+    resampled: Vec<f32>,
+}
+
+impl AudioPipeline {
+    pub fn new(clock_rate: f64, sample_rate: u32, filters: &FilterOptions) -> Self {
+        Self {
+            blip: BlipBuffer::new(clock_rate, sample_rate as f64),
+            filters: filter::build_chain(filters, sample_rate as f32),
+            level: 0.0,
+            resampled: Vec::new(),
+        }
+    }
+
+    /// Convert a block of input samples, one per clock, appending the output to `out`
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<i16>) {
+        for (clock, &sample) in input.iter().enumerate() {
+            if sample != self.level {
+                self.blip.add_delta(clock as u64, sample - self.level);
+                self.level = sample;
+            }
+        }
+        self.blip.end_frame(input.len() as u64);
+
+        self.resampled.clear();
+        self.blip.read_samples(&mut self.resampled);
+        out.extend(self.resampled.iter().map(|&sample| {
+            let sample = self.filters.iter_mut().fold(sample, |sample, filter| filter.process(sample));
+            (sample * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::hw::apu::NATIVE_SAMPLE_RATE;
+
+    const SAMPLE_RATE: u32 = 48000;
+    /// Roughly one NTSC frame of CPU cycles, the size of the blocks the APU's output arrives in
+    const BLOCK_CLOCKS: usize = 29781;
+    /// Clocks per half period of the test square wave, about 3 kHz, whose 9th harmonic is above the output's Nyquist
+    /// frequency
+    const HALF_PERIOD: usize = 298;
+
+    const NO_FILTERS: FilterOptions = FilterOptions { high_pass: [None, None], low_pass: None };
+
+    /// Run a second of a square wave between 0 and `amplitude` through a pipeline
+    fn render_square(amplitude: f32, filters: &FilterOptions) -> Vec<i16> {
+        let mut pipeline = AudioPipeline::new(NATIVE_SAMPLE_RATE, SAMPLE_RATE, filters);
+        let input: Vec<_> = (0..NATIVE_SAMPLE_RATE as usize)
+            .map(|clock| if (clock / HALF_PERIOD).is_multiple_of(2) { amplitude } else { 0.0 })
+            .collect();
+        let mut out = Vec::new();
+        for block in input.chunks(BLOCK_CLOCKS) {
+            pipeline.process(block, &mut out);
+        }
+        out
+    }
+
+    /// Magnitude of a frequency in a signal, measured with a Hann-windowed Goertzel filter
+    fn magnitude(samples: &[i16], frequency: f64) -> f64 {
+        let n = samples.len() as f64;
+        let coefficient = 2.0 * (2.0 * PI * frequency / SAMPLE_RATE as f64).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for (i, &sample) in samples.iter().enumerate() {
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / n).cos();
+            let s0 = sample as f64 * window + coefficient * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        (s1 * s1 + s2 * s2 - coefficient * s1 * s2).sqrt()
+    }
+
+    #[test]
+    fn output_is_at_target_rate() {
+        let out = render_square(0.5, &FilterOptions::default());
+        // a second of input is a second of output, give or take the fraction of a sample still in the buffer
+        assert!(out.len().abs_diff(SAMPLE_RATE as usize) <= 1, "{} samples", out.len());
+    }
+
+    #[test]
+    fn square_wave_does_not_clip() {
+        const AMPLITUDE: f32 = 0.8;
+        for filters in [NO_FILTERS, FilterOptions::default()] {
+            let out = render_square(AMPLITUDE, &filters);
+            let peak = out.iter().map(|sample| sample.unsigned_abs()).max().unwrap();
+            // band-limited edges ring, overshooting each step by around 12%
+            assert!(peak < i16::MAX as u16, "clipped with {:?}", filters);
+            assert!(peak as f32 <= AMPLITUDE * 1.15 * i16::MAX as f32, "peak of {} with {:?}", peak, filters);
+        }
+    }
+
+    #[test]
+    fn square_wave_does_not_alias() {
+        let out = render_square(0.5, &NO_FILTERS);
+        let fundamental = NATIVE_SAMPLE_RATE / (2 * HALF_PERIOD) as f64;
+        let reference = magnitude(&out, fundamental);
+        // the odd harmonics from the 9th up fold back below Nyquist, between the ones which don't. Point sampling would
+        // leave the 9th only 19 dB down.
+        for harmonic in [9.0, 11.0, 13.0, 15.0] {
+            let alias = SAMPLE_RATE as f64 - harmonic * fundamental;
+            let level = 20.0 * (magnitude(&out, alias) / reference).log10();
+            assert!(level < -40.0, "harmonic {} aliased to {:.0} Hz at {:.1} dB", harmonic, alias, level);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct RingState {
+    samples: VecDeque<f32>,
+    /// the most recently played sample, held when the buffer runs dry so that underruns don't click
+    last: f32,
+}
+
+/// Carries samples from the emulation thread to the audio device. Both ends share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct SampleRing {
+    state: Arc<Mutex<RingState>>,
+}
+
+impl SampleRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add samples, discarding the oldest to keep at most `max_len` buffered, which bounds the latency
+    pub fn push(&self, samples: &[f32], max_len: usize) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+
+        state.samples.extend(samples);
+        let excess = state.samples.len().saturating_sub(max_len);
+        state.samples.drain(..excess);
+    }
+
+    /// Fill `out` with the oldest buffered samples
+    pub fn pop(&self, out: &mut [f32]) {
+        let Ok(mut state) = self.state.lock() else {
+            out.fill(0.0);
+            return;
+        };
+
+        for sample in out {
+            if let Some(next) = state.samples.pop_front() {
+                state.last = next;
+            }
+            *sample = state.last;
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+use super::filter::FilterOptions;
+use super::pipeline::AudioPipeline;
+use super::ring::SampleRing;
+
+pub const MAX_LATENCY_MS: u32 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// from 0 to 1
+    pub volume: f32,
+    pub is_muted: bool,
+    /// how much audio can be buffered ahead of the device. Lower values risk crackling when emulation stutters.
+    pub latency_ms: u32,
+    /// output one Namco 163 channel at a time like the hardware, rather than a clean mix
+    pub is_n163_multiplexed: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            volume: 0.75,
+            is_muted: false,
+            latency_ms: 60,
+            is_n163_multiplexed: true,
+        }
+    }
+}
+
+/// The emulation thread's end of the audio output: resamples the APU's output for the device and queues it
+pub struct AudioSink {
+    clock_rate: f64,
+    sample_rate: u32,
+    pipeline: AudioPipeline,
+    ring: SampleRing,
+    settings: AudioSettings,
+    resampled: Vec<i16>,
+    output: Vec<f32>,
+    /// samples at the native rate kept for recording, while a recording is running
+    recorded: Option<Vec<f32>>,
+}
+
+impl AudioSink {
+    pub fn new(clock_rate: f64, sample_rate: u32, ring: SampleRing, settings: AudioSettings) -> Self {
+        Self {
+            clock_rate,
+            sample_rate,
+            pipeline: AudioPipeline::new(clock_rate, sample_rate, &FilterOptions::default()),
+            ring,
+            settings,
+            resampled: Vec::new(),
+            output: Vec::new(),
+            recorded: None,
+        }
+    }
+
+    /// Send output to a different device
+    pub fn set_output(&mut self, sample_rate: u32, ring: SampleRing) {
+        self.sample_rate = sample_rate;
+        self.pipeline = AudioPipeline::new(self.clock_rate, sample_rate, &FilterOptions::default());
+        self.ring = ring;
+    }
+
+    pub fn set_settings(&mut self, settings: AudioSettings) {
+        self.settings = settings;
+    }
+
+    /// Keep a copy of the native output until it's collected with `take_recorded`
+    pub fn set_recording(&mut self, is_enabled: bool) {
+        if is_enabled != self.recorded.is_some() {
+            self.recorded = is_enabled.then(Vec::new);
+        }
+    }
+
+    /// Native samples since the last call, if recording
+    pub fn take_recorded(&mut self) -> Vec<f32> {
+        self.recorded.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Queue samples produced by the APU at its native rate
+    pub fn push(&mut self, samples: &[f32]) {
+        if let Some(ref mut recorded) = self.recorded {
+            recorded.extend_from_slice(samples);
+        }
+
+        self.resampled.clear();
+        self.pipeline.process(samples, &mut self.resampled);
+
+        let volume = if self.settings.is_muted { 0.0 } else { self.settings.volume / i16::MAX as f32 };
+        self.output.clear();
+        self.output.extend(self.resampled.iter().map(|&sample| sample as f32 * volume));
+
+        let max_len = (self.sample_rate as u64 * self.settings.latency_ms as u64 / 1000) as usize;
+        self.ring.push(&self.output, max_len);
+    }
+}
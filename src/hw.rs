@@ -1,8 +1,12 @@
+pub mod apu;
 mod clock;
 mod bus;
 mod component;
+pub mod controller;
 mod device;
 pub mod nes;
 mod cpu;
+pub mod nsf;
+pub mod ppu;
 
 pub use nes::*;
\ No newline at end of file
@@ -0,0 +1,371 @@
+use anyhow::Result;
+
+use super::bus::{Bus, CartridgeSlot};
+use super::component::Component;
+
+mod channel;
+pub use channel::{AudioChannel, NUM_CHANNELS};
+mod dmc;
+use dmc::Dmc;
+mod envelope;
+mod expansion;
+pub use expansion::{create_chips as create_expansion_chips, ExpansionChip, ExpansionOptions};
+mod frame_counter;
+use frame_counter::FrameCounter;
+mod length;
+mod noise;
+use noise::Noise;
+mod pulse;
+use pulse::Pulse;
+mod scope;
+pub use scope::{ChannelScope, SCOPE_LENGTH};
+mod triangle;
+use triangle::Triangle;
+
+const NUM_APU_CHANNELS: usize = AudioChannel::APU.len();
+/// Rate at which the APU produces samples: one per CPU cycle
+pub const NATIVE_SAMPLE_RATE: f64 = 236_250_000.0 / 11.0 / 12.0;
+/// Samples kept for the frontend before the oldest are discarded, around a second's worth
+const MAX_BUFFERED_SAMPLES: usize = 1 << 21;
+/// CPU cycles the DMC's sample fetch halts the CPU for. Depending on what the CPU is doing, it can be anywhere from 1
+/// to 4; this is the common case of a fetch during a read cycle.
+const DMC_DMA_CYCLES: u64 = 4;
+
+/// STATUS ($4015)
+const STATUS_FRAME_IRQ: u8 = 0x40;
+const STATUS_DMC_IRQ: u8 = 0x80;
+
+/// The APU's view of the console. The DMC can only fetch samples from $8000-$FFFF, which is all cartridge space.
+pub struct ApuBus {
+    cartridge: CartridgeSlot,
+}
+
+impl ApuBus {
+    pub const fn new(cartridge: CartridgeSlot) -> Self {
+        Self { cartridge }
+    }
+}
+
+impl Bus for ApuBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        match self.cartridge.read() {
+            Ok(cartridge) => cartridge.as_ref().map_or(0, |c| c.cpu_read(addr)),
+            Err(_) => 0,
+        }
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {
+        // the APU never writes to the bus
+    }
+}
+
+/// The 2A03's audio processing unit
+pub struct Apu {
+    bus: ApuBus,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    /// whether this is the second CPU cycle of an APU cycle
+    is_odd_cycle: bool,
+    /// CPU cycles the CPU must be halted for DMC sample fetches that have happened since they were last collected
+    dma_stall_cycles: u64,
+    samples: Vec<f32>,
+    /// volume of each channel in the mix, from 0 to 1
+    channel_gains: [f32; NUM_CHANNELS],
+    /// overall volume, from 0 to 1
+    volume: f32,
+    scope: Option<ChannelScope>,
+    /// each channel's output on its own, kept while the channels are being recorded separately
+    channel_samples: Option<Vec<[f32; NUM_CHANNELS]>>,
+    /// sound chips on the cartridge
+    expansion_chips: Vec<Box<dyn ExpansionChip>>,
+    expansion_options: ExpansionOptions,
+}
+
+impl Apu {
+    pub fn new(bus: ApuBus) -> Self {
+        Self {
+            bus,
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+            is_odd_cycle: false,
+            dma_stall_cycles: 0,
+            samples: Vec::new(),
+            channel_gains: [1.0; NUM_CHANNELS],
+            volume: 1.0,
+            scope: None,
+            channel_samples: None,
+            expansion_chips: Vec::new(),
+            expansion_options: ExpansionOptions::default(),
+        }
+    }
+
+    /// State of the APU's /IRQ output, from either the frame counter or the DMC
+    pub const fn irq(&self) -> bool {
+        self.frame_counter.is_irq_pending || self.dmc.is_irq_pending
+    }
+
+    /// Collect the number of cycles the CPU has to be halted for DMC DMA
+    pub fn take_dma_stall_cycles(&mut self) -> u64 {
+        std::mem::take(&mut self.dma_stall_cycles)
+    }
+
+    /// Samples produced since the last call, at `NATIVE_SAMPLE_RATE`, ranging from 0 to about 1
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Set the volume of each channel in the mix, from 0 (silent) to 1 (as on hardware)
+    pub fn set_channel_gains(&mut self, gains: [f32; NUM_CHANNELS]) {
+        self.channel_gains = gains;
+    }
+
+    /// Set the overall volume, from 0 (silent) to 1 (as on hardware)
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    /// Start or stop recording the channels' outputs for an oscilloscope
+    pub fn set_scope_enabled(&mut self, is_enabled: bool) {
+        if is_enabled != self.scope.is_some() {
+            self.scope = is_enabled.then(ChannelScope::new);
+        }
+    }
+
+    pub const fn scope(&self) -> Option<&ChannelScope> {
+        self.scope.as_ref()
+    }
+
+    /// Start or stop keeping each channel's output on its own, as if soloed, until it's collected with
+    /// `take_channel_samples`
+    pub fn set_channel_recording(&mut self, is_enabled: bool) {
+        if is_enabled != self.channel_samples.is_some() {
+            self.channel_samples = is_enabled.then(Vec::new);
+        }
+    }
+
+    /// Every channel's output on its own since the last call, indexed by `AudioChannel::index`. Channels not in use
+    /// are silent.
+    pub fn take_channel_samples(&mut self) -> Vec<[f32; NUM_CHANNELS]> {
+        self.channel_samples.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Replace the sound chips on the cartridge
+    pub fn set_expansion_chips(&mut self, chips: Vec<Box<dyn ExpansionChip>>) {
+        self.expansion_chips = chips;
+        for chip in &mut self.expansion_chips {
+            chip.set_options(self.expansion_options);
+        }
+    }
+
+    pub fn set_expansion_options(&mut self, options: ExpansionOptions) {
+        self.expansion_options = options;
+        for chip in &mut self.expansion_chips {
+            chip.set_options(options);
+        }
+    }
+
+    /// Handle a CPU read from the cartridge, if it is from a sound chip's register
+    pub fn read_expansion(&mut self, addr: u16) -> Option<u8> {
+        self.expansion_chips.iter_mut().find_map(|chip| chip.read(addr))
+    }
+
+    /// Handle a CPU write to the cartridge, for any sound chips on it
+    pub fn write_expansion(&mut self, addr: u16, value: u8) {
+        for chip in &mut self.expansion_chips {
+            chip.write(addr, value);
+        }
+    }
+
+    /// The channels of the APU and the sound chips on the cartridge
+    pub fn channels(&self) -> Vec<AudioChannel> {
+        let expansion_channels = self.expansion_chips.iter().flat_map(|chip| chip.channels());
+        AudioChannel::APU.iter().chain(expansion_channels).copied().collect()
+    }
+
+    /// Pitch in Hz of a tonal channel, if it is currently audible
+    pub fn channel_frequency(&self, channel: AudioChannel) -> Option<f32> {
+        match channel {
+            AudioChannel::Pulse1 => self.pulse1.frequency(NATIVE_SAMPLE_RATE),
+            AudioChannel::Pulse2 => self.pulse2.frequency(NATIVE_SAMPLE_RATE),
+            AudioChannel::Triangle => self.triangle.frequency(NATIVE_SAMPLE_RATE),
+            AudioChannel::Noise | AudioChannel::Dmc => None,
+            _ => self.expansion_chips
+                .iter()
+                .find(|chip| chip.channels().contains(&channel))
+                .and_then(|chip| chip.frequency(channel, NATIVE_SAMPLE_RATE)),
+        }
+    }
+
+    /// Handle a CPU read from $4015. The other APU registers are write-only.
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = [
+            self.pulse1.length.is_active(),
+            self.pulse2.length.is_active(),
+            self.triangle.length.is_active(),
+            self.noise.length.is_active(),
+            self.dmc.is_active(),
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |status, (i, &is_active)| status | ((is_active as u8) << i));
+
+        if self.frame_counter.is_irq_pending {
+            status |= STATUS_FRAME_IRQ;
+        }
+        if self.dmc.is_irq_pending {
+            status |= STATUS_DMC_IRQ;
+        }
+        // reading acknowledges the frame IRQ, but not the DMC's
+        self.frame_counter.is_irq_pending = false;
+        status
+    }
+
+    /// Handle a CPU write to one of the APU's registers ($4000-$4013, $4015 or $4017)
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        let register = addr & 3;
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(register, value),
+            0x4004..=0x4007 => self.pulse2.write(register, value),
+            0x4008..=0x400b => self.triangle.write(register, value),
+            0x400c..=0x400f => self.noise.write(register, value),
+            0x4010..=0x4013 => self.dmc.write(register, value),
+            0x4015 => {
+                self.pulse1.length.set_enabled(value & 0x01 != 0);
+                self.pulse2.length.set_enabled(value & 0x02 != 0);
+                self.triangle.length.set_enabled(value & 0x04 != 0);
+                self.noise.length.set_enabled(value & 0x08 != 0);
+                self.dmc.set_enabled(value & 0x10 != 0);
+                self.dmc.is_irq_pending = false;
+            }
+            0x4017 => self.frame_counter.write(value, self.is_odd_cycle),
+            _ => (),
+        }
+    }
+
+    fn channel_outputs(&self) -> [u8; NUM_APU_CHANNELS] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+
+    /// Combine the channels' outputs the way the NES's resistor network does, which is far from linear
+    fn mix(&self, outputs: [u8; NUM_APU_CHANNELS], gains: &[f32; NUM_CHANNELS]) -> f32 {
+        let [pulse1, pulse2, triangle, noise, dmc] = std::array::from_fn(|i| outputs[i] as f32 * gains[i]);
+
+        let pulse = pulse1 + pulse2;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        // the cartridge's sound is mixed in after the APU's, and is roughly linear
+        let expansion_out: f32 = self.expansion_chips.iter().map(|chip| chip.output(gains)).sum();
+
+        (pulse_out + tnd_out + expansion_out) * self.volume
+    }
+
+    /// What each channel in use would output if it were soloed at full volume
+    fn isolated_outputs(&self, outputs: [u8; NUM_APU_CHANNELS]) -> [f32; NUM_CHANNELS] {
+        let mut isolated = [0.0; NUM_CHANNELS];
+        let expansion_channels = self.expansion_chips.iter().flat_map(|chip| chip.channels());
+        for &channel in AudioChannel::APU.iter().chain(expansion_channels) {
+            let mut gains = [0.0; NUM_CHANNELS];
+            gains[channel.index()] = 1.0;
+            isolated[channel.index()] = self.mix(outputs, &gains);
+        }
+        isolated
+    }
+
+    /// Each channel's current output from 0 to 1, for the scope
+    fn channel_levels(&self, outputs: [u8; NUM_APU_CHANNELS]) -> [f32; NUM_CHANNELS] {
+        let mut levels = [0.0; NUM_CHANNELS];
+        for (i, output) in outputs.into_iter().enumerate() {
+            let max_output = if AudioChannel::APU[i] == AudioChannel::Dmc { 127.0 } else { 15.0 };
+            levels[i] = output as f32 / max_output;
+        }
+        for chip in &self.expansion_chips {
+            for &channel in chip.channels() {
+                levels[channel.index()] = chip.level(channel);
+            }
+        }
+        levels
+    }
+
+    fn tick(&mut self) {
+        let signals = self.frame_counter.clock();
+        if signals.quarter_frame {
+            self.pulse1.envelope.clock();
+            self.pulse2.envelope.clock();
+            self.noise.envelope.clock();
+            self.triangle.clock_linear_counter();
+        }
+        if signals.half_frame {
+            self.pulse1.length.clock();
+            self.pulse1.clock_sweep();
+            self.pulse2.length.clock();
+            self.pulse2.clock_sweep();
+            self.triangle.length.clock();
+            self.noise.length.clock();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.is_odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        for chip in &mut self.expansion_chips {
+            chip.clock();
+        }
+        self.is_odd_cycle = !self.is_odd_cycle;
+
+        if let Some(addr) = self.dmc.pending_fetch() {
+            let value = self.bus.read(addr);
+            self.dmc.load_sample(value);
+            self.dma_stall_cycles += DMC_DMA_CYCLES;
+        }
+
+        if self.samples.len() >= MAX_BUFFERED_SAMPLES {
+            // nobody is collecting the samples, so drop the older half rather than growing forever
+            self.samples.drain(..MAX_BUFFERED_SAMPLES / 2);
+        }
+        let outputs = self.channel_outputs();
+        if self.scope.is_some() {
+            let levels = self.channel_levels(outputs);
+            if let Some(scope) = &mut self.scope {
+                scope.record(levels);
+            }
+        }
+        if self.channel_samples.is_some() {
+            let isolated = self.isolated_outputs(outputs);
+            if let Some(channel_samples) = &mut self.channel_samples {
+                if channel_samples.len() >= MAX_BUFFERED_SAMPLES {
+                    channel_samples.drain(..MAX_BUFFERED_SAMPLES / 2);
+                }
+                channel_samples.push(isolated);
+            }
+        }
+        let sample = self.mix(outputs, &self.channel_gains);
+        self.samples.push(sample);
+    }
+}
+
+impl Component for Apu {
+    fn step(&mut self) -> Result<u64> {
+        self.tick();
+        Ok(1)
+    }
+}
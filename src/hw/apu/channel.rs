@@ -0,0 +1,111 @@
+/// The sound channels of the APU and the expansion chips, for mixing and visualization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    Vrc6Pulse1,
+    Vrc6Pulse2,
+    Vrc6Sawtooth,
+    Vrc7Fm1,
+    Vrc7Fm2,
+    Vrc7Fm3,
+    Vrc7Fm4,
+    Vrc7Fm5,
+    Vrc7Fm6,
+    N163Wave1,
+    N163Wave2,
+    N163Wave3,
+    N163Wave4,
+    N163Wave5,
+    N163Wave6,
+    N163Wave7,
+    N163Wave8,
+    Sunsoft5bA,
+    Sunsoft5bB,
+    Sunsoft5bC,
+}
+
+pub const NUM_CHANNELS: usize = AudioChannel::ALL.len();
+
+impl AudioChannel {
+    pub const ALL: [Self; 25] = [
+        Self::Pulse1,
+        Self::Pulse2,
+        Self::Triangle,
+        Self::Noise,
+        Self::Dmc,
+        Self::Vrc6Pulse1,
+        Self::Vrc6Pulse2,
+        Self::Vrc6Sawtooth,
+        Self::Vrc7Fm1,
+        Self::Vrc7Fm2,
+        Self::Vrc7Fm3,
+        Self::Vrc7Fm4,
+        Self::Vrc7Fm5,
+        Self::Vrc7Fm6,
+        Self::N163Wave1,
+        Self::N163Wave2,
+        Self::N163Wave3,
+        Self::N163Wave4,
+        Self::N163Wave5,
+        Self::N163Wave6,
+        Self::N163Wave7,
+        Self::N163Wave8,
+        Self::Sunsoft5bA,
+        Self::Sunsoft5bB,
+        Self::Sunsoft5bC,
+    ];
+    /// The channels built into the APU
+    pub const APU: [Self; 5] = [Self::Pulse1, Self::Pulse2, Self::Triangle, Self::Noise, Self::Dmc];
+    pub const VRC6: [Self; 3] = [Self::Vrc6Pulse1, Self::Vrc6Pulse2, Self::Vrc6Sawtooth];
+    pub const VRC7: [Self; 6] = [Self::Vrc7Fm1, Self::Vrc7Fm2, Self::Vrc7Fm3, Self::Vrc7Fm4, Self::Vrc7Fm5, Self::Vrc7Fm6];
+    /// In the order the chip enables them, so the first N are the ones in use
+    pub const N163: [Self; 8] = [
+        Self::N163Wave1,
+        Self::N163Wave2,
+        Self::N163Wave3,
+        Self::N163Wave4,
+        Self::N163Wave5,
+        Self::N163Wave6,
+        Self::N163Wave7,
+        Self::N163Wave8,
+    ];
+    pub const SUNSOFT_5B: [Self; 3] = [Self::Sunsoft5bA, Self::Sunsoft5bB, Self::Sunsoft5bC];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Pulse1 => "Pulse 1",
+            Self::Pulse2 => "Pulse 2",
+            Self::Triangle => "Triangle",
+            Self::Noise => "Noise",
+            Self::Dmc => "DMC",
+            Self::Vrc6Pulse1 => "VRC6 pulse 1",
+            Self::Vrc6Pulse2 => "VRC6 pulse 2",
+            Self::Vrc6Sawtooth => "VRC6 sawtooth",
+            Self::Vrc7Fm1 => "VRC7 FM 1",
+            Self::Vrc7Fm2 => "VRC7 FM 2",
+            Self::Vrc7Fm3 => "VRC7 FM 3",
+            Self::Vrc7Fm4 => "VRC7 FM 4",
+            Self::Vrc7Fm5 => "VRC7 FM 5",
+            Self::Vrc7Fm6 => "VRC7 FM 6",
+            Self::N163Wave1 => "N163 wave 1",
+            Self::N163Wave2 => "N163 wave 2",
+            Self::N163Wave3 => "N163 wave 3",
+            Self::N163Wave4 => "N163 wave 4",
+            Self::N163Wave5 => "N163 wave 5",
+            Self::N163Wave6 => "N163 wave 6",
+            Self::N163Wave7 => "N163 wave 7",
+            Self::N163Wave8 => "N163 wave 8",
+            Self::Sunsoft5bA => "5B square A",
+            Self::Sunsoft5bB => "5B square B",
+            Self::Sunsoft5bC => "5B square C",
+        }
+    }
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
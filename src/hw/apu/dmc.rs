@@ -0,0 +1,138 @@
+/// Timer periods in CPU cycles (NTSC)
+const RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+
+/// The delta modulation channel ($4010-$4013), which plays 1-bit delta-encoded samples from CPU memory
+#[derive(Debug, Clone, Copy)]
+pub struct Dmc {
+    is_irq_enabled: bool,
+    is_looping: bool,
+    rate: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    is_silenced: bool,
+    pub(super) is_irq_pending: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            is_irq_enabled: false,
+            is_looping: false,
+            rate: RATES[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            is_silenced: true,
+            is_irq_pending: false,
+        }
+    }
+}
+
+impl Dmc {
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.is_irq_enabled = value & 0x80 != 0;
+                self.is_looping = value & 0x40 != 0;
+                self.rate = RATES[(value & 0x0f) as usize];
+                if !self.is_irq_enabled {
+                    self.is_irq_pending = false;
+                }
+            }
+            1 => self.level = value & 0x7f,
+            2 => self.sample_address = 0xc000 | ((value as u16) << 6),
+            _ => self.sample_length = ((value as u16) << 4) + 1,
+        }
+    }
+
+    /// Start or stop sample playback through $4015
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        if !is_enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub const fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// Address of the next sample byte, if the memory reader needs one
+    pub const fn pending_fetch(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Fill the sample buffer with the byte fetched from `pending_fetch`
+    pub fn load_sample(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        // the address wraps around to $8000 rather than $0000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.is_looping {
+                self.restart();
+            } else if self.is_irq_enabled {
+                self.is_irq_pending = true;
+            }
+        }
+    }
+
+    /// Clock the timer once per CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+
+        if !self.is_silenced {
+            if self.shift_register & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.is_silenced = false;
+                    self.shift_register = sample;
+                }
+                None => self.is_silenced = true,
+            }
+        }
+    }
+
+    pub const fn output(&self) -> u8 {
+        self.level
+    }
+}
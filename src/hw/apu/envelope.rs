@@ -0,0 +1,47 @@
+/// Volume envelope shared by the pulse and noise channels
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Envelope {
+    is_constant: bool,
+    is_looping: bool,
+    /// constant volume, or the period of the decay
+    volume: u8,
+    is_start_pending: bool,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Update from the low six bits of the channel's first register
+    pub fn write(&mut self, value: u8) {
+        self.is_looping = value & 0x20 != 0;
+        self.is_constant = value & 0x10 != 0;
+        self.volume = value & 0x0f;
+    }
+
+    /// Restart the decay on the next quarter frame, as happens when the channel's length is loaded
+    pub fn restart(&mut self) {
+        self.is_start_pending = true;
+    }
+
+    /// Clock on each quarter frame
+    pub fn clock(&mut self) {
+        if self.is_start_pending {
+            self.is_start_pending = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.is_looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub const fn output(&self) -> u8 {
+        if self.is_constant { self.volume } else { self.decay }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::rom::ExpansionAudio;
+use super::channel::{AudioChannel, NUM_CHANNELS};
+
+mod n163;
+use n163::N163;
+mod sunsoft5b;
+use sunsoft5b::Sunsoft5b;
+mod vrc6;
+use vrc6::Vrc6;
+mod vrc7;
+use vrc7::Vrc7;
+
+/// Choices in how the chips are emulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionOptions {
+    /// output one Namco 163 channel at a time like the hardware, which whines when many channels are in use, rather
+    /// than a clean mix
+    pub is_n163_multiplexed: bool,
+}
+
+impl Default for ExpansionOptions {
+    fn default() -> Self {
+        Self { is_n163_multiplexed: true }
+    }
+}
+
+/// A sound chip on the cartridge, whose output is mixed with the APU's
+pub trait ExpansionChip: Send + Sync {
+    /// The channels the chip adds
+    fn channels(&self) -> &'static [AudioChannel];
+
+    fn set_options(&mut self, _options: ExpansionOptions) {}
+
+    /// Handle a CPU read from the cartridge, if the address is one of the chip's readable registers
+    fn read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Handle a CPU write to the cartridge. Addresses that aren't the chip's are ignored.
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// Clock the chip once per CPU cycle
+    fn clock(&mut self);
+
+    /// Current output of one of the chip's channels, from 0 to 1
+    fn level(&self, channel: AudioChannel) -> f32;
+
+    /// Output with each channel scaled by its gain, on the same scale as the APU's
+    fn output(&self, gains: &[f32; NUM_CHANNELS]) -> f32;
+
+    /// Pitch in Hz of one of the chip's channels, if it is currently audible
+    fn frequency(&self, channel: AudioChannel, cpu_clock_rate: f64) -> Option<f32>;
+}
+
+/// Create the chips an NSF or a board uses. Only the ones that are emulated are created. `is_vrc6_a0_a1_swapped` is
+/// for the VRC6b board, which wires the VRC6's two low address lines the other way round.
+pub fn create_chips(expansion_audio: ExpansionAudio, is_vrc6_a0_a1_swapped: bool) -> Vec<Box<dyn ExpansionChip>> {
+    let mut chips: Vec<Box<dyn ExpansionChip>> = Vec::new();
+    if expansion_audio.vrc6() {
+        chips.push(Box::new(if is_vrc6_a0_a1_swapped { Vrc6::with_swapped_address_lines() } else { Vrc6::default() }));
+    }
+    if expansion_audio.vrc7() {
+        chips.push(Box::new(Vrc7::default()));
+    }
+    if expansion_audio.n163() {
+        chips.push(Box::new(N163::default()));
+    }
+    if expansion_audio.sunsoft_5b() {
+        chips.push(Box::new(Sunsoft5b::default()));
+    }
+    chips
+}
@@ -0,0 +1,171 @@
+use super::{AudioChannel, ExpansionChip, ExpansionOptions, NUM_CHANNELS};
+
+/// Output per step of a channel's sample times its volume. Boards used different resistors after the chip's DAC, so
+/// this is a typical level: a lone channel at full volume is about twice as loud as an APU pulse.
+const LEVEL: f32 = 0.00133;
+/// CPU cycles the chip spends updating each channel
+const CYCLES_PER_CHANNEL: u8 = 15;
+const RAM_SIZE: usize = 0x80;
+/// Registers of the first channel. They also hold the number of channels in use, and each channel after it is 8 bytes
+/// lower.
+const FIRST_CHANNEL_REGISTERS: usize = 0x78;
+/// Address port bit which advances the address after each access through the data port
+const AUTO_INCREMENT: u8 = 0x80;
+
+/// `channels` has to return a slice of this, which a temporary copy of the constant wouldn't allow
+static CHANNELS: [AudioChannel; 8] = AudioChannel::N163;
+
+/// What a channel last output, held until the chip gets back around to it
+#[derive(Debug, Default, Clone, Copy)]
+struct ChannelOutput {
+    /// 4-bit sample, centred on 8
+    sample: u8,
+    volume: u8,
+}
+
+impl ChannelOutput {
+    fn centred(self) -> f32 {
+        (self.sample as f32 - 8.0) * self.volume as f32
+    }
+}
+
+/// Namco's 163: up to eight wavetable channels, with their waves and registers all in 128 bytes of internal RAM. It
+/// only has one DAC, so it updates and outputs one channel at a time, and the more channels are in use the slower
+/// each one is updated.
+#[derive(Debug)]
+pub struct N163 {
+    ram: [u8; RAM_SIZE],
+    /// the address port, including the auto-increment bit
+    address: u8,
+    outputs: [ChannelOutput; 8],
+    /// the channel being output, which was the last to be updated
+    current: usize,
+    cycles: u8,
+    /// output only the current channel, like the hardware, rather than an even mix of them all
+    is_multiplexed: bool,
+}
+
+impl Default for N163 {
+    fn default() -> Self {
+        Self {
+            ram: [0; RAM_SIZE],
+            address: 0,
+            outputs: [ChannelOutput::default(); 8],
+            current: 0,
+            cycles: 0,
+            is_multiplexed: true,
+        }
+    }
+}
+
+impl N163 {
+    fn num_channels(&self) -> usize {
+        ((self.ram[FIRST_CHANNEL_REGISTERS + 7] >> 4) & 7) as usize + 1
+    }
+
+    /// Access the RAM through the data port
+    fn data_port(&mut self) -> &mut u8 {
+        let addr = (self.address & 0x7f) as usize;
+        if self.address & AUTO_INCREMENT != 0 {
+            self.address = AUTO_INCREMENT | (self.address.wrapping_add(1) & 0x7f);
+        }
+        &mut self.ram[addr]
+    }
+
+    const fn registers(channel: usize) -> usize {
+        FIRST_CHANNEL_REGISTERS - 8 * channel
+    }
+
+    /// 18-bit amount the phase advances by each update
+    fn frequency_register(&self, channel: usize) -> u32 {
+        let r = Self::registers(channel);
+        self.ram[r] as u32 | (self.ram[r + 2] as u32) << 8 | (self.ram[r + 4] as u32 & 3) << 16
+    }
+
+    /// Length of the channel's wave in 4-bit samples
+    fn wave_length(&self, channel: usize) -> u32 {
+        256 - (self.ram[Self::registers(channel) + 4] as u32 & 0xfc)
+    }
+
+    /// Advance a channel's phase, which is kept in RAM, and latch the sample it lands on
+    fn update(&mut self, channel: usize) {
+        let r = Self::registers(channel);
+        let phase = self.ram[r + 1] as u32 | (self.ram[r + 3] as u32) << 8 | (self.ram[r + 5] as u32) << 16;
+        let phase = (phase + self.frequency_register(channel)) % (self.wave_length(channel) << 16);
+        self.ram[r + 1] = phase as u8;
+        self.ram[r + 3] = (phase >> 8) as u8;
+        self.ram[r + 5] = (phase >> 16) as u8;
+
+        // samples are packed two to a byte, low nibble first
+        let sample_addr = (self.ram[r + 6] as u32 + (phase >> 16)) & 0xff;
+        let byte = self.ram[sample_addr as usize >> 1];
+        self.outputs[channel] = ChannelOutput {
+            sample: if sample_addr & 1 == 0 { byte & 0x0f } else { byte >> 4 },
+            volume: self.ram[r + 7] & 0x0f,
+        };
+    }
+}
+
+impl ExpansionChip for N163 {
+    fn channels(&self) -> &'static [AudioChannel] {
+        &CHANNELS[..self.num_channels()]
+    }
+
+    fn set_options(&mut self, options: ExpansionOptions) {
+        self.is_multiplexed = options.is_n163_multiplexed;
+    }
+
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4fff => Some(*self.data_port()),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800..=0x4fff => *self.data_port() = value,
+            0xf800..=0xffff => self.address = value,
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.cycles = 0;
+        self.current = (self.current + 1) % self.num_channels();
+        self.update(self.current);
+    }
+
+    fn level(&self, channel: AudioChannel) -> f32 {
+        match CHANNELS.iter().position(|&c| c == channel) {
+            Some(i) => (self.outputs[i].sample as f32 * self.outputs[i].volume as f32) / 225.0,
+            None => 0.0,
+        }
+    }
+
+    fn output(&self, gains: &[f32; NUM_CHANNELS]) -> f32 {
+        if self.is_multiplexed {
+            self.outputs[self.current].centred() * gains[CHANNELS[self.current].index()] * LEVEL
+        } else {
+            // the average of the multiplexed output, without the whine of switching between channels
+            let num_channels = self.num_channels();
+            let sum: f32 = (0..num_channels).map(|i| self.outputs[i].centred() * gains[CHANNELS[i].index()]).sum();
+            sum / num_channels as f32 * LEVEL
+        }
+    }
+
+    fn frequency(&self, channel: AudioChannel, cpu_clock_rate: f64) -> Option<f32> {
+        let i = CHANNELS[..self.num_channels()].iter().position(|&c| c == channel)?;
+        let frequency = self.frequency_register(i);
+        let is_audible = frequency > 0 && self.ram[Self::registers(i) + 7] & 0x0f > 0;
+        // each update advances the phase, which is 16.16 fixed point in samples
+        let updates_per_second = cpu_clock_rate / (CYCLES_PER_CHANNEL as f64 * self.num_channels() as f64);
+        is_audible.then(|| {
+            (updates_per_second * frequency as f64 / (65536.0 * self.wave_length(i) as f64)) as f32
+        })
+    }
+}
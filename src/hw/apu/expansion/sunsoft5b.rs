@@ -0,0 +1,272 @@
+use super::{AudioChannel, ExpansionChip, NUM_CHANNELS};
+
+/// Output of a channel at full volume. The 5B is loud: a channel at full volume is nearly as loud as both APU pulses
+/// together.
+const LEVEL: f32 = 0.25;
+/// CPU cycles per tick of the tone, noise and envelope counters
+const PRESCALER: u8 = 16;
+/// Each envelope level is 1.5 dB quieter than the one above, and each fixed volume two envelope levels
+const DB_PER_LEVEL: f32 = 1.5;
+const MAX_LEVEL: u8 = 31;
+
+/// Mixer ($07) bits, which are set to turn a source off
+const TONE_DISABLE: u8 = 0x01;
+const NOISE_DISABLE: u8 = 0x08;
+/// Volume ($08-$0A) bit which uses the envelope instead of the fixed volume
+const USE_ENVELOPE: u8 = 0x10;
+
+/// Envelope shape ($0D) bits
+const HOLD: u8 = 0x01;
+const ALTERNATE: u8 = 0x02;
+const ATTACK: u8 = 0x04;
+const CONTINUE: u8 = 0x08;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Tone {
+    /// 12-bit
+    period: u16,
+    counter: u16,
+    is_high: bool,
+    volume: u8,
+}
+
+impl Tone {
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.is_high = !self.is_high;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Noise {
+    /// 5-bit
+    period: u8,
+    counter: u8,
+    /// the noise runs at half the rate of the tones, so it only counts every other tick
+    is_odd_tick: bool,
+    /// 17-bit linear feedback shift register
+    shift: u32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            period: 0,
+            counter: 0,
+            is_odd_tick: false,
+            shift: 1,
+        }
+    }
+}
+
+impl Noise {
+    fn clock(&mut self) {
+        self.is_odd_tick = !self.is_odd_tick;
+        if self.is_odd_tick {
+            return;
+        }
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            let feedback = (self.shift ^ (self.shift >> 3)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 16);
+        }
+    }
+
+    const fn is_high(&self) -> bool {
+        self.shift & 1 != 0
+    }
+}
+
+/// Shared by all three channels, stepping through 32 levels in one of the shapes set by $0D
+#[derive(Debug, Default, Clone, Copy)]
+struct Envelope {
+    /// 16-bit
+    period: u16,
+    counter: u16,
+    shape: u8,
+    /// steps through the levels, counting up whether they rise or fall
+    step: u8,
+    is_rising: bool,
+    is_holding: bool,
+}
+
+impl Envelope {
+    fn write_shape(&mut self, value: u8) {
+        self.shape = value & 0x0f;
+        self.counter = 0;
+        self.step = 0;
+        self.is_rising = self.shape & ATTACK != 0;
+        self.is_holding = false;
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter < self.period.max(1) {
+            return;
+        }
+        self.counter = 0;
+        if self.is_holding {
+            return;
+        }
+
+        self.step += 1;
+        if self.step <= MAX_LEVEL {
+            return;
+        }
+        self.step = MAX_LEVEL;
+        if self.shape & CONTINUE == 0 {
+            // silent once the first ramp is done
+            self.is_rising = false;
+            self.is_holding = true;
+            return;
+        }
+        if self.shape & ALTERNATE != 0 {
+            self.is_rising = !self.is_rising;
+        }
+        if self.shape & HOLD != 0 {
+            self.is_holding = true;
+        } else {
+            self.step = 0;
+        }
+    }
+
+    const fn level(&self) -> u8 {
+        if self.is_rising { self.step } else { MAX_LEVEL - self.step }
+    }
+}
+
+/// Sunsoft's 5B, a licensed YM2149F on the FME-7: three square channels which can each mix in a shared noise
+/// generator and use a shared envelope instead of a fixed volume
+#[derive(Debug)]
+pub struct Sunsoft5b {
+    tones: [Tone; 3],
+    noise: Noise,
+    envelope: Envelope,
+    mixer: u8,
+    /// register selected by $C000
+    register: u8,
+    prescaler: u8,
+    /// amplitude of each envelope level, which are logarithmic
+    amplitudes: [f32; MAX_LEVEL as usize + 1],
+}
+
+impl Default for Sunsoft5b {
+    fn default() -> Self {
+        Self {
+            tones: [Tone::default(); 3],
+            noise: Noise::default(),
+            envelope: Envelope::default(),
+            mixer: 0,
+            register: 0,
+            prescaler: 0,
+            amplitudes: std::array::from_fn(|level| {
+                if level == 0 {
+                    0.0
+                } else {
+                    10f32.powf((level as f32 - MAX_LEVEL as f32) * DB_PER_LEVEL / 20.0)
+                }
+            }),
+        }
+    }
+}
+
+impl Sunsoft5b {
+    fn write_register(&mut self, value: u8) {
+        match self.register {
+            0x00..=0x05 => {
+                let tone = &mut self.tones[self.register as usize / 2];
+                if self.register.is_multiple_of(2) {
+                    tone.period = (tone.period & 0xf00) | value as u16;
+                } else {
+                    tone.period = (tone.period & 0x0ff) | ((value as u16 & 0x0f) << 8);
+                }
+            }
+            0x06 => self.noise.period = value & 0x1f,
+            0x07 => self.mixer = value,
+            0x08..=0x0a => self.tones[self.register as usize - 8].volume = value & 0x1f,
+            0x0b => self.envelope.period = (self.envelope.period & 0xff00) | value as u16,
+            0x0c => self.envelope.period = (self.envelope.period & 0x00ff) | ((value as u16) << 8),
+            0x0d => self.envelope.write_shape(value),
+            // the I/O ports aren't connected
+            _ => (),
+        }
+    }
+
+    /// Envelope level of a channel's volume, whether fixed or from the envelope
+    fn volume_level(&self, i: usize) -> u8 {
+        let volume = self.tones[i].volume;
+        if volume & USE_ENVELOPE != 0 {
+            self.envelope.level()
+        } else if volume == 0 {
+            0
+        } else {
+            volume * 2 + 1
+        }
+    }
+
+    /// Amplitude of a channel from 0 to 1. Sources which are off in the mixer count as high.
+    fn channel_output(&self, i: usize) -> f32 {
+        let is_tone_high = self.tones[i].is_high || self.mixer & (TONE_DISABLE << i) != 0;
+        let is_noise_high = self.noise.is_high() || self.mixer & (NOISE_DISABLE << i) != 0;
+        if is_tone_high && is_noise_high {
+            self.amplitudes[self.volume_level(i) as usize]
+        } else {
+            0.0
+        }
+    }
+}
+
+impl ExpansionChip for Sunsoft5b {
+    fn channels(&self) -> &'static [AudioChannel] {
+        &AudioChannel::SUNSOFT_5B
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xc000..=0xdfff => self.register = value & 0x0f,
+            0xe000..=0xffff => self.write_register(value),
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < PRESCALER {
+            return;
+        }
+        self.prescaler = 0;
+        for tone in &mut self.tones {
+            tone.clock();
+        }
+        self.noise.clock();
+        self.envelope.clock();
+    }
+
+    fn level(&self, channel: AudioChannel) -> f32 {
+        match AudioChannel::SUNSOFT_5B.iter().position(|&c| c == channel) {
+            Some(i) => self.channel_output(i),
+            None => 0.0,
+        }
+    }
+
+    fn output(&self, gains: &[f32; NUM_CHANNELS]) -> f32 {
+        let sum: f32 = AudioChannel::SUNSOFT_5B
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| self.channel_output(i) * gains[channel.index()])
+            .sum();
+        sum * LEVEL
+    }
+
+    fn frequency(&self, channel: AudioChannel, cpu_clock_rate: f64) -> Option<f32> {
+        let i = AudioChannel::SUNSOFT_5B.iter().position(|&c| c == channel)?;
+        let is_audible = self.mixer & (TONE_DISABLE << i) == 0 && self.volume_level(i) > 0;
+        // the output toggles each time the counter reaches the period
+        let period = self.tones[i].period.max(1) as f64;
+        is_audible.then(|| (cpu_clock_rate / (2.0 * PRESCALER as f64 * period)) as f32)
+    }
+}
@@ -0,0 +1,215 @@
+use super::{AudioChannel, ExpansionChip, NUM_CHANNELS};
+
+/// Output per step of the chip's 6-bit DAC. It matches the APU's pulse channels, so a VRC6 pulse is as loud as an
+/// APU one at the same volume.
+const LEVEL: f32 = 0.00752;
+
+/// Frequency control ($9003)
+const HALT: u8 = 0x01;
+const FREQUENCY_X16: u8 = 0x02;
+const FREQUENCY_X256: u8 = 0x04;
+
+/// Shared by the pulse and sawtooth channels: a 12-bit period and an enable flag in the high bit of the third register
+#[derive(Debug, Default, Clone, Copy)]
+struct Timer {
+    period: u16,
+    counter: u16,
+    is_enabled: bool,
+}
+
+impl Timer {
+    fn write_period(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.period = (self.period & 0xf00) | value as u16,
+            2 => {
+                self.period = (self.period & 0x0ff) | ((value as u16 & 0x0f) << 8);
+                self.is_enabled = value & 0x80 != 0;
+            }
+            _ => (),
+        }
+    }
+
+    /// Count down, returning whether the timer reloaded. `shift` divides the period for the frequency control's
+    /// speed-up modes.
+    fn clock(&mut self, shift: u8) -> bool {
+        if !self.is_enabled {
+            return false;
+        }
+        if self.counter == 0 {
+            self.counter = self.period >> shift;
+            true
+        } else {
+            self.counter -= 1;
+            false
+        }
+    }
+
+    fn frequency(&self, cpu_clock_rate: f64, steps: f64) -> f32 {
+        (cpu_clock_rate / (steps * (self.period as f64 + 1.0))) as f32
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Pulse {
+    volume: u8,
+    duty: u8,
+    /// ignore the duty cycle and output the volume constantly
+    is_digitized: bool,
+    timer: Timer,
+    /// counts down from 15, with the output high once it's at or below the duty
+    step: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        if register == 0 {
+            self.volume = value & 0x0f;
+            self.duty = (value >> 4) & 7;
+            self.is_digitized = value & 0x80 != 0;
+        } else {
+            self.timer.write_period(register, value);
+            if !self.timer.is_enabled {
+                self.step = 15;
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.timer.clock(shift) {
+            self.step = self.step.checked_sub(1).unwrap_or(15);
+        }
+    }
+
+    const fn output(&self) -> u8 {
+        if self.timer.is_enabled && (self.is_digitized || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Sawtooth {
+    rate: u8,
+    timer: Timer,
+    /// the accumulator is stepped every other time the timer reloads and reset on the 14th
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn write(&mut self, register: u16, value: u8) {
+        if register == 0 {
+            self.rate = value & 0x3f;
+        } else {
+            self.timer.write_period(register, value);
+            if !self.timer.is_enabled {
+                self.step = 0;
+                self.accumulator = 0;
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.timer.clock(shift) {
+            return;
+        }
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    /// The top five bits of the accumulator
+    const fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// Konami's VRC6: two pulse channels with eight duty cycles and a sawtooth channel
+#[derive(Debug, Default)]
+pub struct Vrc6 {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    sawtooth: Sawtooth,
+    frequency_control: u8,
+    /// the VRC6b board (mapper 26) wires A0 to the chip's A1 and A1 to its A0
+    is_a0_a1_swapped: bool,
+}
+
+impl Vrc6 {
+    pub fn with_swapped_address_lines() -> Self {
+        Self {
+            is_a0_a1_swapped: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl ExpansionChip for Vrc6 {
+    fn channels(&self) -> &'static [AudioChannel] {
+        &AudioChannel::VRC6
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let addr = if self.is_a0_a1_swapped {
+            (addr & !3) | ((addr & 1) << 1) | ((addr >> 1) & 1)
+        } else {
+            addr
+        };
+        let register = addr & 3;
+        match addr {
+            0x9000..=0x9002 => self.pulse1.write(register, value),
+            0x9003 => self.frequency_control = value,
+            0xa000..=0xa002 => self.pulse2.write(register, value),
+            0xb000..=0xb002 => self.sawtooth.write(register, value),
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.frequency_control & HALT != 0 {
+            return;
+        }
+        let shift = if self.frequency_control & FREQUENCY_X256 != 0 {
+            8
+        } else if self.frequency_control & FREQUENCY_X16 != 0 {
+            4
+        } else {
+            0
+        };
+        self.pulse1.clock(shift);
+        self.pulse2.clock(shift);
+        self.sawtooth.clock(shift);
+    }
+
+    fn level(&self, channel: AudioChannel) -> f32 {
+        match channel {
+            AudioChannel::Vrc6Pulse1 => self.pulse1.output() as f32 / 15.0,
+            AudioChannel::Vrc6Pulse2 => self.pulse2.output() as f32 / 15.0,
+            AudioChannel::Vrc6Sawtooth => self.sawtooth.output() as f32 / 31.0,
+            _ => 0.0,
+        }
+    }
+
+    fn output(&self, gains: &[f32; NUM_CHANNELS]) -> f32 {
+        let pulse1 = self.pulse1.output() as f32 * gains[AudioChannel::Vrc6Pulse1.index()];
+        let pulse2 = self.pulse2.output() as f32 * gains[AudioChannel::Vrc6Pulse2.index()];
+        let sawtooth = self.sawtooth.output() as f32 * gains[AudioChannel::Vrc6Sawtooth.index()];
+        (pulse1 + pulse2 + sawtooth) * LEVEL
+    }
+
+    fn frequency(&self, channel: AudioChannel, cpu_clock_rate: f64) -> Option<f32> {
+        let (timer, steps, is_audible) = match channel {
+            AudioChannel::Vrc6Pulse1 => (self.pulse1.timer, 16.0, self.pulse1.volume > 0 && !self.pulse1.is_digitized),
+            AudioChannel::Vrc6Pulse2 => (self.pulse2.timer, 16.0, self.pulse2.volume > 0 && !self.pulse2.is_digitized),
+            AudioChannel::Vrc6Sawtooth => (self.sawtooth.timer, 14.0, self.sawtooth.rate > 0),
+            _ => return None,
+        };
+        (timer.is_enabled && is_audible).then(|| timer.frequency(cpu_clock_rate, steps))
+    }
+}
@@ -0,0 +1,406 @@
+use std::f32::consts::TAU;
+
+use super::{AudioChannel, ExpansionChip, NUM_CHANNELS};
+
+/// The chip runs from its own 3.58 MHz crystal and produces a sample every 72 of its clocks, which is every 36 CPU
+/// cycles
+const SAMPLE_RATE: f32 = 3_579_545.0 / 72.0;
+const CYCLES_PER_SAMPLE: u32 = 36;
+const NUM_FM_CHANNELS: usize = 6;
+/// Peak output of a channel at full volume. Its peak-to-peak swing matches a full-volume APU pulse.
+const LEVEL: f32 = 0.075;
+
+/// Built-in instruments 1-15, as read from the die. Instrument 0 is defined by registers $00-$07.
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27], // buzzy bell
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12], // guitar
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12], // wurly
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27], // flute
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28], // clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4], // synth
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07], // trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17], // organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // bells
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02], // vibes
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12], // vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // tutti
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02], // fretless
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6], // synth bass
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06], // sweep
+];
+
+const MULTIPLIERS: [f32; 16] = [0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0];
+/// Key scale attenuation in dB at block 7 for the top four bits of the F-number, at 3 dB per octave
+const KEY_SCALE_LEVELS: [f32; 16] = [
+    0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625, 18.0, 18.75, 19.125, 19.5, 19.875, 20.25, 20.625, 21.0,
+];
+/// Key scale rates of 0, 1.5, 3 and 6 dB per octave, relative to the table
+const KEY_SCALE_FACTORS: [f32; 4] = [0.0, 0.5, 1.0, 2.0];
+/// Attenuation at which an operator is silent
+const MAX_ATTENUATION: f32 = 96.0;
+/// Seconds for an attack to complete, and for a decay to cover the full range, at an effective rate of 4. Each
+/// further 4 steps of rate halves them.
+const ATTACK_TIME: f32 = 2.826;
+const DECAY_TIME: f32 = 39.28;
+/// Release rates used when a note is keyed off, depending on the channel's sustain flag and the patch
+const SUSTAIN_RELEASE_RATE: u8 = 5;
+const PERCUSSIVE_RELEASE_RATE: u8 = 7;
+const TREMOLO_RATE: f32 = 3.7;
+const TREMOLO_DEPTH: f32 = 4.8;
+const VIBRATO_RATE: f32 = 6.4;
+/// Vibrato bends the pitch up and down by 7 cents
+const VIBRATO_DEPTH: f32 = 0.004;
+
+/// CPU addresses for selecting a register and writing to it
+const REGISTER_SELECT: u16 = 0x9010;
+const REGISTER_WRITE: u16 = 0x9030;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct OperatorPatch {
+    is_tremolo_enabled: bool,
+    is_vibrato_enabled: bool,
+    /// hold the sustain level until key off, rather than decaying through it like a percussive sound
+    is_sustained: bool,
+    is_key_scale_rate_enabled: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+    /// output silence for the negative half of the sine wave
+    is_rectified: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Patch {
+    modulator: OperatorPatch,
+    carrier: OperatorPatch,
+    modulator_total_level: u8,
+    feedback: u8,
+}
+
+impl Patch {
+    fn from_bytes(bytes: &[u8; 8]) -> Self {
+        let operator = |flags: u8, key_scale_level: u8, rates: u8, levels: u8, is_rectified: bool| OperatorPatch {
+            is_tremolo_enabled: flags & 0x80 != 0,
+            is_vibrato_enabled: flags & 0x40 != 0,
+            is_sustained: flags & 0x20 != 0,
+            is_key_scale_rate_enabled: flags & 0x10 != 0,
+            multiplier: flags & 0x0f,
+            key_scale_level,
+            attack_rate: rates >> 4,
+            decay_rate: rates & 0x0f,
+            sustain_level: levels >> 4,
+            release_rate: levels & 0x0f,
+            is_rectified,
+        };
+
+        Self {
+            modulator: operator(bytes[0], bytes[2] >> 6, bytes[4], bytes[6], bytes[3] & 0x08 != 0),
+            carrier: operator(bytes[1], bytes[3] >> 6, bytes[5], bytes[7], bytes[3] & 0x10 != 0),
+            modulator_total_level: bytes[2] & 0x3f,
+            feedback: bytes[3] & 0x07,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    #[default]
+    Off,
+}
+
+/// Seconds for a stage to complete at a 4-bit rate adjusted by the key scale rate. Zero rates never complete.
+fn stage_time(base_time: f32, rate: u8, key_scale_rate: u8) -> Option<f32> {
+    if rate == 0 {
+        return None;
+    }
+    let effective_rate = (rate * 4 + key_scale_rate).min(63) as f32;
+    Some(base_time / 2f32.powf((effective_rate - 4.0) / 4.0))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Operator {
+    /// position in the wave, in cycles
+    phase: f32,
+    state: EnvelopeState,
+    /// envelope attenuation in dB
+    attenuation: f32,
+    /// last two outputs, for the modulator's feedback
+    outputs: [f32; 2],
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self {
+            phase: 0.0,
+            state: EnvelopeState::Off,
+            attenuation: MAX_ATTENUATION,
+            outputs: [0.0; 2],
+        }
+    }
+}
+
+impl Operator {
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.state != EnvelopeState::Off {
+            self.state = EnvelopeState::Release;
+        }
+    }
+
+    /// Advance the envelope by one sample
+    fn clock_envelope(&mut self, patch: &OperatorPatch, key_scale_rate: u8, release_rate: u8) {
+        let key_scale_rate = if patch.is_key_scale_rate_enabled { key_scale_rate } else { key_scale_rate >> 2 };
+        let decay = |attenuation: &mut f32, rate: u8| {
+            if let Some(time) = stage_time(DECAY_TIME, rate, key_scale_rate) {
+                *attenuation += MAX_ATTENUATION / (time * SAMPLE_RATE);
+            }
+        };
+
+        match self.state {
+            EnvelopeState::Attack => match stage_time(ATTACK_TIME, patch.attack_rate, key_scale_rate) {
+                // the attack curves exponentially in dB, from silence down to 0.1 dB over the attack time
+                Some(time) if time > 1.0 / SAMPLE_RATE && patch.attack_rate < 15 => {
+                    self.attenuation *= (-(MAX_ATTENUATION * 10.0).ln() / (time * SAMPLE_RATE)).exp();
+                    if self.attenuation < 0.1 {
+                        self.attenuation = 0.0;
+                        self.state = EnvelopeState::Decay;
+                    }
+                }
+                Some(_) => {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+                None => (),
+            },
+            EnvelopeState::Decay => {
+                decay(&mut self.attenuation, patch.decay_rate);
+                let sustain_level = patch.sustain_level as f32 * 3.0;
+                if self.attenuation >= sustain_level {
+                    self.attenuation = sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                if !patch.is_sustained {
+                    decay(&mut self.attenuation, patch.release_rate);
+                }
+            }
+            EnvelopeState::Release => decay(&mut self.attenuation, release_rate),
+            EnvelopeState::Off => (),
+        }
+
+        if self.attenuation >= MAX_ATTENUATION && self.state != EnvelopeState::Attack {
+            self.attenuation = MAX_ATTENUATION;
+            self.state = EnvelopeState::Off;
+        }
+    }
+
+    /// Advance the phase by one sample and produce the next output, from -1 to 1
+    fn output(&mut self, patch: &OperatorPatch, increment: f32, modulation: f32, attenuation: f32) -> f32 {
+        self.phase = (self.phase + increment).fract();
+        let total_attenuation = self.attenuation + attenuation;
+        let output = if self.state == EnvelopeState::Off || total_attenuation >= MAX_ATTENUATION {
+            0.0
+        } else {
+            let wave = (TAU * (self.phase + modulation)).sin();
+            let wave = if patch.is_rectified { wave.max(0.0) } else { wave };
+            wave * 10f32.powf(-total_attenuation / 20.0)
+        };
+        self.outputs = [output, self.outputs[0]];
+        output
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Channel {
+    f_number: u16,
+    block: u8,
+    is_key_on: bool,
+    /// use a slow release when keyed off
+    is_sustain_on: bool,
+    instrument: u8,
+    /// attenuation of the carrier in 3 dB steps
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    output: f32,
+}
+
+impl Channel {
+    fn set_key_on(&mut self, is_key_on: bool) {
+        if is_key_on && !self.is_key_on {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !is_key_on && self.is_key_on {
+            self.modulator.key_off();
+            self.carrier.key_off();
+        }
+        self.is_key_on = is_key_on;
+    }
+
+    /// Phase increment per sample of an operator with multiplier 1, in cycles
+    fn base_increment(&self) -> f32 {
+        self.f_number as f32 * 2f32.powi(self.block as i32 - 1) / (1 << 18) as f32
+    }
+
+    /// Attenuation in dB from the key scale level, which makes higher notes quieter
+    fn key_scale_attenuation(&self, key_scale_level: u8) -> f32 {
+        let attenuation = KEY_SCALE_LEVELS[(self.f_number >> 5) as usize] - 6.0 * (7 - self.block) as f32;
+        attenuation.max(0.0) * KEY_SCALE_FACTORS[key_scale_level as usize]
+    }
+
+    fn clock(&mut self, patch: &Patch, tremolo: f32, vibrato: f32) {
+        let key_scale_rate = self.block * 2 + (self.f_number >> 8) as u8;
+        let release_rate = |operator: &OperatorPatch| {
+            if self.is_sustain_on {
+                SUSTAIN_RELEASE_RATE
+            } else if operator.is_sustained {
+                operator.release_rate
+            } else {
+                PERCUSSIVE_RELEASE_RATE
+            }
+        };
+        let modulator_release_rate = release_rate(&patch.modulator);
+        let carrier_release_rate = release_rate(&patch.carrier);
+        self.modulator.clock_envelope(&patch.modulator, key_scale_rate, modulator_release_rate);
+        self.carrier.clock_envelope(&patch.carrier, key_scale_rate, carrier_release_rate);
+
+        let increment = |operator: &OperatorPatch| {
+            let vibrato = if operator.is_vibrato_enabled { 1.0 + vibrato * VIBRATO_DEPTH } else { 1.0 };
+            self.base_increment() * MULTIPLIERS[operator.multiplier as usize] * vibrato
+        };
+        let attenuation = |operator: &OperatorPatch, level: f32| {
+            let tremolo = if operator.is_tremolo_enabled { tremolo * TREMOLO_DEPTH } else { 0.0 };
+            level + self.key_scale_attenuation(operator.key_scale_level) + tremolo
+        };
+
+        let modulator_increment = increment(&patch.modulator);
+        let modulator_attenuation = attenuation(&patch.modulator, patch.modulator_total_level as f32 * 0.75);
+        let carrier_increment = increment(&patch.carrier);
+        let carrier_attenuation = attenuation(&patch.carrier, self.volume as f32 * 3.0);
+
+        // feedback shifts the modulator by up to a full cycle, and the modulator shifts the carrier by up to two
+        let feedback = if patch.feedback == 0 {
+            0.0
+        } else {
+            (self.modulator.outputs[0] + self.modulator.outputs[1]) * 2f32.powi(patch.feedback as i32 - 8)
+        };
+        let modulation =
+            self.modulator.output(&patch.modulator, modulator_increment, feedback, modulator_attenuation) * 2.0;
+        self.output = self.carrier.output(&patch.carrier, carrier_increment, modulation, carrier_attenuation);
+    }
+
+    fn frequency(&self, patch: &Patch) -> Option<f32> {
+        (self.carrier.state != EnvelopeState::Off)
+            .then(|| self.base_increment() * MULTIPLIERS[patch.carrier.multiplier as usize] * SAMPLE_RATE)
+    }
+}
+
+/// Konami's VRC7, whose sound is a cut-down Yamaha OPLL: six 2-operator FM channels with 15 built-in instruments and
+/// one that can be programmed
+#[derive(Default)]
+pub struct Vrc7 {
+    selected_register: u8,
+    custom_patch: [u8; 8],
+    channels: [Channel; NUM_FM_CHANNELS],
+    /// CPU cycles until the next sample
+    cycles: u32,
+    /// position of the tremolo and vibrato oscillators, in cycles
+    tremolo_phase: f32,
+    vibrato_phase: f32,
+}
+
+impl Vrc7 {
+    fn patch(&self, instrument: u8) -> Patch {
+        match instrument {
+            0 => Patch::from_bytes(&self.custom_patch),
+            _ => Patch::from_bytes(&PATCHES[instrument as usize - 1]),
+        }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        let index = (register & 0x0f) as usize;
+        match register {
+            0x00..=0x07 => self.custom_patch[index] = value,
+            0x10..=0x15 => self.channels[index].f_number = (self.channels[index].f_number & 0x100) | value as u16,
+            0x20..=0x25 => {
+                let channel = &mut self.channels[index];
+                channel.f_number = (channel.f_number & 0xff) | ((value as u16 & 1) << 8);
+                channel.block = (value >> 1) & 7;
+                channel.is_sustain_on = value & 0x20 != 0;
+                channel.set_key_on(value & 0x10 != 0);
+            }
+            0x30..=0x35 => {
+                self.channels[index].instrument = value >> 4;
+                self.channels[index].volume = value & 0x0f;
+            }
+            _ => (),
+        }
+    }
+
+    fn channel_index(channel: AudioChannel) -> Option<usize> {
+        AudioChannel::VRC7.iter().position(|&c| c == channel)
+    }
+}
+
+impl ExpansionChip for Vrc7 {
+    fn channels(&self) -> &'static [AudioChannel] {
+        &AudioChannel::VRC7
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            REGISTER_SELECT => self.selected_register = value,
+            REGISTER_WRITE => self.write_register(self.selected_register, value),
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_SAMPLE {
+            return;
+        }
+        self.cycles = 0;
+
+        self.tremolo_phase = (self.tremolo_phase + TREMOLO_RATE / SAMPLE_RATE).fract();
+        self.vibrato_phase = (self.vibrato_phase + VIBRATO_RATE / SAMPLE_RATE).fract();
+        // both are triangle waves: tremolo from 0 to 1, and vibrato from -1 to 1
+        let tremolo = 1.0 - 2.0 * (self.tremolo_phase - 0.5).abs();
+        let vibrato = 1.0 - 4.0 * (self.vibrato_phase - 0.5).abs();
+
+        for i in 0..NUM_FM_CHANNELS {
+            let patch = self.patch(self.channels[i].instrument);
+            self.channels[i].clock(&patch, tremolo, vibrato);
+        }
+    }
+
+    fn level(&self, channel: AudioChannel) -> f32 {
+        Self::channel_index(channel).map_or(0.0, |i| (self.channels[i].output + 1.0) / 2.0)
+    }
+
+    fn output(&self, gains: &[f32; NUM_CHANNELS]) -> f32 {
+        let output: f32 = AudioChannel::VRC7
+            .iter()
+            .zip(&self.channels)
+            .map(|(channel, state)| state.output * gains[channel.index()])
+            .sum();
+        output * LEVEL
+    }
+
+    fn frequency(&self, channel: AudioChannel, _cpu_clock_rate: f64) -> Option<f32> {
+        let channel = &self.channels[Self::channel_index(channel)?];
+        channel.frequency(&self.patch(channel.instrument))
+    }
+}
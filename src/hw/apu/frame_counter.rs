@@ -0,0 +1,82 @@
+/// CPU cycles at which each step of the sequence happens, counted from the last reset
+const FOUR_STEP_SEQUENCE: [u16; 4] = [7457, 14913, 22371, 29829];
+const FIVE_STEP_SEQUENCE: [u16; 5] = [7457, 14913, 22371, 29829, 37281];
+/// The 4-step sequence raises its IRQ over three cycles around the last step
+const FOUR_STEP_IRQ_CYCLES: [u16; 3] = [29828, 29829, 29830];
+const FOUR_STEP_PERIOD: u16 = 29830;
+const FIVE_STEP_PERIOD: u16 = 37282;
+
+/// Which of the units driven by the frame counter need clocking this cycle
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameSignals {
+    /// envelopes and the triangle's linear counter
+    pub quarter_frame: bool,
+    /// length counters and sweep units
+    pub half_frame: bool,
+}
+
+/// The frame counter ($4017), which clocks the channels' envelopes, sweeps and length counters at around 240 Hz
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCounter {
+    is_five_step: bool,
+    is_irq_inhibited: bool,
+    pub(super) is_irq_pending: bool,
+    cycle: u16,
+    /// a write takes effect after a delay of 3 or 4 CPU cycles
+    pending_write: Option<(u8, u8)>,
+}
+
+impl FrameCounter {
+    /// Write to $4017. `is_odd_cycle` is whether the write happens on the second half of an APU cycle.
+    pub fn write(&mut self, value: u8, is_odd_cycle: bool) {
+        self.is_irq_inhibited = value & 0x40 != 0;
+        if self.is_irq_inhibited {
+            self.is_irq_pending = false;
+        }
+        self.pending_write = Some((value, if is_odd_cycle { 4 } else { 3 }));
+    }
+
+    /// Advance by one CPU cycle
+    pub fn clock(&mut self) -> FrameSignals {
+        self.cycle += 1;
+        let mut signals = FrameSignals::default();
+
+        if let Some((value, delay)) = self.pending_write {
+            if delay > 1 {
+                self.pending_write = Some((value, delay - 1));
+            } else {
+                self.pending_write = None;
+                self.is_five_step = value & 0x80 != 0;
+                self.cycle = 0;
+                // switching to the 5-step sequence clocks everything immediately
+                if self.is_five_step {
+                    return FrameSignals { quarter_frame: true, half_frame: true };
+                }
+                return signals;
+            }
+        }
+
+        if self.is_five_step {
+            if let Some(step) = FIVE_STEP_SEQUENCE.iter().position(|&c| c == self.cycle) {
+                signals.quarter_frame = step != 3;
+                signals.half_frame = step == 1 || step == 4;
+            }
+            if self.cycle == FIVE_STEP_PERIOD {
+                self.cycle = 0;
+            }
+        } else {
+            if let Some(step) = FOUR_STEP_SEQUENCE.iter().position(|&c| c == self.cycle) {
+                signals.quarter_frame = true;
+                signals.half_frame = step == 1 || step == 3;
+            }
+            if FOUR_STEP_IRQ_CYCLES.contains(&self.cycle) && !self.is_irq_inhibited {
+                self.is_irq_pending = true;
+            }
+            if self.cycle == FOUR_STEP_PERIOD {
+                self.cycle = 0;
+            }
+        }
+
+        signals
+    }
+}
@@ -0,0 +1,44 @@
+const LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Silences a channel after a set number of half frames
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LengthCounter {
+    is_enabled: bool,
+    is_halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// Enable or disable the channel through $4015. Disabling it silences it immediately.
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+        if !is_enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halted(&mut self, is_halted: bool) {
+        self.is_halted = is_halted;
+    }
+
+    /// Load the counter from the top five bits of the channel's last register
+    pub fn load(&mut self, value: u8) {
+        if self.is_enabled {
+            self.counter = LENGTHS[(value >> 3) as usize];
+        }
+    }
+
+    /// Clock on each half frame
+    pub fn clock(&mut self) {
+        if !self.is_halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub const fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
@@ -0,0 +1,70 @@
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+
+/// Timer periods in CPU cycles (NTSC)
+const PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+
+/// The pseudo-random noise channel ($400C-$400F)
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    /// short mode, which taps bit 6 instead of bit 1 for a 93-step sequence
+    is_short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift_register: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            is_short_mode: false,
+            period: PERIODS[0],
+            timer: 0,
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length.set_halted(value & 0x20 != 0);
+                self.envelope.write(value);
+            }
+            1 => (),
+            2 => {
+                self.is_short_mode = value & 0x80 != 0;
+                self.period = PERIODS[(value & 0x0f) as usize];
+            }
+            _ => {
+                self.length.load(value);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clock the timer once per CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap = if self.is_short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub const fn output(&self) -> u8 {
+        if !self.length.is_active() || self.shift_register & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Sweep unit, which periodically bends the pulse channel's pitch
+#[derive(Debug, Default, Clone, Copy)]
+struct Sweep {
+    is_enabled: bool,
+    period: u8,
+    is_negated: bool,
+    shift: u8,
+    is_reload_pending: bool,
+    divider: u8,
+}
+
+/// One of the two square wave channels ($4000-$4003 and $4004-$4007)
+#[derive(Debug, Clone, Copy)]
+pub struct Pulse {
+    /// pulse 1 negates its sweep with ones' complement, so it sweeps down one step further than pulse 2
+    is_first: bool,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    sweep: Sweep,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(is_first: bool) -> Self {
+        Self {
+            is_first,
+            duty: 0,
+            step: 0,
+            period: 0,
+            timer: 0,
+            sweep: Sweep::default(),
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length.set_halted(value & 0x20 != 0);
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep.is_enabled = value & 0x80 != 0;
+                self.sweep.period = (value >> 4) & 7;
+                self.sweep.is_negated = value & 0x08 != 0;
+                self.sweep.shift = value & 7;
+                self.sweep.is_reload_pending = true;
+            }
+            2 => self.period = (self.period & 0x700) | value as u16,
+            _ => {
+                self.period = (self.period & 0xff) | ((value as u16 & 7) << 8);
+                self.length.load(value);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clock the timer, which runs at half the CPU's rate
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.period >> self.sweep.shift;
+        if self.sweep.is_negated {
+            self.period.saturating_sub(change + self.is_first as u16)
+        } else {
+            self.period + change
+        }
+    }
+
+    /// Whether the sweep unit is silencing the channel, which happens whether or not the sweep is enabled
+    fn is_muted(&self) -> bool {
+        self.period < 8 || self.target_period() > 0x7ff
+    }
+
+    /// Clock the sweep unit on each half frame
+    pub fn clock_sweep(&mut self) {
+        if self.sweep.divider == 0 && self.sweep.is_enabled && self.sweep.shift > 0 && !self.is_muted() {
+            self.period = self.target_period();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.is_reload_pending {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.is_reload_pending = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// Pitch in Hz, if the channel is currently audible
+    pub fn frequency(&self, cpu_clock_rate: f64) -> Option<f32> {
+        let is_audible = self.length.is_active() && !self.is_muted() && self.envelope.output() > 0;
+        is_audible.then(|| (cpu_clock_rate / (16.0 * (self.period as f64 + 1.0))) as f32)
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.is_muted() || DUTY_CYCLES[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
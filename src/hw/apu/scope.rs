@@ -0,0 +1,45 @@
+use super::channel::{AudioChannel, NUM_CHANNELS};
+
+/// Number of points kept for each channel
+pub const SCOPE_LENGTH: usize = 1024;
+/// CPU cycles between points, giving a view of about 18 ms
+const DECIMATION: u32 = 32;
+
+/// Recent output of each channel, for drawing an oscilloscope
+#[derive(Debug, Clone)]
+pub struct ChannelScope {
+    points: Box<[[f32; SCOPE_LENGTH]; NUM_CHANNELS]>,
+    /// index of the oldest point
+    position: usize,
+    cycles: u32,
+}
+
+impl ChannelScope {
+    pub fn new() -> Self {
+        Self {
+            points: Box::new([[0.0; SCOPE_LENGTH]; NUM_CHANNELS]),
+            position: 0,
+            cycles: 0,
+        }
+    }
+
+    /// Record the channels' outputs, from 0 to 1, for one CPU cycle
+    pub(super) fn record(&mut self, outputs: [f32; NUM_CHANNELS]) {
+        self.cycles += 1;
+        if self.cycles < DECIMATION {
+            return;
+        }
+        self.cycles = 0;
+
+        for (points, output) in self.points.iter_mut().zip(outputs) {
+            points[self.position] = output;
+        }
+        self.position = (self.position + 1) % SCOPE_LENGTH;
+    }
+
+    /// A channel's points, oldest first
+    pub fn points(&self, channel: AudioChannel) -> impl Iterator<Item = f32> + '_ {
+        let points = &self.points[channel.index()];
+        points[self.position..].iter().chain(&points[..self.position]).copied()
+    }
+}
@@ -0,0 +1,72 @@
+use super::length::LengthCounter;
+
+/// The triangle wave channel ($4008-$400B)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Triangle {
+    /// also halts the length counter
+    is_control_set: bool,
+    linear_reload: u8,
+    linear_counter: u8,
+    is_linear_reload_pending: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    pub(super) length: LengthCounter,
+}
+
+impl Triangle {
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.is_control_set = value & 0x80 != 0;
+                self.length.set_halted(self.is_control_set);
+                self.linear_reload = value & 0x7f;
+            }
+            1 => (),
+            2 => self.period = (self.period & 0x700) | value as u16,
+            _ => {
+                self.period = (self.period & 0xff) | ((value as u16 & 7) << 8);
+                self.length.load(value);
+                self.is_linear_reload_pending = true;
+            }
+        }
+    }
+
+    /// Clock the timer, which unlike the other channels runs at the CPU's rate
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            // periods below 2 produce ultrasonic frequencies which the output stage filters down to a constant level,
+            // so hold the current step rather than aliasing
+            if self.linear_counter > 0 && self.length.is_active() && self.period >= 2 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clock the linear counter on each quarter frame
+    pub fn clock_linear_counter(&mut self) {
+        if self.is_linear_reload_pending {
+            self.linear_counter = self.linear_reload;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.is_control_set {
+            self.is_linear_reload_pending = false;
+        }
+    }
+
+    /// Pitch in Hz, if the channel is currently audible
+    pub fn frequency(&self, cpu_clock_rate: f64) -> Option<f32> {
+        let is_audible = self.linear_counter > 0 && self.length.is_active() && self.period >= 2;
+        is_audible.then(|| (cpu_clock_rate / (32.0 * (self.period as f64 + 1.0))) as f32)
+    }
+
+    /// The triangle keeps outputting its current step when silenced, so it never drops out abruptly
+    pub const fn output(&self) -> u8 {
+        if self.step < 16 { 15 - self.step } else { self.step - 16 }
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Result};
+
+use crate::rom::Cartridge;
+use super::component::Component;
+use super::ppu::{Ppu, PpuEventKind};
+
+/// The cartridge slot, shared between every component that has the cartridge on its bus
+pub type CartridgeSlot = Arc<RwLock<Option<Cartridge>>>;
+
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, value: u8);
+}
+
+/// Clocks the cartridge once per CPU cycle, for boards with timers, and watches its /IRQ output
+pub struct CartridgeClock {
+    cartridge: CartridgeSlot,
+    /// for logging mapper IRQs in the event viewer
+    ppu: Arc<RwLock<Ppu>>,
+    was_irq: bool,
+}
+
+impl CartridgeClock {
+    pub const fn new(cartridge: CartridgeSlot, ppu: Arc<RwLock<Ppu>>) -> Self {
+        Self {
+            cartridge,
+            ppu,
+            was_irq: false,
+        }
+    }
+}
+
+impl Component for CartridgeClock {
+    fn step(&mut self) -> Result<u64> {
+        let is_irq = match *self.cartridge.write().map_err(|_| anyhow!("RwLock poisoned"))? {
+            Some(ref mut cartridge) => {
+                cartridge.cpu_clock();
+                cartridge.irq()
+            }
+            None => false,
+        };
+        if is_irq && !self.was_irq {
+            self.ppu.write().map_err(|_| anyhow!("RwLock poisoned"))?.record_event(PpuEventKind::MapperIrq);
+        }
+        self.was_irq = is_irq;
+        Ok(1)
+    }
+}
@@ -45,7 +45,7 @@ impl Clock {
     }
     
     pub fn run_to(&mut self, time: Duration) -> Result<u64> {
-        self.run_for(time - self.elapsed)
+        self.run_for(time.saturating_sub(self.elapsed))
     }
     
     pub fn tick(&mut self, count: u64) -> Result<()> {
@@ -53,18 +53,14 @@ impl Clock {
 
         for _ in 0..count {
             for divider in &mut self.dividers {
-                divider.ticks_remaining -= 1;
                 if divider.ticks_remaining == 0 {
                     let cycles_run = divider.component.write().map_err(|_| anyhow!("RwLock poisoned"))?.step()?;
                     divider.ticks_remaining = divider.divider * cycles_run;
                 }
+                divider.ticks_remaining -= 1;
             }
         }
 
         Ok(())
     }
-    
-    pub const fn elapsed(&self) -> Duration {
-        self.elapsed
-    }
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+use std::sync::{Arc, RwLock};
+
+/// The buttons on a standard controller, in the order the controller reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    const fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A standard controller. While the strobe bit of $4016 is set, the controller keeps loading the state of its buttons
+/// into a shift register, which the CPU then reads out a bit at a time.
+#[derive(Debug, Default)]
+pub struct Controller {
+    buttons: u8,
+    shift: u8,
+    is_strobe_set: bool,
+}
+
+impl Controller {
+    pub fn set_pressed(&mut self, button: Button, is_pressed: bool) {
+        if is_pressed {
+            self.buttons |= button.mask();
+        } else {
+            self.buttons &= !button.mask();
+        }
+    }
+
+    pub fn write_strobe(&mut self, value: u8) {
+        self.is_strobe_set = value & 1 != 0;
+        if self.is_strobe_set {
+            self.shift = self.buttons;
+        }
+    }
+
+    /// Read the next button. Once all eight have been read, official controllers return 1.
+    pub fn read(&mut self) -> u8 {
+        if self.is_strobe_set {
+            return self.buttons & 1;
+        }
+
+        let bit = self.shift & 1;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+}
+
+/// The two controller ports, shared between the CPU bus and whatever provides input
+pub type ControllerPorts = Arc<RwLock<[Controller; 2]>>;
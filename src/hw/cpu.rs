@@ -1,4 +1,13 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use super::apu::Apu;
+use super::bus::{Bus, CartridgeSlot};
 use super::component::Component;
+use super::controller::ControllerPorts;
+use super::ppu::Ppu;
 
 struct Registers {
     a: u8,
@@ -35,22 +44,6 @@ macro_rules! status {
 }
 
 impl Registers {
-    const fn pcl(&self) -> u8 {
-        (self.pc & 0xff) as u8
-    }
-    
-    const fn set_pcl(&mut self, value: u8) {
-        self.pc = (self.pc & 0xff00) | value as u16;
-    }
-    
-    const fn pch(&self) -> u8 {
-        (self.pc >> 8) as u8
-    }
-    
-    const fn set_pch(&mut self, value: u8) {
-        self.pc = (self.pc & 0x00ff) | (value as u16) << 8;
-    }
-    
     const fn status(&self, bit: u8) -> bool {
         self.p & (1 << bit) != 0
     }
@@ -62,13 +55,699 @@ impl Registers {
     status!(carry, set_carry, 0);
     status!(zero, set_zero, 1);
     status!(interrupt_disable, set_interrupt_disable, 2);
-    status!(decimal_mode, set_decimal_mode, 3);
-    status!(break_command, set_break_command, 4);
+
+    /// The 2A03 has no decimal mode, so the flag can be set and cleared but never affects arithmetic
+    const fn set_decimal_mode(&mut self, value: bool) {
+        self.set_status(3, value);
+    }
+
     status!(overflow, set_overflow, 6);
     status!(negative, set_negative, 7);
 }
 
+/// Base cycle counts of each opcode, not counting page crossings or taken branches
+const CYCLES: [u8; 256] = [
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+];
+
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+/// Cycles an interrupt takes to push the PC and status and jump through the vector
+const INTERRUPT_CYCLES: u64 = 7;
+/// Cycles the CPU is halted for while OAM DMA copies a page to the PPU, not counting the alignment cycle
+const OAM_DMA_CYCLES: u64 = 513;
+/// Bits 4 and 5 of the status register only exist when it's pushed to the stack
+const STATUS_BREAK: u8 = 0x10;
+const STATUS_UNUSED: u8 = 0x20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl Mode {
+    /// Addressing mode of an opcode, following the regular layout of the 6502's opcode table
+    const fn of(opcode: u8) -> Self {
+        // STX, LDX and their unofficial neighbours in rows $8x-$Bx index by Y instead of X
+        let is_y_indexed = opcode & 0xc2 == 0x82;
+        match (opcode & 3, (opcode >> 2) & 7) {
+            (0, 0) if opcode == 0x20 => Self::Absolute,
+            (0 | 2, 0) => if opcode >= 0x80 { Self::Immediate } else { Self::Implied },
+            (0 | 2, 2) | (0 | 2, 6) | (2, 4) => Self::Implied,
+            (0, 3) if opcode == 0x6c => Self::Indirect,
+            (0, 4) => Self::Relative,
+            (_, 0) => Self::IndirectX,
+            (_, 1) => Self::ZeroPage,
+            (_, 2) => Self::Immediate,
+            (_, 3) => Self::Absolute,
+            (_, 4) => Self::IndirectY,
+            (_, 5) if is_y_indexed => Self::ZeroPageY,
+            (_, 5) => Self::ZeroPageX,
+            (_, 6) => Self::AbsoluteY,
+            _ if is_y_indexed => Self::AbsoluteY,
+            _ => Self::AbsoluteX,
+        }
+    }
+}
+
+/// The CPU's view of the console, apart from its own RAM
+pub struct CpuBus {
+    cartridge: CartridgeSlot,
+    ppu: Arc<RwLock<Ppu>>,
+    apu: Arc<RwLock<Apu>>,
+    controllers: ControllerPorts,
+    /// page written to $4014, until the CPU copies it to OAM
+    oam_dma_page: Option<u8>,
+}
+
+impl CpuBus {
+    pub const fn new(
+        cartridge: CartridgeSlot,
+        ppu: Arc<RwLock<Ppu>>,
+        apu: Arc<RwLock<Apu>>,
+        controllers: ControllerPorts,
+    ) -> Self {
+        Self {
+            cartridge,
+            ppu,
+            apu,
+            controllers,
+            oam_dma_page: None,
+        }
+    }
+
+    /// State of the PPU's /NMI output
+    fn nmi(&self) -> bool {
+        self.ppu.read().is_ok_and(|ppu| ppu.nmi())
+    }
+
+    /// State of the /IRQ line, which the APU and the cartridge share
+    fn irq(&self) -> bool {
+        let is_cartridge_irq = self.cartridge.read().is_ok_and(|cartridge| cartridge.as_ref().is_some_and(|c| c.irq()));
+        is_cartridge_irq || self.apu.read().is_ok_and(|apu| apu.irq())
+    }
+
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma_page.take()
+    }
+
+    /// Cycles the CPU has to be halted for while the DMC fetches samples
+    fn take_dmc_dma_cycles(&mut self) -> u64 {
+        self.apu.write().map_or(0, |mut apu| apu.take_dma_stall_cycles())
+    }
+}
+
+impl Bus for CpuBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // the PPU's eight registers are mirrored across $2000-$3FFF
+            0x2000..=0x3fff => self.ppu.write().map_or(0, |mut ppu| ppu.read_register(addr)),
+            0x4015 => self.apu.write().map_or(0, |mut apu| apu.read_status()),
+            // the controllers only drive the low bits, and the rest is usually left with the $40 of the address
+            0x4016 | 0x4017 => {
+                let bit = self.controllers.write().map_or(0, |mut ports| ports[addr as usize - 0x4016].read());
+                bit | 0x40
+            }
+            0x4020.. => {
+                if let Some(value) = self.apu.write().ok().and_then(|mut apu| apu.read_expansion(addr)) {
+                    return value;
+                }
+                match self.cartridge.read() {
+                    Ok(cartridge) => cartridge.as_ref().map_or(0, |c| c.cpu_read(addr)),
+                    Err(_) => 0,
+                }
+            }
+            // open bus isn't emulated
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x2000..=0x3fff => {
+                if let Ok(mut ppu) = self.ppu.write() {
+                    ppu.write_register(addr, value);
+                }
+            }
+            0x4014 => self.oam_dma_page = Some(value),
+            0x4016 => {
+                if let Ok(mut ports) = self.controllers.write() {
+                    for controller in ports.iter_mut() {
+                        controller.write_strobe(value);
+                    }
+                }
+            }
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                if let Ok(mut apu) = self.apu.write() {
+                    apu.write_register(addr, value);
+                }
+            }
+            0x4020.. => {
+                if let Ok(Some(cartridge)) = self.cartridge.write().as_deref_mut() {
+                    cartridge.cpu_write(addr, value);
+                }
+                if let Ok(mut apu) = self.apu.write() {
+                    apu.write_expansion(addr, value);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The 2A03's 6502 core, without decimal mode. Instructions execute all at once rather than cycle by cycle.
 pub struct Cpu {
     regs: Registers,
     ram: [u8; 0x800],
-}
\ No newline at end of file
+    bus: CpuBus,
+    /// whether a KIL opcode has locked up the CPU until it's reset
+    is_jammed: bool,
+    /// whether the CPU runs the cartridge's program by itself. NSF tunes are driven by the player instead.
+    is_running: bool,
+    /// NMI is edge-triggered, so the line's state when it was last polled
+    was_nmi: bool,
+    /// CPU cycles since power-on, for aligning DMA
+    cycle: u64,
+}
+
+impl Cpu {
+    pub fn new(bus: CpuBus) -> Self {
+        Self {
+            regs: Registers::default(),
+            ram: [0; 0x800],
+            bus,
+            is_jammed: false,
+            is_running: false,
+            was_nmi: false,
+            cycle: 0,
+        }
+    }
+
+    /// Start running the cartridge's program from its reset vector
+    pub fn power_on(&mut self) {
+        self.reset();
+        // the APU comes up silent
+        self.write(0x4015, 0x00);
+        self.regs.pc = self.read_word(RESET_VECTOR);
+        self.was_nmi = false;
+        self.is_running = true;
+    }
+
+    /// Stop running by itself, so that the NSF player can drive the CPU
+    pub const fn stop(&mut self) {
+        self.is_running = false;
+    }
+
+    pub const fn pc(&self) -> u16 {
+        self.regs.pc
+    }
+
+    /// Clear RAM and the registers
+    pub fn reset(&mut self) {
+        self.regs = Registers::default();
+        self.ram = [0; 0x800];
+        self.is_jammed = false;
+    }
+
+    /// Start a subroutine with the given A and X registers, as if it was called with JSR from just before
+    /// `return_addr`. It has finished once the PC reaches `return_addr`.
+    pub fn call(&mut self, addr: u16, a: u8, x: u8, return_addr: u16) {
+        self.regs.a = a;
+        self.regs.x = x;
+        self.regs.y = 0;
+        self.push_word(return_addr.wrapping_sub(1));
+        self.regs.pc = addr;
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            self.ram[addr as usize & 0x7ff]
+        } else {
+            self.bus.read(addr)
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        if addr < 0x2000 {
+            self.ram[addr as usize & 0x7ff] = value;
+        } else {
+            self.bus.write(addr, value);
+        }
+    }
+
+    fn read_word(&mut self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.read(addr), self.read(addr.wrapping_add(1))])
+    }
+
+    /// Read a pointer from the zero page, where the high byte wraps around to $00
+    fn read_zero_page_word(&mut self, addr: u8) -> u16 {
+        u16::from_le_bytes([self.read(addr as u16), self.read(addr.wrapping_add(1) as u16)])
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let value = self.read(self.regs.pc);
+        self.regs.pc = self.regs.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        u16::from_le_bytes([self.fetch(), self.fetch()])
+    }
+
+    fn push(&mut self, value: u8) {
+        self.write(0x100 | self.regs.s as u16, value);
+        self.regs.s = self.regs.s.wrapping_sub(1);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.regs.s = self.regs.s.wrapping_add(1);
+        self.read(0x100 | self.regs.s as u16)
+    }
+
+    fn push_word(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
+    }
+
+    fn pull_word(&mut self) -> u16 {
+        u16::from_le_bytes([self.pull(), self.pull()])
+    }
+
+    fn set_zn(&mut self, value: u8) -> u8 {
+        self.regs.set_zero(value == 0);
+        self.regs.set_negative(value & 0x80 != 0);
+        value
+    }
+
+    /// Work out an instruction's effective address. Reads from indexed addresses take an extra cycle when the
+    /// index crosses a page.
+    fn operand_addr(&mut self, mode: Mode, is_read: bool, cycles: &mut u64) -> u16 {
+        let indexed = |base: u16, index: u8, cycles: &mut u64| {
+            let addr = base.wrapping_add(index as u16);
+            if is_read && (addr ^ base) & 0xff00 != 0 {
+                *cycles += 1;
+            }
+            addr
+        };
+
+        match mode {
+            Mode::Immediate => {
+                let addr = self.regs.pc;
+                self.regs.pc = self.regs.pc.wrapping_add(1);
+                addr
+            }
+            Mode::ZeroPage => self.fetch() as u16,
+            Mode::ZeroPageX => self.fetch().wrapping_add(self.regs.x) as u16,
+            Mode::ZeroPageY => self.fetch().wrapping_add(self.regs.y) as u16,
+            Mode::Absolute => self.fetch_word(),
+            Mode::AbsoluteX => {
+                let base = self.fetch_word();
+                indexed(base, self.regs.x, cycles)
+            }
+            Mode::AbsoluteY => {
+                let base = self.fetch_word();
+                indexed(base, self.regs.y, cycles)
+            }
+            Mode::Indirect => {
+                // the pointer's high byte is read from the same page as its low byte
+                let pointer = self.fetch_word();
+                let high = (pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff);
+                u16::from_le_bytes([self.read(pointer), self.read(high)])
+            }
+            Mode::IndirectX => {
+                let pointer = self.fetch().wrapping_add(self.regs.x);
+                self.read_zero_page_word(pointer)
+            }
+            Mode::IndirectY => {
+                let pointer = self.fetch();
+                let base = self.read_zero_page_word(pointer);
+                indexed(base, self.regs.y, cycles)
+            }
+            Mode::Implied | Mode::Relative => 0,
+        }
+    }
+
+    fn read_operand(&mut self, mode: Mode, cycles: &mut u64) -> u8 {
+        let addr = self.operand_addr(mode, true, cycles);
+        self.read(addr)
+    }
+
+    /// Apply a read-modify-write operation to the accumulator or to memory, returning the result
+    fn modify(&mut self, mode: Mode, cycles: &mut u64, op: impl FnOnce(&mut Self, u8) -> u8) -> u8 {
+        if mode == Mode::Implied {
+            let result = op(self, self.regs.a);
+            self.regs.a = result;
+            result
+        } else {
+            let addr = self.operand_addr(mode, false, cycles);
+            let value = self.read(addr);
+            let result = op(self, value);
+            self.write(addr, result);
+            result
+        }
+    }
+
+    fn branch(&mut self, condition: bool, cycles: &mut u64) {
+        let offset = self.fetch() as i8;
+        if condition {
+            let target = self.regs.pc.wrapping_add(offset as u16);
+            *cycles += if (target ^ self.regs.pc) & 0xff00 != 0 { 2 } else { 1 };
+            self.regs.pc = target;
+        }
+    }
+
+    fn add(&mut self, value: u8) {
+        let sum = self.regs.a as u16 + value as u16 + self.regs.carry() as u16;
+        let result = sum as u8;
+        self.regs.set_carry(sum > 0xff);
+        self.regs.set_overflow((self.regs.a ^ result) & (value ^ result) & 0x80 != 0);
+        self.regs.a = self.set_zn(result);
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.regs.set_carry(register >= value);
+        self.set_zn(register.wrapping_sub(value));
+    }
+
+    fn shift_left(&mut self, value: u8) -> u8 {
+        self.regs.set_carry(value & 0x80 != 0);
+        self.set_zn(value << 1)
+    }
+
+    fn shift_right(&mut self, value: u8) -> u8 {
+        self.regs.set_carry(value & 1 != 0);
+        self.set_zn(value >> 1)
+    }
+
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        let carry = self.regs.carry() as u8;
+        self.regs.set_carry(value & 0x80 != 0);
+        self.set_zn((value << 1) | carry)
+    }
+
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let carry = self.regs.carry() as u8;
+        self.regs.set_carry(value & 1 != 0);
+        self.set_zn((value >> 1) | (carry << 7))
+    }
+
+    /// Push the PC and status and jump through an interrupt vector
+    fn interrupt(&mut self, vector: u16) -> u64 {
+        self.push_word(self.regs.pc);
+        self.push(self.regs.p | STATUS_UNUSED);
+        self.regs.set_interrupt_disable(true);
+        self.regs.pc = self.read_word(vector);
+        INTERRUPT_CYCLES
+    }
+
+    /// Take a pending interrupt, returning the number of cycles it took, or `None` if there isn't one. Interrupts are
+    /// only polled between instructions.
+    fn poll_interrupts(&mut self) -> Option<u64> {
+        let nmi = self.bus.nmi();
+        let is_nmi_edge = nmi && !self.was_nmi;
+        self.was_nmi = nmi;
+        if is_nmi_edge {
+            Some(self.interrupt(NMI_VECTOR))
+        } else if !self.regs.interrupt_disable() && self.bus.irq() {
+            Some(self.interrupt(IRQ_VECTOR))
+        } else {
+            None
+        }
+    }
+
+    /// Copy a page of memory to OAM through $2004 if the last instruction started a DMA, returning the number of
+    /// cycles the CPU was halted for by that and by the DMC's sample fetches
+    fn run_dma(&mut self) -> u64 {
+        let dmc_cycles = self.bus.take_dmc_dma_cycles();
+        let Some(page) = self.bus.take_oam_dma() else {
+            return dmc_cycles;
+        };
+
+        for addr in (page as u16) << 8..=(page as u16) << 8 | 0xff {
+            let value = self.read(addr);
+            self.write(0x2004, value);
+        }
+        // an extra cycle is needed to line up with a read cycle when the DMA starts on an odd cycle
+        dmc_cycles + OAM_DMA_CYCLES + self.cycle % 2
+    }
+
+    /// Execute one instruction, and any DMA it triggers, returning the number of cycles it took
+    pub fn run_instruction(&mut self) -> u64 {
+        let cycles = self.execute();
+        self.cycle += cycles;
+        let dma_cycles = self.run_dma();
+        self.cycle += dma_cycles;
+        cycles + dma_cycles
+    }
+
+    fn execute(&mut self) -> u64 {
+        if self.is_jammed {
+            return 1;
+        }
+
+        let opcode = self.fetch();
+        let mode = Mode::of(opcode);
+        let mut cycles = CYCLES[opcode as usize] as u64;
+
+        match opcode {
+            // loads and stores
+            0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.a = self.set_zn(value);
+            }
+            0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.x = self.set_zn(value);
+            }
+            0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.y = self.set_zn(value);
+            }
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
+                let addr = self.operand_addr(mode, false, &mut cycles);
+                self.write(addr, self.regs.a);
+            }
+            0x86 | 0x96 | 0x8e => {
+                let addr = self.operand_addr(mode, false, &mut cycles);
+                self.write(addr, self.regs.x);
+            }
+            0x84 | 0x94 | 0x8c => {
+                let addr = self.operand_addr(mode, false, &mut cycles);
+                self.write(addr, self.regs.y);
+            }
+
+            // transfers
+            0xaa => self.regs.x = self.set_zn(self.regs.a),
+            0xa8 => self.regs.y = self.set_zn(self.regs.a),
+            0x8a => self.regs.a = self.set_zn(self.regs.x),
+            0x98 => self.regs.a = self.set_zn(self.regs.y),
+            0xba => self.regs.x = self.set_zn(self.regs.s),
+            0x9a => self.regs.s = self.regs.x,
+
+            // stack
+            0x48 => self.push(self.regs.a),
+            0x08 => self.push(self.regs.p | STATUS_BREAK | STATUS_UNUSED),
+            0x68 => {
+                let value = self.pull();
+                self.regs.a = self.set_zn(value);
+            }
+            0x28 => self.regs.p = (self.pull() & !STATUS_BREAK) | STATUS_UNUSED,
+
+            // arithmetic and logic
+            0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.add(value);
+            }
+            0xe9 | 0xeb | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.add(!value);
+            }
+            0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.a = self.set_zn(self.regs.a & value);
+            }
+            0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.a = self.set_zn(self.regs.a | value);
+            }
+            0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.a = self.set_zn(self.regs.a ^ value);
+            }
+            0x24 | 0x2c => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.set_zero(self.regs.a & value == 0);
+                self.regs.set_overflow(value & 0x40 != 0);
+                self.regs.set_negative(value & 0x80 != 0);
+            }
+            0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.compare(self.regs.a, value);
+            }
+            0xe0 | 0xe4 | 0xec => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.compare(self.regs.x, value);
+            }
+            0xc0 | 0xc4 | 0xcc => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.compare(self.regs.y, value);
+            }
+
+            // increments, decrements, shifts and rotates
+            0xe6 | 0xf6 | 0xee | 0xfe => {
+                self.modify(mode, &mut cycles, |cpu, value| cpu.set_zn(value.wrapping_add(1)));
+            }
+            0xc6 | 0xd6 | 0xce | 0xde => {
+                self.modify(mode, &mut cycles, |cpu, value| cpu.set_zn(value.wrapping_sub(1)));
+            }
+            0xe8 => self.regs.x = self.set_zn(self.regs.x.wrapping_add(1)),
+            0xc8 => self.regs.y = self.set_zn(self.regs.y.wrapping_add(1)),
+            0xca => self.regs.x = self.set_zn(self.regs.x.wrapping_sub(1)),
+            0x88 => self.regs.y = self.set_zn(self.regs.y.wrapping_sub(1)),
+            0x0a | 0x06 | 0x16 | 0x0e | 0x1e => {
+                self.modify(mode, &mut cycles, Self::shift_left);
+            }
+            0x4a | 0x46 | 0x56 | 0x4e | 0x5e => {
+                self.modify(mode, &mut cycles, Self::shift_right);
+            }
+            0x2a | 0x26 | 0x36 | 0x2e | 0x3e => {
+                self.modify(mode, &mut cycles, Self::rotate_left);
+            }
+            0x6a | 0x66 | 0x76 | 0x6e | 0x7e => {
+                self.modify(mode, &mut cycles, Self::rotate_right);
+            }
+
+            // jumps and calls
+            0x4c | 0x6c => self.regs.pc = self.operand_addr(mode, false, &mut cycles),
+            0x20 => {
+                let addr = self.fetch_word();
+                self.push_word(self.regs.pc.wrapping_sub(1));
+                self.regs.pc = addr;
+            }
+            0x60 => self.regs.pc = self.pull_word().wrapping_add(1),
+            0x40 => {
+                self.regs.p = (self.pull() & !STATUS_BREAK) | STATUS_UNUSED;
+                self.regs.pc = self.pull_word();
+            }
+            0x00 => {
+                self.push_word(self.regs.pc.wrapping_add(1));
+                self.push(self.regs.p | STATUS_BREAK | STATUS_UNUSED);
+                self.regs.set_interrupt_disable(true);
+                self.regs.pc = self.read_word(IRQ_VECTOR);
+            }
+
+            // branches
+            0x10 => self.branch(!self.regs.negative(), &mut cycles),
+            0x30 => self.branch(self.regs.negative(), &mut cycles),
+            0x50 => self.branch(!self.regs.overflow(), &mut cycles),
+            0x70 => self.branch(self.regs.overflow(), &mut cycles),
+            0x90 => self.branch(!self.regs.carry(), &mut cycles),
+            0xb0 => self.branch(self.regs.carry(), &mut cycles),
+            0xd0 => self.branch(!self.regs.zero(), &mut cycles),
+            0xf0 => self.branch(self.regs.zero(), &mut cycles),
+
+            // flags
+            0x18 => self.regs.set_carry(false),
+            0x38 => self.regs.set_carry(true),
+            0x58 => self.regs.set_interrupt_disable(false),
+            0x78 => self.regs.set_interrupt_disable(true),
+            0xb8 => self.regs.set_overflow(false),
+            0xd8 => self.regs.set_decimal_mode(false),
+            0xf8 => self.regs.set_decimal_mode(true),
+
+            // unofficial opcodes that some music drivers rely on
+            0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => {
+                let value = self.read_operand(mode, &mut cycles);
+                self.regs.a = self.set_zn(value);
+                self.regs.x = value;
+            }
+            0x87 | 0x97 | 0x8f | 0x83 => {
+                let addr = self.operand_addr(mode, false, &mut cycles);
+                self.write(addr, self.regs.a & self.regs.x);
+            }
+            0xc7 | 0xd7 | 0xcf | 0xdf | 0xdb | 0xc3 | 0xd3 => {
+                let value = self.modify(mode, &mut cycles, |_, value| value.wrapping_sub(1));
+                self.compare(self.regs.a, value);
+            }
+            0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => {
+                let value = self.modify(mode, &mut cycles, |_, value| value.wrapping_add(1));
+                self.add(!value);
+            }
+            0x07 | 0x17 | 0x0f | 0x1f | 0x1b | 0x03 | 0x13 => {
+                let value = self.modify(mode, &mut cycles, Self::shift_left);
+                self.regs.a = self.set_zn(self.regs.a | value);
+            }
+            0x27 | 0x37 | 0x2f | 0x3f | 0x3b | 0x23 | 0x33 => {
+                let value = self.modify(mode, &mut cycles, Self::rotate_left);
+                self.regs.a = self.set_zn(self.regs.a & value);
+            }
+            0x47 | 0x57 | 0x4f | 0x5f | 0x5b | 0x43 | 0x53 => {
+                let value = self.modify(mode, &mut cycles, Self::shift_right);
+                self.regs.a = self.set_zn(self.regs.a ^ value);
+            }
+            0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => {
+                let value = self.modify(mode, &mut cycles, Self::rotate_right);
+                self.add(value);
+            }
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2 => {
+                warn!("CPU jammed by opcode ${:02X} at ${:04X}", opcode, self.regs.pc.wrapping_sub(1));
+                self.is_jammed = true;
+            }
+            // everything else is a NOP of some length, apart from the unstable unofficial opcodes which are treated as
+            // one anyway
+            _ => {
+                if opcode & 3 == 3 || opcode == 0x9c || opcode == 0x9e {
+                    debug!("Unsupported opcode ${:02X} at ${:04X}", opcode, self.regs.pc.wrapping_sub(1));
+                }
+                if mode != Mode::Implied {
+                    self.read_operand(mode, &mut cycles);
+                }
+            }
+        }
+
+        cycles
+    }
+}
+
+impl Component for Cpu {
+    fn step(&mut self) -> Result<u64> {
+        if !self.is_running || self.is_jammed {
+            return Ok(1);
+        }
+
+        match self.poll_interrupts() {
+            Some(cycles) => {
+                self.cycle += cycles;
+                Ok(cycles)
+            }
+            None => Ok(self.run_instruction()),
+        }
+    }
+}
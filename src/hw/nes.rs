@@ -1,57 +1,153 @@
-use anyhow::Result;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use crate::rom::Cartridge;
+use anyhow::{anyhow, Result};
+
+use crate::rom::{Cartridge, PpuModel};
+use super::apu::{create_expansion_chips, Apu, ApuBus};
+use super::bus::{CartridgeClock, CartridgeSlot};
 use super::clock::Clock;
+use super::controller::{Button, ControllerPorts};
+use super::cpu::{Cpu, CpuBus};
 use super::device::Device;
+use super::nsf::NsfPlayer;
+use super::ppu::{Ppu, PpuBus};
+
+const CPU_DIVIDER: u64 = 12;
+const PPU_DIVIDER: u64 = 4;
 
 pub struct Nes {
     device: Device,
-    cartridge: Option<Cartridge>,
+    cartridge: CartridgeSlot,
+    ppu: Arc<RwLock<Ppu>>,
+    apu: Arc<RwLock<Apu>>,
+    cpu: Arc<RwLock<Cpu>>,
+    controllers: ControllerPorts,
+    nsf_player: Arc<RwLock<NsfPlayer>>,
 }
 
 impl Nes {
     pub fn new() -> Self {
+        let cartridge: CartridgeSlot = Arc::new(RwLock::new(None));
+        let ppu = Arc::new(RwLock::new(Ppu::new(PpuBus::new(Arc::clone(&cartridge)))));
+        let apu = Arc::new(RwLock::new(Apu::new(ApuBus::new(Arc::clone(&cartridge)))));
+        let controllers: ControllerPorts = Arc::new(RwLock::new(Default::default()));
+        let cpu = Arc::new(RwLock::new(Cpu::new(CpuBus::new(
+            Arc::clone(&cartridge),
+            Arc::clone(&ppu),
+            Arc::clone(&apu),
+            Arc::clone(&controllers),
+        ))));
+        let nsf_player = Arc::new(RwLock::new(NsfPlayer::new(Arc::clone(&cpu), Arc::clone(&apu))));
+        let cartridge_clock = Arc::new(RwLock::new(CartridgeClock::new(Arc::clone(&cartridge), Arc::clone(&ppu))));
+
         // TODO: add support for PAL
-        let master_clock = Clock::new(11.0 / 236250000.0);
-        
+        let mut master_clock = Clock::new(11.0 / 236250000.0);
+        master_clock.link(CPU_DIVIDER, cpu.clone());
+        master_clock.link(CPU_DIVIDER, nsf_player.clone());
+        master_clock.link(CPU_DIVIDER, apu.clone());
+        master_clock.link(CPU_DIVIDER, cartridge_clock);
+        master_clock.link(PPU_DIVIDER, ppu.clone());
+
         let mut device = Device::new();
         device.attach(master_clock);
 
         Self {
             device,
-            cartridge: None,
+            cartridge,
+            ppu,
+            apu,
+            cpu,
+            controllers,
+            nsf_player,
         }
     }
-    
-    pub const fn is_cartridge_loaded(&self) -> bool {
-        self.cartridge.is_some()
+
+    pub fn is_cartridge_loaded(&self) -> bool {
+        self.cartridge.read().is_ok_and(|c| c.is_some())
     }
-    
+
     pub const fn is_paused(&self) -> bool {
         self.device.is_paused()
     }
-    
-    pub const fn is_running(&self) -> bool {
+
+    pub fn is_running(&self) -> bool {
         self.is_cartridge_loaded() && !self.is_paused()
     }
-    
+
     pub fn pause(&mut self) {
         self.device.pause();
     }
-    
+
     pub fn resume(&mut self) {
         self.device.resume();
     }
-    
-    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
-        self.cartridge = Some(cartridge);
+
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) -> Result<()> {
+        {
+            let mut ppu = self.ppu_mut()?;
+            ppu.reset();
+            ppu.set_model(cartridge.ppu_model());
+        }
+        let nsf = cartridge.nsf().cloned();
+        let is_nsf = nsf.is_some();
+        self.apu_mut()?.set_expansion_chips(create_expansion_chips(
+            cartridge.expansion_audio(),
+            cartridge.is_vrc6_a0_a1_swapped(),
+        ));
+        *self.cartridge.write().map_err(|_| anyhow!("RwLock poisoned"))? = Some(cartridge);
+        self.nsf_player_mut()?.load(nsf)?;
+
+        // games run straight from the reset vector, while tunes are run by the NSF player
+        let mut cpu = self.cpu.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        if is_nsf {
+            cpu.stop();
+        } else {
+            cpu.power_on();
+        }
+        Ok(())
+    }
+
+    pub fn eject_cartridge(&mut self) -> Result<Option<Cartridge>> {
+        self.cpu.write().map_err(|_| anyhow!("RwLock poisoned"))?.stop();
+        self.nsf_player_mut()?.load(None)?;
+        Ok(self.cartridge.write().map_err(|_| anyhow!("RwLock poisoned"))?.take())
     }
-    
-    pub const fn eject_cartridge(&mut self) -> Option<Cartridge> {
-        self.cartridge.take()
+
+    /// Press or release a button on the controller in one of the two ports, numbered from 0
+    pub fn set_button(&self, port: usize, button: Button, is_pressed: bool) -> Result<()> {
+        self.controllers.write().map_err(|_| anyhow!("RwLock poisoned"))?[port].set_pressed(button, is_pressed);
+        Ok(())
     }
-    
+
+    /// The PPU model required by the loaded cartridge, or the standard 2C02 if there is none
+    pub fn ppu_model(&self) -> PpuModel {
+        match self.cartridge.read() {
+            Ok(cartridge) => cartridge.as_ref().map(Cartridge::ppu_model).unwrap_or(PpuModel::Rp2c02),
+            Err(_) => PpuModel::Rp2c02,
+        }
+    }
+
+    pub fn ppu(&self) -> Result<RwLockReadGuard<'_, Ppu>> {
+        self.ppu.read().map_err(|_| anyhow!("RwLock poisoned"))
+    }
+
+    pub fn ppu_mut(&self) -> Result<RwLockWriteGuard<'_, Ppu>> {
+        self.ppu.write().map_err(|_| anyhow!("RwLock poisoned"))
+    }
+
+    pub fn apu(&self) -> Result<RwLockReadGuard<'_, Apu>> {
+        self.apu.read().map_err(|_| anyhow!("RwLock poisoned"))
+    }
+
+    pub fn apu_mut(&self) -> Result<RwLockWriteGuard<'_, Apu>> {
+        self.apu.write().map_err(|_| anyhow!("RwLock poisoned"))
+    }
+
+    pub fn nsf_player_mut(&self) -> Result<RwLockWriteGuard<'_, NsfPlayer>> {
+        self.nsf_player.write().map_err(|_| anyhow!("RwLock poisoned"))
+    }
+
     pub fn run(&mut self) -> Result<()> {
         self.device.run()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,170 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::rom::Nsf;
+use super::apu::{Apu, NATIVE_SAMPLE_RATE};
+use super::component::Component;
+use super::cpu::Cpu;
+
+/// Address the init and play routines return to. Nothing is mapped there, and the player stops the CPU when it
+/// gets there.
+const RETURN_ADDR: u16 = 0x4100;
+/// The APU produces one sample per CPU cycle
+const CPU_CLOCK_RATE: f64 = NATIVE_SAMPLE_RATE;
+pub const DEFAULT_TRACK_LENGTH: Duration = Duration::from_secs(150);
+pub const DEFAULT_FADE: Duration = Duration::from_secs(8);
+
+/// Plays NSF tunes the way a hardware player does: by calling the tune's init routine to start a track, then its play
+/// routine at a fixed rate
+pub struct NsfPlayer {
+    /// the console's CPU, which the player drives while a tune is loaded
+    cpu: Arc<RwLock<Cpu>>,
+    apu: Arc<RwLock<Apu>>,
+    nsf: Option<Nsf>,
+    track: usize,
+    /// length and fade-out of tracks which don't specify their own
+    default_length: Duration,
+    default_fade: Duration,
+    /// CPU cycles between calls to the play routine
+    play_period: u64,
+    cycles_until_play: u64,
+    /// CPU cycles since the track started
+    elapsed_cycles: u64,
+}
+
+impl NsfPlayer {
+    pub const fn new(cpu: Arc<RwLock<Cpu>>, apu: Arc<RwLock<Apu>>) -> Self {
+        Self {
+            cpu,
+            apu,
+            nsf: None,
+            track: 0,
+            default_length: DEFAULT_TRACK_LENGTH,
+            default_fade: DEFAULT_FADE,
+            play_period: 0,
+            cycles_until_play: 0,
+            elapsed_cycles: 0,
+        }
+    }
+
+    /// Load a tune and start its first track, or stop playing if there is none
+    pub fn load(&mut self, nsf: Option<Nsf>) -> Result<()> {
+        self.nsf = nsf;
+        match self.nsf {
+            Some(ref nsf) => self.start_track(nsf.starting_track),
+            None => Ok(()),
+        }
+    }
+
+    pub const fn nsf(&self) -> Option<&Nsf> {
+        self.nsf.as_ref()
+    }
+
+    pub const fn track(&self) -> usize {
+        self.track
+    }
+
+    /// Set the length and fade-out of tracks which don't specify their own
+    pub fn set_default_length(&mut self, length: Duration, fade: Duration) {
+        self.default_length = length;
+        self.default_fade = fade;
+    }
+
+    /// Length of the current track before it starts fading out, and how long the fade-out lasts
+    pub fn track_length(&self) -> (Duration, Duration) {
+        let track = self.nsf.as_ref().and_then(|nsf| nsf.tracks.get(self.track));
+        (
+            track.and_then(|t| t.length).unwrap_or(self.default_length),
+            track.and_then(|t| t.fade).unwrap_or(self.default_fade),
+        )
+    }
+
+    /// Time since the current track started
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.elapsed_cycles as f64 / CPU_CLOCK_RATE)
+    }
+
+    /// Whether the current track has finished fading out
+    pub fn is_finished(&self) -> bool {
+        let (length, fade) = self.track_length();
+        self.elapsed() >= length + fade
+    }
+
+    fn fade_volume(&self) -> f32 {
+        let (length, fade) = self.track_length();
+        let elapsed = self.elapsed();
+        if elapsed <= length {
+            1.0
+        } else if fade.is_zero() {
+            0.0
+        } else {
+            1.0 - ((elapsed - length).as_secs_f32() / fade.as_secs_f32()).min(1.0)
+        }
+    }
+
+    /// Reset the console and call the tune's init routine for a track, numbered from 0
+    pub fn start_track(&mut self, track: usize) -> Result<()> {
+        let Some(ref nsf) = self.nsf else {
+            return Err(anyhow!("No NSF loaded"));
+        };
+        let init_addr = nsf.init_addr();
+        let initial_banks = nsf.initial_banks();
+        let play_period = nsf.play_period();
+        let track = track.min(nsf.tracks.len() - 1);
+
+        let mut cpu = self.cpu.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        cpu.reset();
+        for addr in 0x6000..=0x7fff {
+            cpu.write(addr, 0);
+        }
+        for addr in 0x4000..=0x4013 {
+            cpu.write(addr, 0);
+        }
+        cpu.write(0x4015, 0x00);
+        cpu.write(0x4015, 0x0f);
+        cpu.write(0x4017, 0x40);
+        if let Some(banks) = initial_banks {
+            for (addr, bank) in (0x5ff8..).zip(banks) {
+                cpu.write(addr, bank);
+            }
+        }
+        self.apu.write().map_err(|_| anyhow!("RwLock poisoned"))?.set_volume(1.0);
+
+        self.track = track;
+        self.play_period = (play_period.as_secs_f64() * CPU_CLOCK_RATE).round() as u64;
+        self.cycles_until_play = self.play_period;
+        self.elapsed_cycles = 0;
+        // the init routine takes the track in A and the TV system in X, which is 0 for NTSC
+        cpu.call(init_addr, track as u8, 0, RETURN_ADDR);
+        Ok(())
+    }
+}
+
+impl Component for NsfPlayer {
+    fn step(&mut self) -> Result<u64> {
+        let Some(ref nsf) = self.nsf else {
+            return Ok(1);
+        };
+        let play_addr = nsf.play_addr();
+
+        let mut cpu = self.cpu.write().map_err(|_| anyhow!("RwLock poisoned"))?;
+        let cycles = if cpu.pc() != RETURN_ADDR {
+            cpu.run_instruction()
+        } else if self.cycles_until_play == 0 && !self.is_finished() {
+            // play isn't called until init has returned, and is called late if it overran the previous frame
+            self.cycles_until_play = self.play_period;
+            let volume = self.fade_volume();
+            self.apu.write().map_err(|_| anyhow!("RwLock poisoned"))?.set_volume(volume);
+            cpu.call(play_addr, 0, 0, RETURN_ADDR);
+            cpu.run_instruction()
+        } else {
+            1
+        };
+
+        self.elapsed_cycles += cycles;
+        self.cycles_until_play = self.cycles_until_play.saturating_sub(cycles);
+        Ok(cycles)
+    }
+}
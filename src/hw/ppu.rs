@@ -0,0 +1,767 @@
+use anyhow::Result;
+
+use crate::rom::PpuModel;
+use super::bus::{Bus, CartridgeSlot};
+use super::component::Component;
+
+mod event;
+pub use event::{PpuEvent, PpuEventKind};
+use event::EventLog;
+mod source;
+pub use source::{PixelSource, TileKey, TileSource};
+use source::TileTracker;
+mod sprite;
+use sprite::*;
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+
+const VBLANK_SCANLINE: u16 = 241;
+const PRE_RENDER_SCANLINE: u16 = 261;
+
+macro_rules! flag {
+    ($getter:ident, $bit:expr) => {
+        const fn $getter(&self) -> bool {
+            self.0 & (1 << $bit) != 0
+        }
+    };
+}
+
+/// PPUCTRL ($2000)
+#[derive(Debug, Default, Clone, Copy)]
+struct Control(u8);
+
+impl Control {
+    const fn vram_increment(&self) -> u16 {
+        if self.0 & 0x04 != 0 { 32 } else { 1 }
+    }
+
+    const fn sprite_table(&self) -> u16 {
+        ((self.0 as u16 >> 3) & 1) << 12
+    }
+
+    const fn background_table(&self) -> u16 {
+        ((self.0 as u16 >> 4) & 1) << 12
+    }
+
+    flag!(tall_sprites, 5);
+    flag!(nmi_enabled, 7);
+}
+
+/// PPUMASK ($2001)
+#[derive(Debug, Default, Clone, Copy)]
+struct Mask(u8);
+
+impl Mask {
+    flag!(greyscale, 0);
+    flag!(show_background_left, 1);
+    flag!(show_sprites_left, 2);
+    flag!(show_background, 3);
+    flag!(show_sprites, 4);
+
+    const fn emphasis(&self) -> u16 {
+        (self.0 as u16 >> 5) << 6
+    }
+
+    const fn is_rendering_enabled(&self) -> bool {
+        self.show_background() || self.show_sprites()
+    }
+}
+
+/// PPUSTATUS ($2002)
+const STATUS_SPRITE_OVERFLOW: u8 = 0x20;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0x40;
+const STATUS_VBLANK: u8 = 0x80;
+
+/// The PPU's view of the console: the cartridge's CHR memory and the console's nametable RAM
+pub struct PpuBus {
+    cartridge: CartridgeSlot,
+    ciram: [u8; 0x800],
+}
+
+impl PpuBus {
+    pub const fn new(cartridge: CartridgeSlot) -> Self {
+        Self {
+            cartridge,
+            ciram: [0; 0x800],
+        }
+    }
+}
+
+impl PpuBus {
+    /// Identify the tile at a pattern table address for HD packs
+    pub fn tile_key(&self, addr: u16) -> TileKey {
+        let addr = addr & 0x1ff0;
+        let offset = match self.cartridge.read() {
+            Ok(cartridge) => cartridge.as_ref().and_then(|c| c.chr_rom_offset(addr)),
+            Err(_) => None,
+        };
+
+        match offset {
+            Some(offset) => TileKey::Rom((offset / 16) as u32),
+            None => TileKey::Ram(std::array::from_fn(|i| self.peek(addr + i as u16))),
+        }
+    }
+
+    /// Drive an address onto the bus without reading or writing, as the PPU does with its VRAM address when it isn't
+    /// rendering, for boards which watch the address lines
+    pub fn set_address(&mut self, addr: u16) {
+        if let Ok(mut cartridge) = self.cartridge.write() {
+            if let Some(ref mut cartridge) = *cartridge {
+                cartridge.ppu_address(addr & 0x3fff);
+            }
+        }
+    }
+
+    /// Read from the bus without any side effects
+    pub fn peek(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3fff;
+        let Ok(cartridge) = self.cartridge.read() else {
+            return 0;
+        };
+        let Some(ref cartridge) = *cartridge else {
+            return 0;
+        };
+
+        if addr < 0x2000 {
+            cartridge.ppu_read(addr)
+        } else {
+            self.ciram[cartridge.mirroring().ciram_offset(addr)]
+        }
+    }
+}
+
+impl Bus for PpuBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0x3fff;
+        let Ok(mut cartridge) = self.cartridge.write() else {
+            return 0;
+        };
+        let Some(ref mut cartridge) = *cartridge else {
+            return 0;
+        };
+
+        cartridge.ppu_address(addr);
+        if addr < 0x2000 {
+            cartridge.ppu_read(addr)
+        } else {
+            self.ciram[cartridge.mirroring().ciram_offset(addr)]
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let addr = addr & 0x3fff;
+        let Ok(mut cartridge) = self.cartridge.write() else {
+            return;
+        };
+        let Some(ref mut cartridge) = *cartridge else {
+            return;
+        };
+
+        cartridge.ppu_address(addr);
+        if addr < 0x2000 {
+            cartridge.ppu_write(addr, value);
+        } else {
+            self.ciram[cartridge.mirroring().ciram_offset(addr)] = value;
+        }
+    }
+}
+
+/// The 2C02 picture processing unit, or one of the Vs. System's RGB PPUs
+pub struct Ppu {
+    bus: PpuBus,
+    model: PpuModel,
+    ctrl: Control,
+    mask: Mask,
+    status: u8,
+    oam_addr: u8,
+    oam: [u8; 0x100],
+    /// value last read from OAM by the rendering hardware
+    oam_latch: u8,
+    secondary_oam: [u8; 4 * SPRITES_PER_SCANLINE],
+    secondary_oam_addr: u8,
+    evaluation_state: EvaluationState,
+    /// whether sprite 0 was found during evaluation for the next scanline
+    next_line_has_sprite_zero: bool,
+    /// whether the first sprite unit on the current scanline holds sprite 0
+    line_has_sprite_zero: bool,
+    sprite_units: [SpriteUnit; SPRITES_PER_SCANLINE],
+    /// sprites beyond the eighth on the current scanline, when the sprite limit is removed
+    extra_sprite_units: Vec<SpriteUnit>,
+    is_sprite_limit_removed: bool,
+    palette: [u8; 0x20],
+    /// current VRAM address
+    v: u16,
+    /// temporary VRAM address
+    t: u16,
+    /// fine X scroll
+    x: u8,
+    /// first/second write toggle for PPUSCROLL and PPUADDR
+    w: bool,
+    read_buffer: u8,
+    /// value last driven on the PPU's CPU-facing data bus
+    io_latch: u8,
+    scanline: u16,
+    dot: u16,
+    is_odd_frame: bool,
+    frame_number: u64,
+    /// phase of the NTSC color subcarrier at the current dot
+    subcarrier_phase: u8,
+    /// subcarrier phase at the first pixel of the frame currently being drawn
+    back_buffer_phase: u8,
+    /// subcarrier phase at the first pixel of the most recently completed frame
+    front_buffer_phase: u8,
+    /// a PPUSTATUS read on the dot before vblank starts prevents the flag from being set for that frame
+    suppress_vblank: bool,
+    nametable_latch: u8,
+    attribute_latch: u8,
+    pattern_low_latch: u8,
+    pattern_high_latch: u8,
+    pattern_low_shifter: u16,
+    pattern_high_shifter: u16,
+    attribute_low_shifter: u16,
+    attribute_high_shifter: u16,
+    events: EventLog,
+    /// completed frames waiting to be collected, when frame capture is enabled
+    captured_frames: Option<Vec<Box<[u16]>>>,
+    tile_tracker: Option<TileTracker>,
+    /// frame currently being drawn
+    back_buffer: Box<[u16]>,
+    /// most recently completed frame
+    front_buffer: Box<[u16]>,
+}
+
+impl Ppu {
+    pub fn new(bus: PpuBus) -> Self {
+        Self {
+            bus,
+            model: PpuModel::Rp2c02,
+            ctrl: Control::default(),
+            mask: Mask::default(),
+            status: 0,
+            oam_addr: 0,
+            oam: [0; 0x100],
+            oam_latch: 0,
+            secondary_oam: [0xff; 4 * SPRITES_PER_SCANLINE],
+            secondary_oam_addr: 0,
+            evaluation_state: EvaluationState::Done,
+            next_line_has_sprite_zero: false,
+            line_has_sprite_zero: false,
+            sprite_units: [SpriteUnit::default(); SPRITES_PER_SCANLINE],
+            extra_sprite_units: Vec::new(),
+            is_sprite_limit_removed: false,
+            palette: [0; 0x20],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            scanline: 0,
+            dot: 0,
+            is_odd_frame: false,
+            frame_number: 0,
+            subcarrier_phase: 0,
+            back_buffer_phase: 0,
+            front_buffer_phase: 0,
+            suppress_vblank: false,
+            nametable_latch: 0,
+            attribute_latch: 0,
+            pattern_low_latch: 0,
+            pattern_high_latch: 0,
+            pattern_low_shifter: 0,
+            pattern_high_shifter: 0,
+            attribute_low_shifter: 0,
+            attribute_high_shifter: 0,
+            events: EventLog::default(),
+            captured_frames: None,
+            tile_tracker: None,
+            back_buffer: vec![0; FRAME_WIDTH * FRAME_HEIGHT].into_boxed_slice(),
+            front_buffer: vec![0; FRAME_WIDTH * FRAME_HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    /// The most recently completed frame as 9-bit pixels: the palette index in the low 6 bits and the emphasis bits
+    /// from PPUMASK in the high 3 bits
+    pub fn frame(&self) -> &[u16] {
+        &self.front_buffer
+    }
+
+    /// Phase of the NTSC color subcarrier (0-11) at the first pixel of the most recently completed frame
+    pub const fn frame_phase(&self) -> usize {
+        self.front_buffer_phase as usize
+    }
+
+    /// Number of frames that have been completed since power-on
+    pub const fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// Clear the registers the CPU can write, as they are at power-on, so that nothing is left over from the previous
+    /// game
+    pub fn reset(&mut self) {
+        self.ctrl = Control::default();
+        self.mask = Mask::default();
+        self.status = 0;
+        self.oam_addr = 0;
+        self.v = 0;
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.read_buffer = 0;
+    }
+
+    /// Set which PPU the console has. The palette is up to the renderer, but the 2C05 also differs in its registers.
+    pub fn set_model(&mut self, model: PpuModel) {
+        self.model = model;
+    }
+
+    /// Value the 2C05 revisions return in the low bits of PPUSTATUS, so that games can check which PPU they're on
+    const fn status_id(&self) -> Option<u8> {
+        match self.model {
+            PpuModel::Rc2c05(1 | 4) => Some(0x1b),
+            PpuModel::Rc2c05(2) => Some(0x3d),
+            PpuModel::Rc2c05(3) => Some(0x1c),
+            _ => None,
+        }
+    }
+
+    /// Draw every sprite on each scanline instead of only the first eight. This only affects the picture; sprite
+    /// evaluation and the overflow flag still behave as they do on hardware.
+    pub fn set_sprite_limit_removed(&mut self, is_removed: bool) {
+        self.is_sprite_limit_removed = is_removed;
+    }
+
+    /// State of the PPU's /NMI output. The CPU is responsible for detecting the edge.
+    pub const fn nmi(&self) -> bool {
+        self.ctrl.nmi_enabled() && self.status & STATUS_VBLANK != 0
+    }
+
+    /// Keep a copy of every completed frame until it's collected with `take_captured_frames`, so that no frames are
+    /// missed when recording
+    pub fn set_frame_capture(&mut self, is_enabled: bool) {
+        self.captured_frames = is_enabled.then(Vec::new);
+    }
+
+    /// Frames completed since the last call, oldest first
+    pub fn take_captured_frames(&mut self) -> Vec<Box<[u16]>> {
+        self.captured_frames.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Record events over each frame for the event viewer. Recording is off by default.
+    pub fn set_event_logging(&mut self, is_enabled: bool) {
+        self.events.set_enabled(is_enabled);
+    }
+
+    /// Events recorded during the most recently completed frame, in the order they occurred
+    pub fn events(&self) -> &[PpuEvent] {
+        self.events.events()
+    }
+
+    /// Record an event raised by another component, such as a mapper IRQ, at the current scanline and dot
+    pub fn record_event(&mut self, kind: PpuEventKind) {
+        self.events.record(self.scanline, self.dot, kind);
+    }
+
+    /// Read from the PPU's address space, including palette RAM, without any side effects
+    pub fn peek(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3fff;
+        if addr >= 0x3f00 {
+            self.palette[Self::palette_index(addr)]
+        } else {
+            self.bus.peek(addr)
+        }
+    }
+
+    pub const fn oam(&self) -> &[u8; 0x100] {
+        &self.oam
+    }
+
+    pub const fn background_pattern_table(&self) -> u16 {
+        self.ctrl.background_table()
+    }
+
+    pub const fn sprite_pattern_table(&self) -> u16 {
+        self.ctrl.sprite_table()
+    }
+
+    pub const fn has_tall_sprites(&self) -> bool {
+        self.ctrl.tall_sprites()
+    }
+
+    /// Scroll position within the 512x480 area of the four nametables, as set by the last writes to PPUCTRL,
+    /// PPUSCROLL and PPUADDR
+    pub const fn scroll(&self) -> (u16, u16) {
+        let x = ((self.t >> 10) & 1) * 256 + (self.t & 0x1f) * 8 + self.x as u16;
+        let y = ((self.t >> 11) & 1) * 240 + ((self.t >> 5) & 0x1f) * 8 + ((self.t >> 12) & 7);
+        (x, y)
+    }
+
+    const fn is_rendering(&self) -> bool {
+        self.mask.is_rendering_enabled() && (self.scanline < FRAME_HEIGHT as u16 || self.scanline == PRE_RENDER_SCANLINE)
+    }
+
+    /// Handle a CPU read from one of the PPU's registers ($2000-$3FFF)
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        match addr & 7 {
+            2 => {
+                self.io_latch = match self.status_id() {
+                    Some(id) => (self.status & 0xe0) | id,
+                    None => (self.status & 0xe0) | (self.io_latch & 0x1f),
+                };
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                if self.scanline == VBLANK_SCANLINE && self.dot == 0 {
+                    self.suppress_vblank = true;
+                }
+            }
+            4 => {
+                // during rendering, reads return whatever the sprite hardware is currently looking at
+                self.io_latch = if self.is_rendering() {
+                    self.oam_latch
+                } else {
+                    self.oam[self.oam_addr as usize]
+                };
+            }
+            7 => {
+                let addr = self.v & 0x3fff;
+                if addr >= 0x3f00 {
+                    // palette reads are immediate, but the buffer is filled from the nametable "underneath" the palette
+                    self.io_latch = (self.read_palette(addr) & 0x3f) | (self.io_latch & 0xc0);
+                    self.read_buffer = self.bus.read(addr - 0x1000);
+                } else {
+                    self.io_latch = self.read_buffer;
+                    self.read_buffer = self.bus.read(addr);
+                }
+                self.increment_vram_address();
+            }
+            // write-only registers return the contents of the I/O latch
+            _ => (),
+        }
+
+        self.io_latch
+    }
+
+    /// Handle a CPU write to one of the PPU's registers ($2000-$3FFF)
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        self.record_event(PpuEventKind::RegisterWrite { addr: 0x2000 | (addr & 7), value });
+        self.io_latch = value;
+        let register = match (self.model, addr & 7) {
+            // the 2C05 has PPUCTRL and PPUMASK the other way around
+            (PpuModel::Rc2c05(_), register @ 0..=1) => register ^ 1,
+            (_, register) => register,
+        };
+        match register {
+            0 => {
+                let was_nmi_active = self.nmi();
+                self.ctrl = Control(value);
+                if self.nmi() && !was_nmi_active {
+                    self.record_event(PpuEventKind::Nmi);
+                }
+                self.t = (self.t & 0xf3ff) | ((value as u16 & 3) << 10);
+            }
+            1 => self.mask = Mask(value),
+            2 => (),
+            3 => self.oam_addr = value,
+            4 => {
+                if self.is_rendering() {
+                    // writes during rendering are ignored, but bump the sprite index of OAMADDR
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
+                } else {
+                    // bits 2-4 of the attribute byte don't exist in OAM
+                    let value = if self.oam_addr & 3 == 2 { value & 0xe3 } else { value };
+                    self.oam[self.oam_addr as usize] = value;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                }
+            }
+            5 => {
+                if self.w {
+                    self.t = (self.t & 0x8c1f) | ((value as u16 & 7) << 12) | ((value as u16 & 0xf8) << 2);
+                } else {
+                    self.t = (self.t & !0x1f) | (value as u16 >> 3);
+                    self.x = value & 7;
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if self.w {
+                    self.t = (self.t & 0xff00) | value as u16;
+                    self.v = self.t;
+                    // the new address appears on the bus straight away, which games can use to toggle A12
+                    if !self.is_rendering() {
+                        self.bus.set_address(self.v);
+                    }
+                } else {
+                    self.t = (self.t & 0x00ff) | ((value as u16 & 0x3f) << 8);
+                }
+                self.w = !self.w;
+            }
+            7 => {
+                let addr = self.v & 0x3fff;
+                if addr >= 0x3f00 {
+                    self.palette[Self::palette_index(addr)] = value & 0x3f;
+                } else {
+                    self.bus.write(addr, value);
+                }
+                self.increment_vram_address();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    const fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1f) as usize;
+        // the background color entries of the sprite palettes mirror those of the background palettes
+        if index & 0x13 == 0x10 {
+            index & 0x0f
+        } else {
+            index
+        }
+    }
+
+    fn read_palette(&self, addr: u16) -> u8 {
+        let color = self.palette[Self::palette_index(addr)];
+        if self.mask.greyscale() {
+            color & 0x30
+        } else {
+            color
+        }
+    }
+
+    fn increment_vram_address(&mut self) {
+        if self.is_rendering() {
+            // accessing PPUDATA during rendering triggers both scroll increments instead of the normal increment
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            self.v = self.v.wrapping_add(self.ctrl.vram_increment()) & 0x7fff;
+        }
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001f == 0x001f {
+            self.v &= !0x001f;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03e0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // coarse Y values in the attribute table wrap without switching nametables
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal_position(&mut self) {
+        self.v = (self.v & !0x041f) | (self.t & 0x041f);
+    }
+
+    fn copy_vertical_position(&mut self) {
+        self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_low_shifter <<= 1;
+        self.pattern_high_shifter <<= 1;
+        self.attribute_low_shifter <<= 1;
+        self.attribute_high_shifter <<= 1;
+    }
+
+    fn reload_background_shifters(&mut self) {
+        self.pattern_low_shifter = (self.pattern_low_shifter & 0xff00) | self.pattern_low_latch as u16;
+        self.pattern_high_shifter = (self.pattern_high_shifter & 0xff00) | self.pattern_high_latch as u16;
+        let low = if self.attribute_latch & 1 != 0 { 0xff } else { 0x00 };
+        let high = if self.attribute_latch & 2 != 0 { 0xff } else { 0x00 };
+        self.attribute_low_shifter = (self.attribute_low_shifter & 0xff00) | low;
+        self.attribute_high_shifter = (self.attribute_high_shifter & 0xff00) | high;
+        self.track_background_reload();
+    }
+
+    fn fetch_background(&mut self) {
+        match (self.dot - 1) % 8 {
+            0 => self.nametable_latch = self.bus.read(0x2000 | (self.v & 0x0fff)),
+            2 => {
+                let v = self.v;
+                let attribute = self.bus.read(0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+                let shift = ((v >> 4) & 4) | (v & 2);
+                self.attribute_latch = (attribute >> shift) & 3;
+            }
+            4 => {
+                let addr = self.background_pattern_address();
+                self.pattern_low_latch = self.bus.read(addr);
+            }
+            6 => {
+                let addr = self.background_pattern_address() + 8;
+                self.pattern_high_latch = self.bus.read(addr);
+                self.track_background_fetch();
+            }
+            7 => self.increment_coarse_x(),
+            _ => (),
+        }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        self.ctrl.background_table() | ((self.nametable_latch as u16) << 4) | ((self.v >> 12) & 7)
+    }
+
+    fn background_pixel(&self, x: usize) -> u8 {
+        if !self.mask.show_background() || (x < 8 && !self.mask.show_background_left()) {
+            return 0;
+        }
+
+        let bit = 15 - self.x;
+        let pattern = ((self.pattern_low_shifter >> bit) & 1) | (((self.pattern_high_shifter >> bit) & 1) << 1);
+        if pattern == 0 {
+            return 0;
+        }
+        let attribute = ((self.attribute_low_shifter >> bit) & 1) | (((self.attribute_high_shifter >> bit) & 1) << 1);
+        ((attribute << 2) | pattern) as u8
+    }
+
+    fn render_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+        let color = if self.mask.is_rendering_enabled() {
+            let background = self.background_pixel(x);
+            let (sprite, is_sprite_zero_opaque) = self.sprite_pixel(x);
+            if is_sprite_zero_opaque && background != 0 && x != FRAME_WIDTH - 1
+                && self.status & STATUS_SPRITE_ZERO_HIT == 0 {
+                self.status |= STATUS_SPRITE_ZERO_HIT;
+                self.record_event(PpuEventKind::SpriteZeroHit);
+            }
+
+            self.track_pixel(x, background, sprite);
+            let pixel = match sprite {
+                Some(sprite) if background == 0 || !sprite.is_behind_background => 0x10 | sprite.color,
+                _ => background,
+            };
+            self.read_palette(0x3f00 | pixel as u16)
+        } else if self.v & 0x3f00 == 0x3f00 {
+            self.track_pixel(x, 0, None);
+            // when rendering is disabled and the VRAM address points into palette RAM, that color is output instead
+            // of the backdrop
+            self.read_palette(self.v)
+        } else {
+            self.track_pixel(x, 0, None);
+            self.read_palette(0x3f00)
+        };
+
+        self.back_buffer[self.scanline as usize * FRAME_WIDTH + x] = color as u16 | self.mask.emphasis();
+    }
+
+    fn tick(&mut self) {
+        let is_visible_line = self.scanline < FRAME_HEIGHT as u16;
+        let is_pre_render_line = self.scanline == PRE_RENDER_SCANLINE;
+
+        if self.scanline == 0 && self.dot == 0 {
+            self.events.end_frame();
+        }
+
+        if is_pre_render_line && self.dot == 1 {
+            self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+        }
+
+        if (is_visible_line || is_pre_render_line) && self.mask.is_rendering_enabled() {
+            let dot = self.dot;
+            if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+                self.shift_background();
+                if (dot - 1).is_multiple_of(8) {
+                    self.reload_background_shifters();
+                }
+            }
+
+            if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+                self.fetch_background();
+            } else if dot == 337 || dot == 339 {
+                // unused nametable fetches at the end of the line
+                self.nametable_latch = self.bus.read(0x2000 | (self.v & 0x0fff));
+            }
+
+            if is_visible_line && (1..=256).contains(&dot) {
+                self.evaluate_sprites();
+            } else if (257..=320).contains(&dot) {
+                self.fetch_sprites(is_pre_render_line);
+            } else if is_pre_render_line && (1..=8).contains(&dot) {
+                self.corrupt_oam();
+            }
+
+            if dot == 256 {
+                self.increment_y();
+            } else if dot == 257 {
+                self.copy_horizontal_position();
+            } else if is_pre_render_line && (280..=304).contains(&dot) {
+                self.copy_vertical_position();
+            }
+        }
+
+        if is_visible_line && (1..=256).contains(&self.dot) {
+            if self.scanline == 0 && self.dot == 1 {
+                self.back_buffer_phase = self.subcarrier_phase;
+            }
+            self.render_pixel();
+        }
+
+        if self.scanline == VBLANK_SCANLINE && self.dot == 1 {
+            if !self.suppress_vblank {
+                self.status |= STATUS_VBLANK;
+                if self.nmi() {
+                    self.record_event(PpuEventKind::Nmi);
+                }
+            }
+            self.suppress_vblank = false;
+            std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
+            if let Some(ref mut tracker) = self.tile_tracker {
+                tracker.swap_buffers();
+            }
+            if let Some(ref mut frames) = self.captured_frames {
+                frames.push(self.front_buffer.clone());
+            }
+            self.front_buffer_phase = self.back_buffer_phase;
+            self.frame_number += 1;
+        }
+
+        self.advance();
+    }
+
+    fn advance(&mut self) {
+        // on odd frames with rendering enabled, the last dot of the pre-render line is skipped
+        let skip_dot = self.scanline == PRE_RENDER_SCANLINE && self.dot == DOTS_PER_SCANLINE - 2
+            && self.is_odd_frame && self.mask.is_rendering_enabled();
+
+        // the phase counts half master clock cycles: each dot lasts 4 master clock cycles, or 8 halves, and the
+        // subcarrier completes a cycle every 6, or 12 halves
+        self.subcarrier_phase = (self.subcarrier_phase + 8) % 12;
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE || skip_dot {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.is_odd_frame = !self.is_odd_frame;
+            }
+        }
+    }
+}
+
+impl Component for Ppu {
+    fn step(&mut self) -> Result<u64> {
+        self.tick();
+        Ok(1)
+    }
+}
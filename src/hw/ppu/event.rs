@@ -0,0 +1,50 @@
+/// Something that happened during a frame, for debugging raster effects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuEventKind {
+    /// A CPU write to one of the PPU's registers, given as $2000-$2007
+    RegisterWrite { addr: u16, value: u8 },
+    MapperIrq,
+    SpriteZeroHit,
+    Nmi,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PpuEvent {
+    pub scanline: u16,
+    pub dot: u16,
+    pub kind: PpuEventKind,
+}
+
+/// Events recorded over the frame in progress and the previous frame
+#[derive(Debug, Default)]
+pub struct EventLog {
+    is_enabled: bool,
+    current: Vec<PpuEvent>,
+    previous: Vec<PpuEvent>,
+}
+
+impl EventLog {
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+        if !is_enabled {
+            self.current.clear();
+            self.previous.clear();
+        }
+    }
+
+    pub fn record(&mut self, scanline: u16, dot: u16, kind: PpuEventKind) {
+        if self.is_enabled {
+            self.current.push(PpuEvent { scanline, dot, kind });
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+    }
+
+    /// Events from the most recently completed frame
+    pub fn events(&self) -> &[PpuEvent] {
+        &self.previous
+    }
+}
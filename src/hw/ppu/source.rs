@@ -0,0 +1,188 @@
+use super::sprite::SpritePixel;
+use super::{Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+
+/// Identifies a tile the way HD packs do: by its index in CHR ROM, or by its pattern data for games with CHR RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileKey {
+    Rom(u32),
+    Ram([u8; 16]),
+}
+
+/// The tile a pixel was drawn from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileSource {
+    pub key: TileKey,
+    /// The four colors of the palette the tile was drawn with, with the backdrop color in the most significant byte
+    pub palette: u32,
+    /// Position of the pixel within the tile's pattern data
+    pub x: u8,
+    pub y: u8,
+    /// The pixel's value in the pattern data, from 0 to 3
+    pub color: u8,
+    pub is_flipped_horizontally: bool,
+    pub is_flipped_vertically: bool,
+}
+
+/// The background and sprite tiles that were candidates for a pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PixelSource {
+    pub background: Option<TileSource>,
+    pub is_background_opaque: bool,
+    /// The sprite with the highest priority at this pixel, if any were opaque
+    pub sprite: Option<TileSource>,
+    pub is_sprite_behind_background: bool,
+}
+
+impl PixelSource {
+    /// Whether the sprite at this pixel, if any, is the one that was displayed
+    pub const fn is_sprite_visible(&self) -> bool {
+        self.sprite.is_some() && (!self.is_background_opaque || !self.is_sprite_behind_background)
+    }
+}
+
+/// A tile fetched by the rendering hardware, before it's known which pixels it will be drawn at
+#[derive(Debug, Clone, Copy)]
+struct FetchedTile {
+    key: TileKey,
+    row: u8,
+    palette: u8,
+    x: u8,
+    attributes: u8,
+}
+
+/// Follows tiles through the rendering pipeline to work out which tile each pixel came from
+pub struct TileTracker {
+    background_latch: Option<FetchedTile>,
+    /// tiles in the high and low halves of the background shifters
+    background_tiles: [Option<FetchedTile>; 2],
+    /// tiles loaded into each sprite unit, followed by the extra units when the sprite limit is removed
+    sprite_tiles: Vec<Option<FetchedTile>>,
+    back_buffer: Box<[PixelSource]>,
+    front_buffer: Box<[PixelSource]>,
+}
+
+impl TileTracker {
+    pub fn new() -> Self {
+        Self {
+            background_latch: None,
+            background_tiles: [None; 2],
+            sprite_tiles: Vec::new(),
+            back_buffer: vec![PixelSource::default(); FRAME_WIDTH * FRAME_HEIGHT].into_boxed_slice(),
+            front_buffer: vec![PixelSource::default(); FRAME_WIDTH * FRAME_HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    pub fn swap_buffers(&mut self) {
+        std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
+    }
+}
+
+impl Ppu {
+    /// Track which tile each pixel is drawn from, for HD packs. Tracking is off by default.
+    pub fn set_tile_tracking(&mut self, is_enabled: bool) {
+        self.tile_tracker = is_enabled.then(TileTracker::new);
+    }
+
+    /// Where each pixel of the most recently completed frame came from, if tile tracking is enabled
+    pub fn pixel_sources(&self) -> Option<&[PixelSource]> {
+        self.tile_tracker.as_ref().map(|tracker| &*tracker.front_buffer)
+    }
+
+    /// The four colors of one of the eight palettes as packed by `TileSource`
+    fn palette_key(&self, palette: u8) -> u32 {
+        let base = palette as usize * 4;
+        u32::from_be_bytes([self.palette[0], self.palette[base + 1], self.palette[base + 2], self.palette[base + 3]])
+    }
+
+    pub(super) fn track_background_fetch(&mut self) {
+        if self.tile_tracker.is_none() {
+            return;
+        }
+
+        let addr = self.background_pattern_address();
+        let tile = FetchedTile {
+            key: self.bus.tile_key(addr),
+            row: (addr & 7) as u8,
+            palette: self.attribute_latch,
+            x: 0,
+            attributes: 0,
+        };
+        if let Some(ref mut tracker) = self.tile_tracker {
+            tracker.background_latch = Some(tile);
+        }
+    }
+
+    pub(super) fn track_background_reload(&mut self) {
+        if let Some(ref mut tracker) = self.tile_tracker {
+            tracker.background_tiles = [tracker.background_tiles[1], tracker.background_latch];
+        }
+    }
+
+    /// Record the tile loaded into a sprite unit, or `None` if the unit is empty
+    pub(super) fn track_sprite_fetch(&mut self, unit: usize, sprite: Option<(u16, u8, u8)>) {
+        if self.tile_tracker.is_none() {
+            return;
+        }
+
+        let tile = sprite.map(|(addr, attributes, x)| FetchedTile {
+            key: self.bus.tile_key(addr),
+            row: (addr & 7) as u8,
+            palette: 4 + (attributes & 3),
+            x,
+            attributes,
+        });
+        if let Some(ref mut tracker) = self.tile_tracker {
+            if tracker.sprite_tiles.len() <= unit {
+                tracker.sprite_tiles.resize(unit + 1, None);
+            }
+            tracker.sprite_tiles[unit] = tile;
+        }
+    }
+
+    /// Record the tiles at the pixel being drawn
+    pub(super) fn track_pixel(&mut self, x: usize, background: u8, sprite: Option<SpritePixel>) {
+        let Some(ref tracker) = self.tile_tracker else {
+            return;
+        };
+
+        // the background shifters hold two tiles, and the pixel comes from the bit selected by fine X, offset by the
+        // number of shifts since the last reload
+        let column = self.x as usize + x % 8;
+        let background_tile = if self.mask.show_background() { tracker.background_tiles[column / 8] } else { None };
+        let background_source = background_tile.map(|tile| TileSource {
+            key: tile.key,
+            palette: self.palette_key(tile.palette),
+            x: (column % 8) as u8,
+            y: tile.row,
+            color: background & 3,
+            is_flipped_horizontally: false,
+            is_flipped_vertically: false,
+        });
+
+        let sprite_tile = sprite.and_then(|sprite| tracker.sprite_tiles.get(sprite.unit).copied().flatten());
+        let sprite_source = sprite_tile.map(|tile| {
+            let is_flipped_horizontally = tile.attributes & 0x40 != 0;
+            let column = (x - tile.x as usize) as u8 & 7;
+            TileSource {
+                key: tile.key,
+                palette: self.palette_key(tile.palette),
+                x: if is_flipped_horizontally { 7 - column } else { column },
+                y: tile.row,
+                color: sprite.map_or(0, |sprite| sprite.color & 3),
+                is_flipped_horizontally,
+                is_flipped_vertically: tile.attributes & 0x80 != 0,
+            }
+        });
+
+        let source = PixelSource {
+            background: background_source,
+            is_background_opaque: background != 0,
+            sprite: sprite_source,
+            is_sprite_behind_background: sprite.is_some_and(|sprite| sprite.is_behind_background),
+        };
+        let index = self.scanline as usize * FRAME_WIDTH + x;
+        if let Some(ref mut tracker) = self.tile_tracker {
+            tracker.back_buffer[index] = source;
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::hw::bus::Bus;
+
+use super::{Ppu, STATUS_SPRITE_OVERFLOW};
+
+pub const SPRITES_PER_SCANLINE: usize = 8;
+
+/// Progress of sprite evaluation through primary OAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvaluationState {
+    /// Copying in-range sprites to secondary OAM
+    Search,
+    /// Secondary OAM is full; scanning (incorrectly) for an overflowing sprite
+    Overflow,
+    /// An overflowing sprite was found; reading its remaining bytes
+    OverflowCopy(u8),
+    /// All 64 sprites have been checked
+    Done,
+}
+
+/// One of the eight sprite output units
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpriteUnit {
+    pattern_low: u8,
+    pattern_high: u8,
+    attributes: u8,
+    x: u8,
+}
+
+impl SpriteUnit {
+    const fn palette(&self) -> u8 {
+        self.attributes & 3
+    }
+
+    const fn is_behind_background(&self) -> bool {
+        self.attributes & 0x20 != 0
+    }
+
+    const fn is_flipped_horizontally(&self) -> bool {
+        self.attributes & 0x40 != 0
+    }
+
+    /// The pattern value of this sprite at the current dot, if it's in range
+    const fn pattern(&self) -> u8 {
+        if self.x != 0 {
+            return 0;
+        }
+        ((self.pattern_low >> 7) & 1) | (((self.pattern_high >> 7) & 1) << 1)
+    }
+
+    fn shift(&mut self) {
+        if self.x > 0 {
+            self.x -= 1;
+        } else {
+            self.pattern_low <<= 1;
+            self.pattern_high <<= 1;
+        }
+    }
+}
+
+/// A sprite's contribution to a single pixel
+#[derive(Debug, Clone, Copy)]
+pub struct SpritePixel {
+    /// Index into the sprite palettes
+    pub color: u8,
+    pub is_behind_background: bool,
+    /// Sprite unit the pixel came from
+    pub unit: usize,
+}
+
+impl Ppu {
+    const fn sprite_height(&self) -> u16 {
+        if self.ctrl.tall_sprites() { 16 } else { 8 }
+    }
+
+    fn is_sprite_in_range(&self, y: u8) -> bool {
+        self.scanline.wrapping_sub(y as u16) < self.sprite_height()
+    }
+
+    /// Run one dot of secondary OAM clearing (dots 1-64) or sprite evaluation (dots 65-256) for the next scanline
+    pub(super) fn evaluate_sprites(&mut self) {
+        let dot = self.dot;
+        if dot <= 64 {
+            // reads during the clear return $FF regardless of the contents of OAM
+            self.oam_latch = 0xff;
+            if dot.is_multiple_of(2) {
+                self.secondary_oam[(dot as usize / 2) - 1] = 0xff;
+            }
+            return;
+        }
+
+        if dot == 65 {
+            self.secondary_oam_addr = 0;
+            self.evaluation_state = EvaluationState::Search;
+            self.next_line_has_sprite_zero = false;
+        }
+
+        // odd dots read from primary OAM, even dots write to secondary OAM
+        if dot % 2 == 1 {
+            self.oam_latch = self.oam[self.oam_addr as usize];
+            return;
+        }
+
+        let value = self.oam_latch;
+        match self.evaluation_state {
+            EvaluationState::Search => {
+                self.secondary_oam[self.secondary_oam_addr as usize] = value;
+                if self.oam_addr & 3 == 0 {
+                    if !self.is_sprite_in_range(value) {
+                        self.next_sprite(4);
+                        return;
+                    }
+
+                    // whichever sprite is evaluated first is treated as sprite 0, even if OAMADDR wasn't 0
+                    if dot == 66 {
+                        self.next_line_has_sprite_zero = true;
+                    }
+                }
+
+                self.secondary_oam_addr += 1;
+                self.next_sprite(1);
+                if self.secondary_oam_addr as usize == self.secondary_oam.len() && self.evaluation_state == EvaluationState::Search {
+                    self.evaluation_state = EvaluationState::Overflow;
+                }
+            }
+            EvaluationState::Overflow => {
+                // secondary OAM is full, so writes are ignored. the hardware treats whatever byte it's looking at as a
+                // Y coordinate, and when a sprite isn't in range it increments both the sprite index and the byte
+                // index, producing the well-known false positives and negatives in the overflow flag.
+                if self.is_sprite_in_range(value) {
+                    self.status |= STATUS_SPRITE_OVERFLOW;
+                    self.evaluation_state = EvaluationState::OverflowCopy(3);
+                    self.next_sprite(1);
+                } else {
+                    let next = (self.oam_addr & !3).wrapping_add(4) | (self.oam_addr.wrapping_add(1) & 3);
+                    if next & !3 == 0 {
+                        self.evaluation_state = EvaluationState::Done;
+                    }
+                    self.oam_addr = next;
+                }
+            }
+            EvaluationState::OverflowCopy(remaining) => {
+                self.evaluation_state = if remaining > 1 {
+                    EvaluationState::OverflowCopy(remaining - 1)
+                } else {
+                    EvaluationState::Done
+                };
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            EvaluationState::Done => {
+                // the hardware keeps trying and failing to copy the Y coordinates of the remaining sprites
+                self.oam_addr = self.oam_addr.wrapping_add(4) & !3;
+            }
+        }
+    }
+
+    fn next_sprite(&mut self, increment: u8) {
+        let (next, has_wrapped) = self.oam_addr.overflowing_add(increment);
+        self.oam_addr = next;
+        if has_wrapped {
+            self.evaluation_state = EvaluationState::Done;
+        }
+    }
+
+    /// Run one dot of the sprite pattern fetches (dots 257-320) for the next scanline
+    pub(super) fn fetch_sprites(&mut self, is_pre_render_line: bool) {
+        self.oam_addr = 0;
+
+        let offset = (self.dot - 257) as usize;
+        let index = offset / 8;
+        let entry = &self.secondary_oam[index * 4..index * 4 + 4];
+        let (y, tile, attributes, x) = (entry[0], entry[1], entry[2], entry[3]);
+        self.oam_latch = entry[(offset % 8).min(3)];
+
+        match offset % 8 {
+            // garbage nametable fetches
+            0 | 2 => {
+                self.bus.read(0x2000 | (self.v & 0x0fff));
+            }
+            4 | 6 => {
+                let row = self.scanline.wrapping_sub(y as u16) & 0x0f;
+                let addr = self.sprite_pattern_address(tile, attributes, row) + if offset % 8 == 6 { 8 } else { 0 };
+                let mut pattern = self.bus.read(addr);
+
+                // sprites which weren't found during evaluation, and all sprites on the pre-render line, are
+                // transparent even though the fetch still happens
+                let count = self.secondary_oam_addr as usize / 4;
+                if is_pre_render_line || index >= count {
+                    pattern = 0;
+                }
+
+                let unit = &mut self.sprite_units[index];
+                unit.attributes = attributes;
+                unit.x = x;
+                if unit.is_flipped_horizontally() {
+                    pattern = pattern.reverse_bits();
+                }
+                if offset % 8 == 4 {
+                    unit.pattern_low = pattern;
+                } else {
+                    unit.pattern_high = pattern;
+                    let is_empty = is_pre_render_line || index >= count;
+                    self.track_sprite_fetch(index, (!is_empty).then_some((addr, attributes, x)));
+                }
+            }
+            _ => (),
+        }
+
+        if self.dot == 320 {
+            self.line_has_sprite_zero = self.next_line_has_sprite_zero && !is_pre_render_line;
+            self.extra_sprite_units.clear();
+            if self.is_sprite_limit_removed && !is_pre_render_line {
+                self.fetch_extra_sprites();
+            }
+        }
+    }
+
+    /// Load the sprites on the next scanline that didn't fit in secondary OAM into extra sprite units
+    ///
+    /// Nothing here is visible to the CPU or the cartridge: CHR is read without side effects, and the hardware
+    /// evaluation has already run, so the overflow flag and sprite 0 hit behave exactly as they do with the limit.
+    fn fetch_extra_sprites(&mut self) {
+        if (self.secondary_oam_addr as usize) < self.secondary_oam.len() {
+            return;
+        }
+
+        let mut units = std::mem::take(&mut self.extra_sprite_units);
+        let mut fetched = Vec::new();
+        let in_range = self.oam.chunks_exact(4).filter(|entry| self.is_sprite_in_range(entry[0]));
+        for entry in in_range.skip(SPRITES_PER_SCANLINE) {
+            let (y, tile, attributes, x) = (entry[0], entry[1], entry[2], entry[3]);
+            let row = self.scanline.wrapping_sub(y as u16) & 0x0f;
+            let addr = self.sprite_pattern_address(tile, attributes, row);
+            let mut unit = SpriteUnit {
+                pattern_low: self.bus.peek(addr),
+                pattern_high: self.bus.peek(addr + 8),
+                attributes,
+                x,
+            };
+            if unit.is_flipped_horizontally() {
+                unit.pattern_low = unit.pattern_low.reverse_bits();
+                unit.pattern_high = unit.pattern_high.reverse_bits();
+            }
+            fetched.push((addr, attributes, x));
+            units.push(unit);
+        }
+        self.extra_sprite_units = units;
+
+        for (i, sprite) in fetched.into_iter().enumerate() {
+            self.track_sprite_fetch(SPRITES_PER_SCANLINE + i, Some(sprite));
+        }
+    }
+
+    fn sprite_pattern_address(&self, tile: u8, attributes: u8, row: u16) -> u16 {
+        let height = self.sprite_height();
+        let is_flipped_vertically = attributes & 0x80 != 0;
+        let row = if is_flipped_vertically { height - 1 - (row % height) } else { row % height };
+
+        if height == 16 {
+            let table = (tile as u16 & 1) << 12;
+            let tile = (tile as u16 & 0xfe) + (row >> 3);
+            table | (tile << 4) | (row & 7)
+        } else {
+            self.ctrl.sprite_table() | ((tile as u16) << 4) | row
+        }
+    }
+
+    /// Get the sprite output for the current dot and advance the sprite units
+    ///
+    /// Returns the frontmost opaque sprite pixel, if any, and whether sprite 0 has an opaque pixel here.
+    pub(super) fn sprite_pixel(&mut self, x: usize) -> (Option<SpritePixel>, bool) {
+        let is_visible = self.mask.show_sprites() && (x >= 8 || self.mask.show_sprites_left());
+
+        let mut pixel = None;
+        let mut is_sprite_zero_opaque = false;
+        // extra sprites come after all the hardware sprites in OAM, so they're always lower priority
+        for (i, unit) in self.sprite_units.iter().chain(self.extra_sprite_units.iter()).enumerate() {
+            let pattern = unit.pattern();
+            if pattern == 0 || !is_visible {
+                continue;
+            }
+
+            if i == 0 && self.line_has_sprite_zero {
+                is_sprite_zero_opaque = true;
+            }
+
+            if pixel.is_none() {
+                pixel = Some(SpritePixel {
+                    color: (unit.palette() << 2) | pattern,
+                    is_behind_background: unit.is_behind_background(),
+                    unit: i,
+                });
+            }
+        }
+
+        for unit in self.sprite_units.iter_mut().chain(self.extra_sprite_units.iter_mut()) {
+            unit.shift();
+        }
+
+        (pixel, is_sprite_zero_opaque)
+    }
+
+    /// Emulate the OAM corruption that occurs when rendering starts with OAMADDR at 8 or higher
+    pub(super) fn corrupt_oam(&mut self) {
+        if self.oam_addr >= 8 {
+            let i = (self.dot - 1) as usize;
+            self.oam[i] = self.oam[(self.oam_addr as usize & 0xf8) + i];
+        }
+    }
+}
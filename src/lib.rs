@@ -3,8 +3,11 @@ use std::fs::File;
 use eframe::egui;
 
 mod app;
+mod audio;
 mod rom;
 mod hw;
+mod record;
+mod video;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
@@ -20,8 +23,8 @@ pub fn launch() -> eframe::Result {
 
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([341.0, 302.0]) // +40 pixels height for menu
-            .with_min_inner_size([341.0, 262.0]),
+            .with_inner_size(app::NersApp::default_window_size())
+            .with_min_inner_size([256.0, 240.0]),
         ..Default::default()
     };
 
@@ -29,7 +32,7 @@ pub fn launch() -> eframe::Result {
 
     rt.block_on(async {
         eframe::run_native(
-            "ners",
+            app::APP_ID,
             native_options,
             Box::new(|cc| Ok(Box::new(app::NersApp::new(cc)))),
         )
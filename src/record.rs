@@ -0,0 +1,117 @@
+use std::io::{Seek, Write};
+
+use anyhow::Result;
+
+use crate::video::Image;
+
+mod avi;
+mod riff;
+mod wav;
+mod y4m;
+
+pub use avi::AviWriter;
+pub use wav::WavWriter;
+pub use y4m::Y4mWriter;
+
+/// The NTSC NES's exact frame rate of 60.0988 Hz, as a fraction: the 236.25/11 MHz master clock divided by 4 for the
+/// PPU, over an average of 89341.5 dots per frame
+pub const FRAME_RATE_NUMERATOR: u32 = 39375000;
+pub const FRAME_RATE_DENOMINATOR: u32 = 655171;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// A Y4M video stream with a separate WAV audio track
+    Y4mWav,
+    /// A single AVI with uncompressed video and audio
+    Avi,
+}
+
+enum Output<W: Write + Seek> {
+    Y4mWav(Y4mWriter<W>, WavWriter<W>),
+    Avi(AviWriter<W>),
+}
+
+/// Records every frame and audio sample the emulator produces
+pub struct Recorder<W: Write + Seek> {
+    output: Output<W>,
+}
+
+impl<W: Write + Seek> Recorder<W> {
+    pub fn y4m_wav(video: W, audio: W, width: usize, height: usize, sample_rate: u32) -> Result<Self> {
+        Ok(Self {
+            output: Output::Y4mWav(Y4mWriter::new(video, width, height)?, WavWriter::new(audio, sample_rate)?),
+        })
+    }
+
+    pub fn avi(out: W, width: usize, height: usize, sample_rate: u32) -> Result<Self> {
+        Ok(Self {
+            output: Output::Avi(AviWriter::new(out, width, height, sample_rate)?),
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &Image) -> Result<()> {
+        match self.output {
+            Output::Y4mWav(ref mut video, _) => video.write_frame(image),
+            Output::Avi(ref mut avi) => avi.write_frame(image),
+        }
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        match self.output {
+            Output::Y4mWav(_, ref mut audio) => audio.write_samples(samples),
+            Output::Avi(ref mut avi) => avi.write_samples(samples),
+        }
+    }
+
+    /// Finish writing all files and return them in the order they were provided
+    pub fn finish(self) -> Result<Vec<W>> {
+        match self.output {
+            Output::Y4mWav(video, audio) => Ok(vec![video.finish()?, audio.finish()?]),
+            Output::Avi(avi) => Ok(vec![avi.finish()?]),
+        }
+    }
+}
+
+/// Records audio alone to WAV files: the full mix, and optionally any number of separate tracks alongside it
+pub struct AudioRecorder<W: Write + Seek> {
+    tracks: Vec<WavWriter<W>>,
+}
+
+impl<W: Write + Seek> AudioRecorder<W> {
+    pub fn new(files: Vec<W>, sample_rate: u32) -> Result<Self> {
+        Ok(Self {
+            tracks: files.into_iter().map(|file| WavWriter::new(file, sample_rate)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Append samples to one of the tracks, numbered in the order their files were provided
+    pub fn write_samples(&mut self, track: usize, samples: &[i16]) -> Result<()> {
+        self.tracks[track].write_samples(samples)
+    }
+
+    /// Finish writing all files and return them in the order they were provided
+    pub fn finish(self) -> Result<Vec<W>> {
+        self.tracks.into_iter().map(WavWriter::finish).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn audio_recorder_writes_each_track_to_its_own_file() {
+        let files = vec![Cursor::new(Vec::new()), Cursor::new(Vec::new()), Cursor::new(Vec::new())];
+        let mut recorder = AudioRecorder::new(files, 48000).unwrap();
+        recorder.write_samples(0, &[1, 2, 3]).unwrap();
+        recorder.write_samples(2, &[4]).unwrap();
+        recorder.write_samples(0, &[5]).unwrap();
+
+        let files = recorder.finish().unwrap();
+        // everything after each file's 44-byte header
+        let data: Vec<_> = files.into_iter().map(|file| file.into_inner()[44..].to_vec()).collect();
+        assert_eq!(data, [vec![1, 0, 2, 0, 3, 0, 5, 0], vec![], vec![4, 0]]);
+    }
+}
@@ -0,0 +1,287 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use anyhow::{anyhow, Result};
+
+use crate::video::Image;
+use super::{riff, FRAME_RATE_DENOMINATOR, FRAME_RATE_NUMERATOR};
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+const VIDEO_CHUNK_ID: &[u8; 4] = b"00db";
+const AUDIO_CHUNK_ID: &[u8; 4] = b"01wb";
+const BLOCK_ALIGN: u32 = 2;
+/// AVI 1.0 files are limited by 32-bit RIFF sizes; stop short of that to leave room for the index
+const MAX_MOVIE_SIZE: u64 = 0xf000_0000;
+
+/// Offsets within the header of the fields that can only be filled in once the recording is finished
+const RIFF_SIZE_OFFSET: u64 = 4;
+const TOTAL_FRAMES_OFFSET: u64 = 48;
+
+struct IndexEntry {
+    id: [u8; 4],
+    offset: u32,
+    size: u32,
+}
+
+/// Writes an AVI 1.0 file with uncompressed 24-bit RGB video and 16-bit mono PCM audio
+pub struct AviWriter<W: Write + Seek> {
+    out: W,
+    width: usize,
+    height: usize,
+    video_length_offset: u64,
+    audio_length_offset: u64,
+    movie_size_offset: u64,
+    movie_size: u32,
+    frame_count: u32,
+    sample_count: u32,
+    index: Vec<IndexEntry>,
+    frame: Vec<u8>,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    pub fn new(mut out: W, width: usize, height: usize, sample_rate: u32) -> Result<Self> {
+        // rows of a DIB are padded to a multiple of 4 bytes
+        let stride = (width * 3).next_multiple_of(4);
+        let frame_size = (stride * height) as u32;
+        let micros_per_frame = (1_000_000u64 * FRAME_RATE_DENOMINATOR as u64 / FRAME_RATE_NUMERATOR as u64) as u32;
+        let (w, h) = (width as u32, height as u32);
+
+        let mut main_header = Vec::new();
+        riff::u32s(&mut main_header, &[
+            micros_per_frame,
+            frame_size * 61 + sample_rate * BLOCK_ALIGN,
+            0,
+            AVIF_HASINDEX,
+            0, // total frames
+            0,
+            2,
+            frame_size,
+            w,
+            h,
+            0, 0, 0, 0,
+        ]);
+
+        let mut video_header = Vec::new();
+        video_header.extend_from_slice(b"vids");
+        riff::u32s(&mut video_header, &[0, 0, 0, 0, FRAME_RATE_DENOMINATOR, FRAME_RATE_NUMERATOR, 0]);
+        let video_length_field = video_header.len();
+        riff::u32s(&mut video_header, &[0, frame_size, u32::MAX, 0]);
+        riff::u16s(&mut video_header, &[0, 0, w as u16, h as u16]);
+
+        let mut video_format = Vec::new();
+        // a positive height means the rows are stored bottom-up
+        riff::u32s(&mut video_format, &[40, w, h]);
+        riff::u16s(&mut video_format, &[1, 24]);
+        riff::u32s(&mut video_format, &[0, frame_size, 0, 0, 0, 0]);
+
+        let mut audio_header = Vec::new();
+        audio_header.extend_from_slice(b"auds");
+        riff::u32s(&mut audio_header, &[0, 0, 0, 0, BLOCK_ALIGN, sample_rate * BLOCK_ALIGN, 0]);
+        let audio_length_field = audio_header.len();
+        riff::u32s(&mut audio_header, &[0, sample_rate * BLOCK_ALIGN, u32::MAX, BLOCK_ALIGN]);
+        riff::u16s(&mut audio_header, &[0, 0, 0, 0]);
+
+        let mut video_list = Vec::new();
+        riff::chunk(&mut video_list, b"strh", &video_header);
+        riff::chunk(&mut video_list, b"strf", &video_format);
+        let mut audio_list = Vec::new();
+        riff::chunk(&mut audio_list, b"strh", &audio_header);
+        riff::chunk(&mut audio_list, b"strf", &riff::pcm_format(sample_rate));
+
+        let mut header_list = Vec::new();
+        riff::chunk(&mut header_list, b"avih", &main_header);
+        // each stream's length field is in its strh chunk, after the list and chunk headers
+        let video_length_offset = header_list.len() + 12 + 8 + video_length_field;
+        riff::list(&mut header_list, b"strl", &video_list);
+        let audio_length_offset = header_list.len() + 12 + 8 + audio_length_field;
+        riff::list(&mut header_list, b"strl", &audio_list);
+
+        let mut header = Vec::new();
+        header.extend_from_slice(b"RIFF");
+        riff::u32s(&mut header, &[0]);
+        header.extend_from_slice(b"AVI ");
+        let list_start = header.len() + 12;
+        riff::list(&mut header, b"hdrl", &header_list);
+        let movie_size_offset = header.len() + 4;
+        header.extend_from_slice(b"LIST");
+        riff::u32s(&mut header, &[4]);
+        header.extend_from_slice(b"movi");
+        out.write_all(&header)?;
+
+        Ok(Self {
+            out,
+            width,
+            height,
+            video_length_offset: (list_start + video_length_offset) as u64,
+            audio_length_offset: (list_start + audio_length_offset) as u64,
+            movie_size_offset: movie_size_offset as u64,
+            movie_size: 4,
+            frame_count: 0,
+            sample_count: 0,
+            index: Vec::new(),
+            frame: vec![0; frame_size as usize],
+        })
+    }
+
+    fn write_chunk(&mut self, id: &[u8; 4], data: &[u8]) -> Result<()> {
+        let padded_size = data.len().next_multiple_of(2) as u64;
+        if self.movie_size as u64 + 8 + padded_size > MAX_MOVIE_SIZE {
+            return Err(anyhow!("AVI file size limit reached"));
+        }
+
+        let mut chunk = Vec::with_capacity(padded_size as usize + 8);
+        riff::chunk(&mut chunk, id, data);
+        self.out.write_all(&chunk)?;
+        // index offsets are relative to the "movi" identifier
+        self.index.push(IndexEntry { id: *id, offset: self.movie_size, size: data.len() as u32 });
+        self.movie_size += chunk.len() as u32;
+        Ok(())
+    }
+
+    pub fn write_frame(&mut self, image: &Image) -> Result<()> {
+        let stride = self.frame.len() / self.height;
+        for (y, row) in image.pixels.chunks_exact(image.width).take(self.height).enumerate() {
+            let start = (self.height - 1 - y) * stride;
+            for (x, &[r, g, b, _]) in row.iter().take(self.width).enumerate() {
+                self.frame[start + x * 3..start + x * 3 + 3].copy_from_slice(&[b, g, r]);
+            }
+        }
+
+        let frame = std::mem::take(&mut self.frame);
+        let result = self.write_chunk(VIDEO_CHUNK_ID, &frame);
+        self.frame = frame;
+        result?;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.write_chunk(AUDIO_CHUNK_ID, &data)?;
+        self.sample_count += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        let mut index = Vec::with_capacity(self.index.len() * 16);
+        for entry in &self.index {
+            index.extend_from_slice(&entry.id);
+            riff::u32s(&mut index, &[AVIIF_KEYFRAME, entry.offset, entry.size]);
+        }
+        let mut index_chunk = Vec::new();
+        riff::chunk(&mut index_chunk, b"idx1", &index);
+        self.out.write_all(&index_chunk)?;
+
+        let file_size = self.out.stream_position()?;
+        let patches = [
+            (RIFF_SIZE_OFFSET, file_size as u32 - 8),
+            (TOTAL_FRAMES_OFFSET, self.frame_count),
+            (self.video_length_offset, self.frame_count),
+            (self.audio_length_offset, self.sample_count),
+            (self.movie_size_offset, self.movie_size),
+        ];
+        for (offset, value) in patches {
+            self.out.seek(SeekFrom::Start(offset))?;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const WIDTH: usize = 3;
+    const HEIGHT: usize = 2;
+    const SAMPLE_RATE: u32 = 48000;
+    /// rows of 9 bytes padded to 12
+    const FRAME_SIZE: u32 = 24;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Offset of the data of the nth chunk with an ID
+    fn find_chunk(file: &[u8], id: &[u8; 4], n: usize) -> usize {
+        file.windows(4).enumerate().filter(|(_, window)| window == id).nth(n).map(|(i, _)| i + 8).unwrap()
+    }
+
+    fn record() -> Vec<u8> {
+        let mut image = Image::new(WIDTH, HEIGHT);
+        image.pixels[0] = [1, 2, 3, 255];
+        image.pixels[WIDTH] = [4, 5, 6, 255];
+
+        let mut avi = AviWriter::new(Cursor::new(Vec::new()), WIDTH, HEIGHT, SAMPLE_RATE).unwrap();
+        avi.write_frame(&image).unwrap();
+        avi.write_samples(&[1, 2, 3]).unwrap();
+        avi.write_frame(&image).unwrap();
+        avi.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn header_describes_the_streams() {
+        let file = record();
+        assert_eq!(&file[0..4], b"RIFF");
+        assert_eq!(u32_at(&file, RIFF_SIZE_OFFSET as usize), file.len() as u32 - 8);
+        assert_eq!(&file[8..12], b"AVI ");
+
+        let main_header = find_chunk(&file, b"avih", 0);
+        assert_eq!(u32_at(&file, main_header), 16639, "microseconds per frame");
+        assert_eq!(u32_at(&file, main_header + 12), AVIF_HASINDEX);
+        assert_eq!(main_header + 16, TOTAL_FRAMES_OFFSET as usize);
+        assert_eq!(u32_at(&file, main_header + 16), 2, "total frames");
+        assert_eq!(u32_at(&file, main_header + 24), 2, "streams");
+        assert_eq!((u32_at(&file, main_header + 32), u32_at(&file, main_header + 36)), (3, 2));
+
+        let video = find_chunk(&file, b"strh", 0);
+        assert_eq!(&file[video..video + 4], b"vids");
+        let rate = (u32_at(&file, video + 20), u32_at(&file, video + 24));
+        assert_eq!(rate, (FRAME_RATE_DENOMINATOR, FRAME_RATE_NUMERATOR));
+        assert_eq!(u32_at(&file, video + 32), 2, "video length");
+        let video_format = find_chunk(&file, b"strf", 0);
+        assert_eq!(u32_at(&file, video_format + 20), FRAME_SIZE);
+
+        let audio = find_chunk(&file, b"strh", 1);
+        assert_eq!(&file[audio..audio + 4], b"auds");
+        assert_eq!((u32_at(&file, audio + 20), u32_at(&file, audio + 24)), (2, SAMPLE_RATE * 2));
+        assert_eq!(u32_at(&file, audio + 32), 3, "audio length");
+        let audio_format = find_chunk(&file, b"strf", 1);
+        assert_eq!(u32_at(&file, audio_format + 4), SAMPLE_RATE);
+    }
+
+    #[test]
+    fn movie_is_indexed_and_stored_bottom_up() {
+        let file = record();
+        let movie = find_chunk(&file, b"movi", 0) - 8;
+        assert_eq!(&file[movie - 8..movie - 4], b"LIST");
+        let movie_size = u32_at(&file, movie - 4) as usize;
+        assert_eq!(movie_size, 4 + 3 * 8 + 2 * FRAME_SIZE as usize + 6);
+
+        // the image's bottom row is stored first, in BGR order
+        let frame = movie + 12;
+        assert_eq!(&file[frame - 8..frame - 4], VIDEO_CHUNK_ID);
+        assert_eq!(&file[frame..frame + 3], &[6, 5, 4]);
+        assert_eq!(&file[frame + 12..frame + 15], &[3, 2, 1]);
+
+        let index = find_chunk(&file, b"idx1", 0);
+        assert_eq!(index, movie + movie_size + 8);
+        let entries: Vec<_> = file[index..]
+            .chunks_exact(16)
+            .map(|entry| (&entry[0..4], u32_at(entry, 4), u32_at(entry, 8), u32_at(entry, 12)))
+            .collect();
+        assert_eq!(entries, [
+            (&VIDEO_CHUNK_ID[..], AVIIF_KEYFRAME, 4, FRAME_SIZE),
+            (&AUDIO_CHUNK_ID[..], AVIIF_KEYFRAME, 4 + 8 + FRAME_SIZE, 6),
+            (&VIDEO_CHUNK_ID[..], AVIIF_KEYFRAME, 4 + 16 + FRAME_SIZE + 6, FRAME_SIZE),
+        ]);
+    }
+}
@@ -0,0 +1,42 @@
+//! Helpers for building RIFF files, the container used by both WAV and AVI
+
+pub fn chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    // chunks are padded to an even length
+    if !data.len().is_multiple_of(2) {
+        out.push(0);
+    }
+}
+
+pub fn list(out: &mut Vec<u8>, kind: &[u8; 4], contents: &[u8]) {
+    out.extend_from_slice(b"LIST");
+    out.extend_from_slice(&(contents.len() as u32 + 4).to_le_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(contents);
+}
+
+pub fn u16s(out: &mut Vec<u8>, values: &[u16]) {
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+pub fn u32s(out: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// The WAVEFORMATEX structure for 16-bit mono PCM
+pub fn pcm_format(sample_rate: u32) -> Vec<u8> {
+    const FORMAT_PCM: u16 = 1;
+    const BLOCK_ALIGN: u16 = 2;
+
+    let mut format = Vec::new();
+    u16s(&mut format, &[FORMAT_PCM, 1]);
+    u32s(&mut format, &[sample_rate, sample_rate * BLOCK_ALIGN as u32]);
+    u16s(&mut format, &[BLOCK_ALIGN, 16]);
+    format
+}
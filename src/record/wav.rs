@@ -0,0 +1,102 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use anyhow::Result;
+
+use super::riff;
+
+/// Size of everything in a WAV file before the sample data
+const HEADER_SIZE: u32 = 44;
+
+/// Writes 16-bit mono PCM to a WAV file
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> Result<Self> {
+        let mut header = Vec::new();
+        header.extend_from_slice(b"RIFF");
+        // the sizes are filled in when the file is finished
+        riff::u32s(&mut header, &[0]);
+        header.extend_from_slice(b"WAVE");
+        riff::chunk(&mut header, b"fmt ", &riff::pcm_format(sample_rate));
+        header.extend_from_slice(b"data");
+        riff::u32s(&mut header, &[0]);
+        out.write_all(&header)?;
+
+        Ok(Self { out, data_size: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.out.write_all(&data)?;
+        self.data_size += data.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_describes_16_bit_mono_pcm() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+        wav.write_samples(&[0; 5]).unwrap();
+        let file = wav.finish().unwrap().into_inner();
+
+        assert_eq!(file.len(), HEADER_SIZE as usize + 10);
+        assert_eq!(&file[0..4], b"RIFF");
+        assert_eq!(u32_at(&file, 4), file.len() as u32 - 8);
+        assert_eq!(&file[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&file, 16), 16);
+        // PCM, 1 channel
+        assert_eq!((u16_at(&file, 20), u16_at(&file, 22)), (1, 1));
+        // sample rate and bytes per second
+        assert_eq!((u32_at(&file, 24), u32_at(&file, 28)), (44100, 88200));
+        // bytes per sample frame and bits per sample
+        assert_eq!((u16_at(&file, 32), u16_at(&file, 34)), (2, 16));
+        assert_eq!(&file[36..40], b"data");
+        assert_eq!(u32_at(&file, 40), 10);
+    }
+
+    #[test]
+    fn samples_survive_a_round_trip() {
+        let mut samples: Vec<i16> = (0..1000).map(|i| (i * 97 % 65536 - 32768) as i16).collect();
+        samples.extend([i16::MIN, i16::MAX]);
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48000).unwrap();
+        for block in samples.chunks(300) {
+            wav.write_samples(block).unwrap();
+        }
+        wav.write_samples(&[]).unwrap();
+        let file = wav.finish().unwrap().into_inner();
+
+        let data_size = u32_at(&file, HEADER_SIZE as usize - 4) as usize;
+        assert_eq!(data_size, samples.len() * 2);
+        let data = &file[HEADER_SIZE as usize..];
+        assert_eq!(data.len(), data_size);
+        let read: Vec<i16> = data.chunks_exact(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])).collect();
+        assert_eq!(read, samples);
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::video::Image;
+use super::{FRAME_RATE_DENOMINATOR, FRAME_RATE_NUMERATOR};
+
+/// Writes frames to a YUV4MPEG2 stream as full-range 4:4:4 YCbCr
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: usize,
+    height: usize,
+    planes: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut out: W, width: usize, height: usize) -> Result<Self> {
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444 XCOLORRANGE=FULL",
+            width, height, FRAME_RATE_NUMERATOR, FRAME_RATE_DENOMINATOR,
+        )?;
+
+        Ok(Self {
+            out,
+            width,
+            height,
+            planes: vec![0; width * height * 3],
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &Image) -> Result<()> {
+        let size = self.width * self.height;
+        let (y_plane, chroma) = self.planes.split_at_mut(size);
+        let (cb_plane, cr_plane) = chroma.split_at_mut(size);
+        for (i, &[r, g, b, _]) in image.pixels.iter().take(size).enumerate() {
+            let (r, g, b) = (r as f32, g as f32, b as f32);
+            y_plane[i] = (0.299 * r + 0.587 * g + 0.114 * b).round() as u8;
+            cb_plane[i] = (128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b).round().clamp(0.0, 255.0) as u8;
+            cr_plane[i] = (128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b).round().clamp(0.0, 255.0) as u8;
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.planes)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
@@ -3,39 +3,172 @@ use std::io::Read;
 use anyhow::{anyhow, Result};
 use binrw::BinReaderExt;
 
+mod board;
+use board::{create_board, Board, BoardConfig, NsfBoard};
 mod ines;
 use ines::*;
+pub use ines::PpuModel;
+mod nsf;
+pub use nsf::{ExpansionAudio, Nsf};
+use nsf::{NSFE_MAGIC, NSF_MAGIC};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+impl Mirroring {
+    /// Map a nametable address in the PPU's address space to an offset into the console's 2KB of nametable RAM
+    pub const fn ciram_offset(self, addr: u16) -> usize {
+        let addr = (addr & 0x0fff) as usize;
+        let page = match self {
+            Self::Horizontal => addr >> 11,
+            Self::Vertical => (addr >> 10) & 1,
+            Self::SingleScreenLower => 0,
+            Self::SingleScreenUpper => 1,
+        };
+        (page << 10) | (addr & 0x3ff)
+    }
+}
+
+/// A game or tune, ready to be inserted into the console
 #[derive(Debug)]
 pub struct Cartridge {
-    prg_rom: Vec<u8>,
-    chr_rom: Vec<u8>,
+    board: Box<dyn Board>,
+    ppu_model: PpuModel,
+    /// sound chips on the board, or used by the tune
+    expansion_audio: ExpansionAudio,
+    /// whether the board is a VRC6b, which swaps the VRC6's A0 and A1
+    is_vrc6_a0_a1_swapped: bool,
+    /// the tune, if this is an NSF rather than a game
+    nsf: Option<Nsf>,
 }
 
 impl Cartridge {
     pub fn from_rom<F: Read + BinReaderExt>(mut f: F) -> Result<Self> {
-        // only INES format supported at the moment
+        let mut magic = [0; 4];
+        f.read_exact(&mut magic)?;
+        f.rewind()?;
+        if &magic == NSF_MAGIC || &magic == NSFE_MAGIC {
+            return Ok(Self::from_nsf(Nsf::from_file(f)?));
+        }
+
+        // only INES format supported for games at the moment
         let rom: INes = f.read_le()?;
         // only NTSC currently supported
         if rom.tv_system() != Some(TvSystem::Ntsc) {
             return Err(anyhow!("Only NTSC is currently supported"));
         }
-        // only NROM mapper currently supported
-        if rom.mapper()? != Mapper::NROM {
-            return Err(anyhow!("Only NROM mapper is currently supported"));
-        }
-        // no submappers currently supported
-        if rom.submapper_id().unwrap_or(0) != 0 {
-            return Err(anyhow!("Submappers are not currently supported"));
-        }
         // trainers not supported
         if rom.trainer().is_some() {
             return Err(anyhow!("Trainers are not currently supported"));
         }
-        
-        Ok(Self {
+        // four-screen nametables not supported
+        if rom.has_alternative_nametable_layout() {
+            return Err(anyhow!("Four-screen nametables are not currently supported"));
+        }
+
+        let board = create_board(BoardConfig {
+            mapper: rom.mapper_id() as u16,
+            submapper: rom.submapper_id().unwrap_or(0),
             prg_rom: rom.prg_rom().to_owned(),
             chr_rom: rom.chr_rom().to_owned(),
+            chr_ram_size: rom.chr_ram_size().max(rom.chr_nvram_size()),
+            prg_ram_size: rom.prg_ram_size(),
+            prg_nvram_size: rom.prg_nvram_size(),
+            mirroring: match rom.nametable_arrangement() {
+                NametableArrangement::Vertical => Mirroring::Horizontal,
+                NametableArrangement::Horizontal => Mirroring::Vertical,
+            },
+        })?;
+
+        Ok(Self {
+            board,
+            ppu_model: rom.ppu_model(),
+            expansion_audio: rom.expansion_audio(),
+            is_vrc6_a0_a1_swapped: rom.is_vrc6_a0_a1_swapped(),
+            nsf: None,
         })
     }
-}
\ No newline at end of file
+
+    /// Build the cartridge an NSF player would provide for a tune
+    pub fn from_nsf(mut nsf: Nsf) -> Self {
+        Self {
+            board: Box::new(NsfBoard::new(nsf.take_prg_rom(), nsf.initial_banks())),
+            ppu_model: PpuModel::Rp2c02,
+            expansion_audio: nsf.expansion_audio,
+            is_vrc6_a0_a1_swapped: false,
+            nsf: Some(nsf),
+        }
+    }
+
+    pub const fn nsf(&self) -> Option<&Nsf> {
+        self.nsf.as_ref()
+    }
+
+    pub const fn expansion_audio(&self) -> ExpansionAudio {
+        self.expansion_audio
+    }
+
+    pub const fn is_vrc6_a0_a1_swapped(&self) -> bool {
+        self.is_vrc6_a0_a1_swapped
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.board.mirroring()
+    }
+
+    pub const fn ppu_model(&self) -> PpuModel {
+        self.ppu_model
+    }
+
+    /// Offset into CHR ROM that a pattern table address maps to, if the cartridge has CHR ROM
+    pub fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        self.board.chr_rom_offset(addr)
+    }
+
+    /// Read from the cartridge region ($4020-$FFFF) of the CPU's address space
+    pub fn cpu_read(&self, addr: u16) -> u8 {
+        self.board.cpu_read(addr)
+    }
+
+    /// Write to the cartridge region ($4020-$FFFF) of the CPU's address space
+    pub fn cpu_write(&mut self, addr: u16, value: u8) {
+        self.board.cpu_write(addr, value);
+    }
+
+    /// Read from the pattern table region ($0000-$1FFF) of the PPU's address space
+    pub fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.ppu_read(addr)
+    }
+
+    /// Write to the pattern table region ($0000-$1FFF) of the PPU's address space
+    pub fn ppu_write(&mut self, addr: u16, value: u8) {
+        self.board.ppu_write(addr, value);
+    }
+
+    /// Let the board see an address the PPU is about to access
+    pub fn ppu_address(&mut self, addr: u16) {
+        self.board.ppu_address(addr);
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.board.cpu_clock();
+    }
+
+    /// State of the cartridge's /IRQ output
+    pub fn irq(&self) -> bool {
+        self.board.irq()
+    }
+
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.board.battery_ram()
+    }
+
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        self.board.load_battery_ram(data);
+    }
+}
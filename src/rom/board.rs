@@ -0,0 +1,149 @@
+use std::fmt::Debug;
+
+use anyhow::{anyhow, Result};
+
+use super::Mirroring;
+
+mod discrete;
+use discrete::{Discrete, Kind as DiscreteKind};
+mod fme7;
+use fme7::Fme7;
+mod mmc1;
+use mmc1::{Mmc1, Revision as Mmc1Revision};
+mod mmc3;
+use mmc3::{Mmc3, Revision as Mmc3Revision};
+mod n163;
+use n163::N163;
+mod nrom;
+use nrom::Nrom;
+mod nsf;
+pub use nsf::NsfBoard;
+mod vrc6;
+use vrc6::Vrc6;
+mod vrc7;
+use vrc7::Vrc7;
+mod vrc_irq;
+
+/// The circuitry on a cartridge: its memory, and the mapper which decides what the CPU and PPU see of it
+pub trait Board: Debug + Send + Sync {
+    /// Read from the cartridge region ($4020-$FFFF) of the CPU's address space, without side effects
+    fn cpu_read(&self, addr: u16) -> u8;
+
+    /// Write to the cartridge region ($4020-$FFFF) of the CPU's address space
+    fn cpu_write(&mut self, addr: u16, value: u8);
+
+    /// Read from the pattern table region ($0000-$1FFF) of the PPU's address space, without side effects
+    fn ppu_read(&self, addr: u16) -> u8;
+
+    /// Write to the pattern table region ($0000-$1FFF) of the PPU's address space
+    fn ppu_write(&mut self, addr: u16, value: u8);
+
+    /// Watch the PPU's address bus. Called with every address the PPU reads or writes, nametables included, before
+    /// the access, for boards which track lines such as A12.
+    fn ppu_address(&mut self, _addr: u16) {}
+
+    /// How the nametables are currently mapped into the console's 2KB of nametable RAM
+    fn mirroring(&self) -> Mirroring;
+
+    /// Offset into CHR ROM that a pattern table address currently maps to, if the board has CHR ROM
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize>;
+
+    /// Clock the board once per CPU cycle, for boards with timers
+    fn cpu_clock(&mut self) {}
+
+    /// State of the board's /IRQ output
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Contents of the board's battery-backed RAM, if it has any
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Restore battery-backed RAM saved from an earlier session
+    fn load_battery_ram(&mut self, _data: &[u8]) {}
+}
+
+/// Everything a board is built from: the ROM's contents and what its header says about the rest of the cartridge
+#[derive(Debug)]
+pub struct BoardConfig {
+    /// 12-bit NES 2.0 mapper number
+    pub mapper: u16,
+    /// NES 2.0 submapper, or 0 if the header doesn't give one
+    pub submapper: u8,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    /// only used if there is no CHR ROM
+    pub chr_ram_size: usize,
+    pub prg_ram_size: usize,
+    /// battery-backed PRG RAM
+    pub prg_nvram_size: usize,
+    /// the mirroring set by the board's solder pads, which some mappers can override
+    pub mirroring: Mirroring,
+}
+
+type Constructor = fn(BoardConfig) -> Box<dyn Board>;
+
+/// Supported boards by mapper number and submapper. A submapper of `None` matches any that aren't listed before it.
+const BOARDS: &[(u16, Option<u8>, Constructor)] = &[
+    (0, None, |config| Box::new(Nrom::new(config))),
+    // The MMC1A and MMC1B are told apart by mapper number rather than by submapper: NES 2.0 gives boards with the
+    // MMC1A, which can't disable PRG RAM, mapper 155 (below), and leaves mapper 1 to the MMC1B and later. Submapper 3
+    // is deprecated in favour of mapper 155, but some headers still use it for the MMC1A, so it's accepted too.
+    (1, Some(3), |config| Box::new(Mmc1::new(config, Mmc1Revision::Mmc1a))),
+    (1, Some(5), |config| Box::new(Mmc1::new(config, Mmc1Revision::Mmc1b).with_fixed_prg())),
+    // submappers 1, 2 and 4 are deprecated too, and SUROM, SOROM and SXROM are told apart by their memory sizes instead
+    (1, None, |config| Box::new(Mmc1::new(config, Mmc1Revision::Mmc1b))),
+    (2, None, |config| Box::new(Discrete::new(config, DiscreteKind::UxRom))),
+    (3, None, |config| Box::new(Discrete::new(config, DiscreteKind::CnRom))),
+    (4, Some(1), |config| Box::new(Mmc3::new(config, Mmc3Revision::Mmc6))),
+    (4, Some(4), |config| Box::new(Mmc3::new(config, Mmc3Revision::Mmc3a))),
+    (4, None, |config| Box::new(Mmc3::new(config, Mmc3Revision::Mmc3c))),
+    (7, None, |config| Box::new(Discrete::new(config, DiscreteKind::AxRom))),
+    (11, None, |config| Box::new(Discrete::new(config, DiscreteKind::ColorDreams))),
+    (13, None, |config| Box::new(Discrete::new(config, DiscreteKind::CpRom))),
+    // the sound chip is handled by the APU at $4800 and $F800, which the board also sees
+    (19, None, |config| Box::new(N163::new(config))),
+    // the VRC6a and VRC6b differ only in which of A0 and A1 pick a register
+    (24, None, |config| Box::new(Vrc6::new(config, false))),
+    (26, None, |config| Box::new(Vrc6::new(config, true))),
+    (34, Some(1), |config| Box::new(Discrete::new(config, DiscreteKind::Nina001))),
+    (34, Some(2), |config| Box::new(Discrete::new(config, DiscreteKind::BnRom))),
+    // without a submapper, only the NINA-001 has CHR ROM
+    (34, None, |config| {
+        let kind = if config.chr_rom.is_empty() { DiscreteKind::BnRom } else { DiscreteKind::Nina001 };
+        Box::new(Discrete::new(config, kind))
+    }),
+    (66, None, |config| Box::new(Discrete::new(config, DiscreteKind::GxRom))),
+    // the 5B's sound is handled by the APU at $C000 and $E000
+    (69, None, |config| Box::new(Fme7::new(config))),
+    (79, None, |config| Box::new(Discrete::new(config, DiscreteKind::Nina03_06))),
+    (85, None, |config| Box::new(Vrc7::new(config))),
+    // the MMC1A, as explained at mapper 1
+    (155, None, |config| Box::new(Mmc1::new(config, Mmc1Revision::Mmc1a))),
+];
+
+/// Build the board for a mapper and submapper
+pub fn create_board(config: BoardConfig) -> Result<Box<dyn Board>> {
+    let (_, _, constructor) = BOARDS
+        .iter()
+        .find(|(mapper, submapper, _)| {
+            *mapper == config.mapper && submapper.is_none_or(|submapper| submapper == config.submapper)
+        })
+        .ok_or_else(|| anyhow!("Mapper {} submapper {} is not supported", config.mapper, config.submapper))?;
+    Ok(constructor(config))
+}
+
+/// Read from a memory of any size, mirroring it if the offset is past the end. Missing memory reads as 0.
+fn read_mirrored(memory: &[u8], offset: usize) -> u8 {
+    if memory.is_empty() { 0 } else { memory[offset % memory.len()] }
+}
+
+/// Write to a memory of any size, mirroring it if the offset is past the end
+fn write_mirrored(memory: &mut [u8], offset: usize, value: u8) {
+    if !memory.is_empty() {
+        let len = memory.len();
+        memory[offset % len] = value;
+    }
+}
@@ -0,0 +1,309 @@
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+/// PRG RAM on the NINA-001, whose registers sit at the top of it
+const NINA_001_PRG_RAM_SIZE: usize = 0x2000;
+/// CHR RAM on the CPROM, which has four 4KB banks
+const CPROM_CHR_RAM_SIZE: usize = 0x4000;
+
+/// Boards whose bank switching is done with a latch or two of discrete logic rather than a mapper chip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// 16KB PRG bank at $8000, with the last bank fixed at $C000
+    UxRom,
+    /// 8KB CHR bank
+    CnRom,
+    /// 32KB PRG bank and a choice of single-screen nametable
+    AxRom,
+    /// 32KB PRG bank and 8KB CHR bank
+    ColorDreams,
+    /// 4KB CHR RAM bank at $1000, with the first bank fixed at $0000
+    CpRom,
+    /// 32KB PRG bank
+    BnRom,
+    /// 32KB PRG bank and two 4KB CHR banks, switched by writes to the top of PRG RAM
+    Nina001,
+    /// 32KB PRG bank and 8KB CHR bank
+    GxRom,
+    /// 32KB PRG bank and 8KB CHR bank, switched by writes to $4100-$5FFF
+    Nina03_06,
+}
+
+#[derive(Debug)]
+pub struct Discrete {
+    kind: Kind,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    mirroring: Mirroring,
+    /// whether writes to the latch are ANDed with the ROM byte being driven onto the bus at the same address
+    has_bus_conflicts: bool,
+
+    /// 16KB banks at $8000 and $C000
+    prg_banks: [usize; 2],
+    /// 4KB banks at $0000 and $1000
+    chr_banks: [usize; 2],
+}
+
+impl Discrete {
+    pub fn new(config: BoardConfig, kind: Kind) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        let chr_ram_size = if kind == Kind::CpRom {
+            config.chr_ram_size.max(CPROM_CHR_RAM_SIZE)
+        } else {
+            config.chr_ram_size
+        };
+        let prg_ram_size = if kind == Kind::Nina001 {
+            config.prg_ram_size.max(config.prg_nvram_size).max(NINA_001_PRG_RAM_SIZE)
+        } else {
+            0
+        };
+        let num_prg_banks = config.prg_rom.len().div_ceil(PRG_BANK_SIZE);
+        Self {
+            kind,
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; prg_ram_size],
+            has_battery: kind == Kind::Nina001 && config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            mirroring: if kind == Kind::AxRom { Mirroring::SingleScreenLower } else { config.mirroring },
+            // NES 2.0 submapper 2 marks the boards with bus conflicts for the mappers which come both ways
+            has_bus_conflicts: config.submapper == 2 && matches!(kind, Kind::UxRom | Kind::CnRom | Kind::AxRom),
+            prg_banks: if kind == Kind::UxRom { [0, num_prg_banks.saturating_sub(1)] } else { [0, 1] },
+            chr_banks: [0, 1],
+        }
+    }
+
+    fn set_prg_32kb(&mut self, bank: u8) {
+        self.prg_banks = [bank as usize * 2, bank as usize * 2 + 1];
+    }
+
+    fn set_chr_8kb(&mut self, bank: u8) {
+        self.chr_banks = [bank as usize * 2, bank as usize * 2 + 1];
+    }
+
+    /// Load the latch, or one of the NINA-001's registers
+    fn write_latch(&mut self, addr: u16, value: u8) {
+        match self.kind {
+            Kind::UxRom => self.prg_banks[0] = value as usize,
+            Kind::CnRom => self.set_chr_8kb(value),
+            Kind::AxRom => {
+                self.set_prg_32kb(value & 0x07);
+                self.mirroring = if value & 0x10 == 0 {
+                    Mirroring::SingleScreenLower
+                } else {
+                    Mirroring::SingleScreenUpper
+                };
+            }
+            Kind::ColorDreams => {
+                self.set_prg_32kb(value & 0x03);
+                self.set_chr_8kb(value >> 4);
+            }
+            Kind::CpRom => self.chr_banks[1] = value as usize & 0x03,
+            Kind::BnRom => self.set_prg_32kb(value),
+            Kind::Nina001 => match addr {
+                0x7ffd => self.set_prg_32kb(value & 0x01),
+                0x7ffe => self.chr_banks[0] = value as usize & 0x0f,
+                _ => self.chr_banks[1] = value as usize & 0x0f,
+            },
+            Kind::GxRom => {
+                self.set_prg_32kb((value >> 4) & 0x03);
+                self.set_chr_8kb(value & 0x03);
+            }
+            Kind::Nina03_06 => {
+                self.set_prg_32kb((value >> 3) & 0x01);
+                self.set_chr_8kb(value & 0x07);
+            }
+        }
+    }
+
+    /// Whether a write to an address loads the latch
+    const fn is_latch(&self, addr: u16) -> bool {
+        match self.kind {
+            Kind::Nina001 => matches!(addr, 0x7ffd..=0x7fff),
+            // only A8 and the top three address lines are decoded
+            Kind::Nina03_06 => addr & 0xe100 == 0x4100,
+            _ => addr >= 0x8000,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        self.prg_banks[(addr as usize >> 14) & 1] * PRG_BANK_SIZE + (addr as usize & 0x3fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[(addr as usize >> 12) & 1] * CHR_BANK_SIZE + (addr as usize & 0x0fff)
+    }
+}
+
+impl Board for Discrete {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff => read_mirrored(&self.prg_ram, addr as usize - 0x6000),
+            0x8000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        // the NINA-001's registers are written through to the RAM underneath them
+        if let 0x6000..=0x7fff = addr {
+            write_mirrored(&mut self.prg_ram, addr as usize - 0x6000, value);
+        }
+        if self.is_latch(addr) {
+            let value = if self.has_bus_conflicts { value & self.cpu_read(addr) } else { value };
+            self.write_latch(addr, value);
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A board with each 16KB PRG bank and 4KB CHR bank filled with its number, or with CHR RAM if `num_chr_banks` is 0
+    fn board(kind: Kind, submapper: u8, num_prg_banks: u8, num_chr_banks: u8) -> Discrete {
+        let config = BoardConfig {
+            mapper: 0,
+            submapper,
+            prg_rom: (0..num_prg_banks).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..num_chr_banks).flat_map(|bank| [bank; CHR_BANK_SIZE]).collect(),
+            chr_ram_size: if num_chr_banks == 0 { 0x2000 } else { 0 },
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Vertical,
+        };
+        Discrete::new(config, kind)
+    }
+
+    #[test]
+    fn uxrom_switches_16kb_at_8000_with_the_last_bank_fixed() {
+        let mut uxrom = board(Kind::UxRom, 0, 8, 0);
+        assert_eq!((uxrom.cpu_read(0x8000), uxrom.cpu_read(0xc000)), (0, 7));
+        uxrom.cpu_write(0x8000, 3);
+        assert_eq!((uxrom.cpu_read(0x8000), uxrom.cpu_read(0xffff)), (3, 7));
+    }
+
+    #[test]
+    fn cnrom_switches_8kb_of_chr() {
+        let mut cnrom = board(Kind::CnRom, 0, 2, 8);
+        cnrom.cpu_write(0x8000, 2);
+        assert_eq!((cnrom.ppu_read(0x0000), cnrom.ppu_read(0x1fff)), (4, 5));
+    }
+
+    #[test]
+    fn axrom_switches_32kb_and_the_single_screen_nametable() {
+        let mut axrom = board(Kind::AxRom, 0, 8, 0);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+        axrom.cpu_write(0x8000, 0x12);
+        assert_eq!((axrom.cpu_read(0x8000), axrom.cpu_read(0xc000)), (4, 5));
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn gxrom_and_color_dreams_take_their_banks_from_opposite_nibbles() {
+        let mut gxrom = board(Kind::GxRom, 0, 8, 8);
+        gxrom.cpu_write(0x8000, 0x31);
+        assert_eq!((gxrom.cpu_read(0x8000), gxrom.ppu_read(0x0000)), (6, 2));
+
+        let mut color_dreams = board(Kind::ColorDreams, 0, 8, 8);
+        color_dreams.cpu_write(0x8000, 0x13);
+        assert_eq!((color_dreams.cpu_read(0x8000), color_dreams.ppu_read(0x0000)), (6, 2));
+    }
+
+    #[test]
+    fn cprom_switches_chr_ram_at_1000_only() {
+        let mut cprom = board(Kind::CpRom, 0, 2, 0);
+        cprom.ppu_write(0x0000, 0x11);
+        cprom.ppu_write(0x1000, 0x22);
+        cprom.cpu_write(0x8000, 2);
+        assert_eq!((cprom.ppu_read(0x0000), cprom.ppu_read(0x1000)), (0x11, 0));
+        cprom.cpu_write(0x8000, 1);
+        assert_eq!(cprom.ppu_read(0x1000), 0x22);
+    }
+
+    #[test]
+    fn nina_001_registers_are_at_the_top_of_prg_ram() {
+        let mut nina = board(Kind::Nina001, 1, 4, 8);
+        nina.cpu_write(0x7ffd, 1);
+        nina.cpu_write(0x7ffe, 5);
+        nina.cpu_write(0x7fff, 6);
+        assert_eq!(nina.cpu_read(0x8000), 2);
+        assert_eq!((nina.ppu_read(0x0000), nina.ppu_read(0x1000)), (5, 6));
+        // and are written through to the RAM underneath
+        assert_eq!(nina.cpu_read(0x7ffe), 5);
+
+        // the rest of PRG RAM and ROM are left alone
+        nina.cpu_write(0x6000, 0);
+        nina.cpu_write(0x8000, 0);
+        assert_eq!(nina.cpu_read(0x8000), 2);
+    }
+
+    #[test]
+    fn nina_03_06_decodes_only_a8_and_the_top_address_lines() {
+        let mut nina = board(Kind::Nina03_06, 0, 4, 16);
+        nina.cpu_write(0x4100, 0x0b);
+        assert_eq!((nina.cpu_read(0x8000), nina.ppu_read(0x0000)), (2, 6));
+        nina.cpu_write(0x5fff, 0x01);
+        assert_eq!((nina.cpu_read(0x8000), nina.ppu_read(0x0000)), (0, 2));
+        for addr in [0x4200, 0x8000] {
+            nina.cpu_write(addr, 0x0f);
+            assert_eq!(nina.cpu_read(0x8000), 0, "${addr:04X} loaded the latch");
+        }
+    }
+
+    #[test]
+    fn bus_conflicts_and_the_written_value_with_rom() {
+        // $8000 holds bank 0's zeros and $C000 the last bank's 7s
+        let mut uxrom = board(Kind::UxRom, 2, 8, 0);
+        uxrom.cpu_write(0x8000, 5);
+        assert_eq!(uxrom.cpu_read(0x8000), 0);
+        uxrom.cpu_write(0xc000, 5);
+        assert_eq!(uxrom.cpu_read(0x8000), 5);
+        uxrom.cpu_write(0xc000, 0x0e);
+        assert_eq!(uxrom.cpu_read(0x8000), 6);
+    }
+
+    #[test]
+    fn boards_without_bus_conflicts_take_the_written_value() {
+        for submapper in [0, 1] {
+            let mut uxrom = board(Kind::UxRom, submapper, 8, 0);
+            uxrom.cpu_write(0x8000, 5);
+            assert_eq!(uxrom.cpu_read(0x8000), 5, "submapper {submapper}");
+        }
+    }
+}
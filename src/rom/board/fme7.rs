@@ -0,0 +1,226 @@
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// $6000 bank (command 8) bits
+const PRG_RAM_SELECT: u8 = 0x40;
+const PRG_RAM_ENABLE: u8 = 0x80;
+
+/// IRQ control (command D) bits
+const IRQ_ENABLE: u8 = 0x01;
+const IRQ_COUNTER_ENABLE: u8 = 0x80;
+
+/// Sunsoft's FME-7 and 5B: four 8KB PRG banks, one of which can be PRG RAM, eight 1KB CHR banks and a 16-bit CPU
+/// cycle counter which raises an IRQ. Registers are written through a command port at $8000 and a parameter port at
+/// $A000. The 5B adds three square wave channels, handled by the APU at $C000 and $E000.
+#[derive(Debug)]
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+
+    command: u8,
+    chr_banks: [u8; 8],
+    /// the bank at $6000, then those at $8000, $A000 and $C000
+    prg_banks: [u8; 4],
+    mirroring: Mirroring,
+
+    irq_control: u8,
+    irq_counter: u16,
+    is_irq_pending: bool,
+}
+
+impl Fme7 {
+    pub fn new(config: BoardConfig) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        Self {
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; config.prg_ram_size.max(config.prg_nvram_size)],
+            has_battery: config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: config.mirroring,
+            irq_control: 0,
+            irq_counter: 0,
+            is_irq_pending: false,
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+            0x8..=0xb => self.prg_banks[self.command as usize - 0x8] = value,
+            0xc => {
+                self.mirroring = match value & 3 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xd => {
+                self.irq_control = value;
+                self.is_irq_pending = false;
+            }
+            0xe => self.irq_counter = (self.irq_counter & 0xff00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00ff) | ((value as u16) << 8),
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x6000..=0xdfff => self.prg_banks[(addr as usize - 0x6000) >> 13] as usize & 0x3f,
+            _ => self.prg_rom.len().div_ceil(PRG_BANK_SIZE).saturating_sub(1),
+        };
+        bank * PRG_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> usize {
+        (self.prg_banks[0] as usize & 0x3f) * PRG_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[(addr as usize >> 10) & 7] as usize * CHR_BANK_SIZE + (addr as usize & 0x03ff)
+    }
+
+    const fn is_prg_ram_mapped(&self) -> bool {
+        self.prg_banks[0] & PRG_RAM_SELECT != 0
+    }
+
+    const fn is_prg_ram_enabled(&self) -> bool {
+        self.prg_banks[0] & (PRG_RAM_SELECT | PRG_RAM_ENABLE) == PRG_RAM_SELECT | PRG_RAM_ENABLE
+    }
+}
+
+impl Board for Fme7 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => read_mirrored(&self.prg_ram, self.prg_ram_offset(addr)),
+            // PRG RAM that's selected but disabled reads as open bus
+            0x6000..=0x7fff if self.is_prg_ram_mapped() => 0,
+            0x6000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                let offset = self.prg_ram_offset(addr);
+                write_mirrored(&mut self.prg_ram, offset, value);
+            }
+            0x8000..=0x9fff => self.command = value & 0x0f,
+            0xa000..=0xbfff => self.write_parameter(value),
+            // $C000-$FFFF is the 5B's sound
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_control & IRQ_COUNTER_ENABLE == 0 {
+            return;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+        if self.irq_counter == 0xffff && self.irq_control & IRQ_ENABLE != 0 {
+            self.is_irq_pending = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.is_irq_pending
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A board whose PRG ROM holds its 8KB bank number in every byte
+    fn board() -> Fme7 {
+        let config = BoardConfig {
+            mapper: 69,
+            submapper: 0,
+            prg_rom: (0..16).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect(),
+            chr_rom: vec![0; 0x2000],
+            chr_ram_size: 0,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Vertical,
+        };
+        Fme7::new(config)
+    }
+
+    fn write_command(fme7: &mut Fme7, command: u8, value: u8) {
+        fme7.cpu_write(0x8000, command);
+        fme7.cpu_write(0xa000, value);
+    }
+
+    #[test]
+    fn prg_rom_or_ram_at_6000() {
+        let mut fme7 = board();
+        write_command(&mut fme7, 0x8, 5);
+        assert_eq!(fme7.cpu_read(0x6000), 5);
+        fme7.cpu_write(0x6000, 0xaa);
+        assert_eq!(fme7.cpu_read(0x6000), 5);
+
+        write_command(&mut fme7, 0x8, PRG_RAM_SELECT | PRG_RAM_ENABLE);
+        fme7.cpu_write(0x6000, 0xaa);
+        assert_eq!(fme7.cpu_read(0x6000), 0xaa);
+
+        write_command(&mut fme7, 0x8, PRG_RAM_SELECT);
+        assert_eq!(fme7.cpu_read(0x6000), 0);
+    }
+
+    #[test]
+    fn irq_on_counter_underflow() {
+        let mut fme7 = board();
+        write_command(&mut fme7, 0xe, 1);
+        write_command(&mut fme7, 0xf, 0);
+        write_command(&mut fme7, 0xd, IRQ_ENABLE | IRQ_COUNTER_ENABLE);
+        fme7.cpu_clock();
+        assert!(!fme7.irq());
+        fme7.cpu_clock();
+        assert!(fme7.irq());
+
+        // acknowledged by writing the control
+        write_command(&mut fme7, 0xd, IRQ_COUNTER_ENABLE);
+        assert!(!fme7.irq());
+    }
+}
@@ -0,0 +1,365 @@
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x1000;
+/// PRG ROM past this needs the outer bank bit that SUROM and SXROM take from the CHR registers
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+
+/// Bit of a write to $8000-$FFFF which resets the shift register
+const RESET: u8 = 0x80;
+/// Control bits set by a reset, which fix the last PRG bank at $C000
+const CONTROL_RESET: u8 = 0x0c;
+/// Control bit for switching CHR in two 4KB banks rather than one 8KB bank
+const CONTROL_CHR_4KB: u8 = 0x10;
+/// PRG bank register bit which disables PRG RAM on the MMC1B and later
+const PRG_RAM_DISABLE: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revision {
+    /// PRG RAM is always enabled
+    Mmc1a,
+    Mmc1b,
+}
+
+/// Nintendo's MMC1, on the SxROM boards. Registers are written a bit at a time through a serial port, and boards with
+/// 8KB of CHR RAM reuse the CHR bank bits for larger PRG ROM and RAM:
+/// - SNROM: bit 4 disables PRG RAM
+/// - SOROM: bit 3 switches between two 8KB banks of PRG RAM
+/// - SUROM: bit 4 switches between the two 256KB halves of 512KB of PRG ROM
+/// - SXROM: as SUROM, and bits 2-3 switch between four 8KB banks of PRG RAM
+#[derive(Debug)]
+pub struct Mmc1 {
+    revision: Revision,
+    prg_rom: Vec<u8>,
+    /// volatile PRG RAM followed by any battery-backed RAM
+    prg_ram: Vec<u8>,
+    prg_nvram_size: usize,
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    /// SEROM and SHROM wire all 32KB of PRG ROM straight through, ignoring the PRG bank
+    is_prg_fixed: bool,
+
+    shift: u8,
+    num_bits_shifted: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+
+    /// CPU cycles since power-on, for ignoring writes on consecutive cycles
+    cycle: u64,
+    last_write_cycle: Option<u64>,
+    /// PPU A12, which picks the CHR register used for the extra PRG bits in 4KB mode
+    is_a12_high: bool,
+}
+
+impl Mmc1 {
+    pub fn new(config: BoardConfig, revision: Revision) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        Self {
+            revision,
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; config.prg_ram_size + config.prg_nvram_size],
+            prg_nvram_size: config.prg_nvram_size,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            is_prg_fixed: false,
+            shift: 0,
+            num_bits_shifted: 0,
+            control: CONTROL_RESET,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+            cycle: 0,
+            last_write_cycle: None,
+            is_a12_high: false,
+        }
+    }
+
+    /// Ignore the PRG bank register, for SEROM and SHROM
+    pub const fn with_fixed_prg(mut self) -> Self {
+        self.is_prg_fixed = true;
+        self
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9fff => self.control = value,
+            0xa000..=0xbfff => self.chr_banks[0] = value,
+            0xc000..=0xdfff => self.chr_banks[1] = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    /// The CHR register whose upper bits the SxROM boards use for PRG, which is the one for the pattern table the PPU
+    /// last accessed
+    const fn board_bits(&self) -> u8 {
+        if self.control & CONTROL_CHR_4KB != 0 && self.is_a12_high {
+            self.chr_banks[1]
+        } else {
+            self.chr_banks[0]
+        }
+    }
+
+    /// Whether the board uses the CHR register bits for PRG rather than CHR
+    fn has_board_bits(&self) -> bool {
+        self.is_chr_ram && self.chr.len() <= 0x2000
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        let is_disabled_by_mmc = self.revision != Revision::Mmc1a && self.prg_bank & PRG_RAM_DISABLE != 0;
+        // SNROM's extra enable, which is only there on boards with 256KB of PRG ROM or less and 8KB of RAM
+        let is_disabled_by_board = self.has_board_bits()
+            && self.prg_rom.len() <= PRG_OUTER_BANK_SIZE
+            && self.prg_ram.len() <= PRG_RAM_BANK_SIZE
+            && self.board_bits() & 0x10 != 0;
+        !self.prg_ram.is_empty() && !is_disabled_by_mmc && !is_disabled_by_board
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> usize {
+        let bank = match self.prg_ram.len() / PRG_RAM_BANK_SIZE {
+            // SXROM
+            4.. if self.has_board_bits() => (self.board_bits() >> 2) & 3,
+            // SOROM
+            2..=3 if self.has_board_bits() => (self.board_bits() >> 3) & 1,
+            _ => 0,
+        } as usize;
+        bank * PRG_RAM_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        if self.is_prg_fixed {
+            return addr as usize & 0x7fff;
+        }
+
+        let bank = self.prg_bank as usize & 0x0f;
+        let is_upper_half = addr >= 0xc000;
+        let bank = match ((self.control >> 2) & 3, is_upper_half) {
+            // 32KB mode ignores the low bit of the bank
+            (0 | 1, _) => (bank & 0x0e) | is_upper_half as usize,
+            // first bank fixed at $8000
+            (2, false) => 0,
+            (2, true) => bank,
+            // last bank fixed at $C000
+            (_, false) => bank,
+            (_, true) => 0x0f,
+        };
+        // SUROM and SXROM's 256KB outer bank
+        let outer_bank = if self.has_board_bits() && self.prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            (self.board_bits() as usize & 0x10) >> 4
+        } else {
+            0
+        };
+        outer_bank * PRG_OUTER_BANK_SIZE + bank * PRG_BANK_SIZE + (addr as usize & 0x3fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = if self.control & CONTROL_CHR_4KB != 0 {
+            self.chr_banks[(addr >> 12) as usize & 1] as usize
+        } else {
+            (self.chr_banks[0] as usize & 0x1e) | ((addr >> 12) as usize & 1)
+        };
+        bank * CHR_BANK_SIZE + (addr as usize & 0x0fff)
+    }
+}
+
+impl Board for Mmc1 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => read_mirrored(&self.prg_ram, self.prg_ram_offset(addr)),
+            0x8000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                let offset = self.prg_ram_offset(addr);
+                write_mirrored(&mut self.prg_ram, offset, value);
+            }
+            0x8000.. => {
+                // the serial port ignores the second of writes on consecutive cycles, such as the dummy write of a
+                // read-modify-write instruction. The CPU runs instructions all at once, so those can land on the same
+                // cycle here.
+                let is_consecutive = self.last_write_cycle.is_some_and(|cycle| self.cycle - cycle <= 1);
+                self.last_write_cycle = Some(self.cycle);
+                if is_consecutive {
+                    return;
+                }
+
+                if value & RESET != 0 {
+                    self.shift = 0;
+                    self.num_bits_shifted = 0;
+                    self.control |= CONTROL_RESET;
+                    return;
+                }
+                self.shift |= (value & 1) << self.num_bits_shifted;
+                self.num_bits_shifted += 1;
+                if self.num_bits_shifted == 5 {
+                    self.write_register(addr, self.shift);
+                    self.shift = 0;
+                    self.num_bits_shifted = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn ppu_address(&mut self, addr: u16) {
+        self.is_a12_high = addr & 0x1000 != 0;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 3 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn cpu_clock(&mut self) {
+        self.cycle += 1;
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        (self.prg_nvram_size > 0).then(|| &self.prg_ram[self.prg_ram.len() - self.prg_nvram_size..])
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        let start = self.prg_ram.len() - self.prg_nvram_size;
+        let len = data.len().min(self.prg_nvram_size);
+        self.prg_ram[start..start + len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 128KB of PRG ROM with each 16KB bank filled with its number, and 8KB of CHR RAM
+    fn board() -> Mmc1 {
+        let config = BoardConfig {
+            mapper: 1,
+            submapper: 0,
+            prg_rom: (0..8).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect(),
+            chr_rom: Vec::new(),
+            chr_ram_size: 0x2000,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Horizontal,
+        };
+        Mmc1::new(config, Revision::Mmc1b)
+    }
+
+    /// Write a register through the serial port a bit at a time, low bit first, as games do with a few cycles between
+    fn write_serial(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.cpu_write(addr, (value >> bit) & 1);
+            mmc1.cpu_clock();
+            mmc1.cpu_clock();
+        }
+    }
+
+    #[test]
+    fn registers_are_written_after_five_bits() {
+        let mut mmc1 = board();
+        for bit in 0..4 {
+            mmc1.cpu_write(0xe000, (3 >> bit) & 1);
+            mmc1.cpu_clock();
+            mmc1.cpu_clock();
+            assert_eq!(mmc1.cpu_read(0x8000), 0, "the register was written after {} bits", bit + 1);
+        }
+        mmc1.cpu_write(0xe000, 0);
+        assert_eq!(mmc1.cpu_read(0x8000), 3);
+        // the last bank stays fixed at $C000
+        assert_eq!(mmc1.cpu_read(0xc000), 7);
+    }
+
+    #[test]
+    fn the_register_is_picked_by_the_last_write() {
+        let mut mmc1 = board();
+        for (i, addr) in [0x8000, 0x8000, 0xa000, 0xc000, 0xe000].into_iter().enumerate() {
+            mmc1.cpu_write(addr, (0x03 >> i) & 1);
+            mmc1.cpu_clock();
+            mmc1.cpu_clock();
+        }
+        assert_eq!(mmc1.cpu_read(0x8000), 3);
+        // the control register written to first is untouched
+        assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn reset_clears_the_shift_register_and_fixes_the_last_bank() {
+        let mut mmc1 = board();
+        // switch to 32KB mode
+        write_serial(&mut mmc1, 0x8000, 0x02);
+        write_serial(&mut mmc1, 0xe000, 0x02);
+        assert_eq!(mmc1.cpu_read(0xc000), 3);
+
+        mmc1.cpu_write(0xe000, 1);
+        mmc1.cpu_clock();
+        mmc1.cpu_clock();
+        mmc1.cpu_write(0x8000, RESET);
+        mmc1.cpu_clock();
+        mmc1.cpu_clock();
+        assert_eq!(mmc1.cpu_read(0xc000), 7);
+
+        write_serial(&mut mmc1, 0xe000, 0x04);
+        assert_eq!(mmc1.cpu_read(0x8000), 4);
+    }
+
+    #[test]
+    fn writes_on_consecutive_cycles_are_ignored() {
+        let mut mmc1 = board();
+        // a read-modify-write instruction's dummy write of the old value, then the new value a cycle later
+        mmc1.cpu_write(0xe000, 1);
+        mmc1.cpu_clock();
+        mmc1.cpu_write(0xe000, 0);
+        mmc1.cpu_clock();
+        mmc1.cpu_clock();
+        for _ in 0..4 {
+            mmc1.cpu_write(0xe000, 0);
+            mmc1.cpu_clock();
+            mmc1.cpu_clock();
+        }
+        assert_eq!(mmc1.cpu_read(0x8000), 1);
+
+        // and so is a reset on the next cycle
+        mmc1.cpu_write(0xe000, 1);
+        mmc1.cpu_clock();
+        mmc1.cpu_write(0x8000, RESET);
+        mmc1.cpu_clock();
+        mmc1.cpu_clock();
+        for _ in 0..4 {
+            mmc1.cpu_write(0xe000, 0);
+            mmc1.cpu_clock();
+            mmc1.cpu_clock();
+        }
+        assert_eq!(mmc1.cpu_read(0x8000), 1);
+    }
+
+    #[test]
+    fn prg_ram_can_be_disabled_on_the_mmc1b() {
+        let mut mmc1 = board();
+        mmc1.cpu_write(0x6000, 0x55);
+        assert_eq!(mmc1.cpu_read(0x6000), 0x55);
+        write_serial(&mut mmc1, 0xe000, PRG_RAM_DISABLE);
+        assert_eq!(mmc1.cpu_read(0x6000), 0);
+    }
+}
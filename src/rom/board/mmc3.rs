@@ -0,0 +1,427 @@
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+/// The MMC6's RAM, inside the chip and mirrored across $7000-$7FFF
+const MMC6_RAM_SIZE: usize = 0x400;
+/// CPU cycles A12 has to stay low before the MMC3 counts it rising again, which filters out the toggling between
+/// sprite pattern and nametable fetches so that only the switch from background to sprite fetches is counted
+const A12_LOW_CYCLES: u8 = 3;
+
+/// Bank select bit which swaps the switchable and fixed PRG banks at $8000 and $C000
+const SELECT_PRG_MODE: u8 = 0x40;
+/// Bank select bit which swaps the 2KB and 1KB CHR banks between the pattern tables
+const SELECT_CHR_INVERSION: u8 = 0x80;
+/// Bank select bit which enables the MMC6's internal RAM
+const SELECT_MMC6_RAM_ENABLE: u8 = 0x20;
+
+/// PRG RAM protect bits on the MMC3
+const PROTECT_ENABLE: u8 = 0x80;
+const PROTECT_DENY_WRITES: u8 = 0x40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revision {
+    /// The MMC3A's "old" IRQ: a counter that reaches 0 only raises an IRQ by counting down to it or being reloaded
+    /// through $C001, so a latch of 0 raises just one
+    Mmc3a,
+    /// The MMC3B and MMC3C's "new" IRQ, raised on every clock that leaves the counter at 0
+    Mmc3c,
+    /// The "new" IRQ, with 1KB of RAM inside the chip in place of PRG RAM, protected a half at a time
+    Mmc6,
+}
+
+/// Nintendo's MMC3 and MMC6, on the TxROM and HKROM boards: two switchable 8KB PRG banks, six CHR banks, and a counter
+/// clocked by rising edges on PPU A12 which raises an IRQ partway down the screen
+#[derive(Debug)]
+pub struct Mmc3 {
+    revision: Revision,
+    prg_rom: Vec<u8>,
+    /// PRG RAM, or the MMC6's internal RAM
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+
+    bank_select: u8,
+    /// R0-R1 are 2KB CHR banks, R2-R5 1KB CHR banks and R6-R7 8KB PRG banks
+    banks: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_protect: u8,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    should_reload_irq: bool,
+    is_irq_enabled: bool,
+    is_irq_pending: bool,
+
+    is_a12_high: bool,
+    /// CPU cycles since A12 last went low, up to `A12_LOW_CYCLES`
+    a12_low_cycles: u8,
+}
+
+impl Mmc3 {
+    pub fn new(config: BoardConfig, revision: Revision) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        let prg_ram_size = if revision == Revision::Mmc6 {
+            MMC6_RAM_SIZE
+        } else {
+            config.prg_ram_size.max(config.prg_nvram_size)
+        };
+        Self {
+            revision,
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; prg_ram_size],
+            has_battery: config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: config.mirroring,
+            prg_ram_protect: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            should_reload_irq: false,
+            is_irq_enabled: false,
+            is_irq_pending: false,
+            is_a12_high: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        let is_odd = addr & 1 != 0;
+        match (addr, is_odd) {
+            (0x8000..=0x9fff, false) => self.bank_select = value,
+            (0x8000..=0x9fff, true) => self.banks[self.bank_select as usize & 7] = value,
+            (0xa000..=0xbfff, false) => {
+                self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            (0xa000..=0xbfff, true) => {
+                // the MMC6 ignores its protection bits while its RAM is disabled
+                if self.revision != Revision::Mmc6 || self.is_mmc6_ram_enabled() {
+                    self.prg_ram_protect = value;
+                }
+            }
+            (0xc000..=0xdfff, false) => self.irq_latch = value,
+            (0xc000..=0xdfff, true) => {
+                self.irq_counter = 0;
+                self.should_reload_irq = true;
+            }
+            (_, false) => {
+                self.is_irq_enabled = false;
+                self.is_irq_pending = false;
+            }
+            (_, true) => self.is_irq_enabled = true,
+        }
+    }
+
+    /// Clock the IRQ counter, on a filtered rising edge of A12
+    fn clock_irq_counter(&mut self) {
+        let was_reloaded_by_write = self.should_reload_irq;
+        let previous = self.irq_counter;
+        if self.irq_counter == 0 || self.should_reload_irq {
+            self.irq_counter = self.irq_latch;
+            self.should_reload_irq = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        let is_irq = self.irq_counter == 0
+            && match self.revision {
+                Revision::Mmc3a => previous != 0 || was_reloaded_by_write,
+                Revision::Mmc3c | Revision::Mmc6 => true,
+            };
+        if is_irq && self.is_irq_enabled {
+            self.is_irq_pending = true;
+        }
+    }
+
+    const fn is_mmc6_ram_enabled(&self) -> bool {
+        self.bank_select & SELECT_MMC6_RAM_ENABLE != 0
+    }
+
+    /// Whether the CPU can read and write PRG RAM at an address. The MMC6 protects each 512-byte half of its RAM
+    /// separately, with a pair of bits in $A001: bits 4-5 for $7000-$71FF and bits 6-7 for $7200-$73FF, the lower
+    /// of each pair enabling writes and the upper reads.
+    const fn prg_ram_access(&self, addr: u16) -> (bool, bool) {
+        match self.revision {
+            Revision::Mmc6 => {
+                let bits = if addr & 0x200 == 0 { self.prg_ram_protect >> 4 } else { self.prg_ram_protect >> 6 };
+                let is_enabled = self.is_mmc6_ram_enabled();
+                (is_enabled && bits & 2 != 0, is_enabled && bits & 1 != 0)
+            }
+            Revision::Mmc3a | Revision::Mmc3c => {
+                let is_enabled = self.prg_ram_protect & PROTECT_ENABLE != 0;
+                (is_enabled, is_enabled && self.prg_ram_protect & PROTECT_DENY_WRITES == 0)
+            }
+        }
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> usize {
+        match self.revision {
+            Revision::Mmc6 => addr as usize & (MMC6_RAM_SIZE - 1),
+            Revision::Mmc3a | Revision::Mmc3c => addr as usize & 0x1fff,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let last_bank = self.prg_rom.len().div_ceil(PRG_BANK_SIZE).saturating_sub(1);
+        let slot = (addr as usize >> 13) & 3;
+        // PRG mode 1 swaps $8000 and $C000
+        let slot = if self.bank_select & SELECT_PRG_MODE != 0 && slot & 1 == 0 { slot ^ 2 } else { slot };
+        let bank = match slot {
+            0 => self.banks[6] as usize,
+            1 => self.banks[7] as usize,
+            2 => last_bank.saturating_sub(1),
+            _ => last_bank,
+        };
+        bank * PRG_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        // CHR inversion swaps the pattern tables
+        let addr = if self.bank_select & SELECT_CHR_INVERSION != 0 { addr ^ 0x1000 } else { addr };
+        let slot = (addr as usize >> 10) & 7;
+        let bank = match slot {
+            // 2KB banks ignore the low bit
+            0..=3 => (self.banks[slot / 2] as usize & !1) | (slot & 1),
+            _ => self.banks[slot - 2] as usize,
+        };
+        bank * CHR_BANK_SIZE + (addr as usize & 0x03ff)
+    }
+}
+
+impl Board for Mmc3 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            // the MMC6's RAM is only at $7000-$7FFF
+            0x6000..=0x6fff if self.revision == Revision::Mmc6 => 0,
+            0x6000..=0x7fff => {
+                let (is_readable, _) = self.prg_ram_access(addr);
+                if is_readable {
+                    read_mirrored(&self.prg_ram, self.prg_ram_offset(addr))
+                } else {
+                    0
+                }
+            }
+            0x8000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x6fff if self.revision == Revision::Mmc6 => (),
+            0x6000..=0x7fff => {
+                let (_, is_writable) = self.prg_ram_access(addr);
+                if is_writable {
+                    let offset = self.prg_ram_offset(addr);
+                    write_mirrored(&mut self.prg_ram, offset, value);
+                }
+            }
+            0x8000.. => self.write_register(addr, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn ppu_address(&mut self, addr: u16) {
+        let is_a12_high = addr & 0x1000 != 0;
+        if is_a12_high && !self.is_a12_high && self.a12_low_cycles >= A12_LOW_CYCLES {
+            self.clock_irq_counter();
+        }
+        if is_a12_high {
+            self.a12_low_cycles = 0;
+        }
+        self.is_a12_high = is_a12_high;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.is_a12_high {
+            self.a12_low_cycles = (self.a12_low_cycles + 1).min(A12_LOW_CYCLES);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.is_irq_pending
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(revision: Revision) -> Mmc3 {
+        let config = BoardConfig {
+            mapper: 4,
+            submapper: 0,
+            prg_rom: vec![0; 0x8000],
+            chr_rom: vec![0; 0x2000],
+            chr_ram_size: 0,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Vertical,
+        };
+        Mmc3::new(config, revision)
+    }
+
+    fn mmc6() -> Mmc3 {
+        let mut mmc6 = board(Revision::Mmc6);
+        mmc6.cpu_write(0x8000, SELECT_MMC6_RAM_ENABLE);
+        mmc6
+    }
+
+    /// Set up the IRQ counter to be reloaded from a latch on its next clock, with IRQs enabled
+    fn start_irq(board: &mut Mmc3, latch: u8) {
+        board.cpu_write(0xc000, latch);
+        board.cpu_write(0xc001, 0);
+        board.cpu_write(0xe001, 0);
+    }
+
+    /// Clock the IRQ counter the way a scanline does, with A12 rising after being low for a few CPU cycles
+    fn clock_scanline(board: &mut Mmc3) {
+        board.ppu_address(0x0000);
+        for _ in 0..A12_LOW_CYCLES {
+            board.cpu_clock();
+        }
+        board.ppu_address(0x1000);
+    }
+
+    /// Clock the counter for a number of scanlines, returning those after which an IRQ was pending, acknowledging it
+    fn irq_scanlines(board: &mut Mmc3, scanlines: usize) -> Vec<usize> {
+        (1..=scanlines)
+            .filter(|_| {
+                clock_scanline(board);
+                let is_irq = board.irq();
+                if is_irq {
+                    board.cpu_write(0xe000, 0);
+                    board.cpu_write(0xe001, 0);
+                }
+                is_irq
+            })
+            .collect()
+    }
+
+    #[test]
+    fn irq_is_raised_when_the_counter_reaches_zero() {
+        for revision in [Revision::Mmc3a, Revision::Mmc3c] {
+            let mut mmc3 = board(revision);
+            start_irq(&mut mmc3, 3);
+            // the first clock reloads the counter, and it counts down from there
+            assert_eq!(irq_scanlines(&mut mmc3, 10), [4, 8], "{revision:?}");
+        }
+    }
+
+    #[test]
+    fn irq_stays_pending_until_acknowledged() {
+        let mut mmc3 = board(Revision::Mmc3c);
+        start_irq(&mut mmc3, 0);
+        clock_scanline(&mut mmc3);
+        clock_scanline(&mut mmc3);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xe000, 0);
+        assert!(!mmc3.irq());
+        // and none are raised while disabled
+        clock_scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn new_irq_with_zero_latch_fires_every_scanline() {
+        let mut mmc3 = board(Revision::Mmc3c);
+        start_irq(&mut mmc3, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 4), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn old_irq_with_zero_latch_fires_once_per_reload() {
+        let mut mmc3 = board(Revision::Mmc3a);
+        start_irq(&mut mmc3, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 4), [1]);
+        mmc3.cpu_write(0xc001, 0);
+        assert_eq!(irq_scanlines(&mut mmc3, 4), [1]);
+    }
+
+    #[test]
+    fn a12_rises_too_soon_after_falling_are_not_counted() {
+        let mut mmc3 = board(Revision::Mmc3c);
+        start_irq(&mut mmc3, 1);
+        clock_scanline(&mut mmc3);
+        // the quick toggling between sprite pattern and nametable fetches
+        for _ in 0..8 {
+            mmc3.ppu_address(0x2000);
+            mmc3.cpu_clock();
+            mmc3.ppu_address(0x1000);
+        }
+        assert!(!mmc3.irq());
+        clock_scanline(&mut mmc3);
+        assert!(mmc3.irq());
+    }
+
+    #[test]
+    fn mmc6_protects_each_half_of_its_ram_separately() {
+        // the low half is controlled by bits 4 (write) and 5 (read), the high half by bits 6 (write) and 7 (read)
+        for (addr, write_bit, read_bit) in [(0x7000, 0x10, 0x20), (0x7200, 0x40, 0x80)] {
+            let mut mmc6 = mmc6();
+
+            mmc6.cpu_write(0xa001, write_bit);
+            mmc6.cpu_write(addr, 0x55);
+            assert_eq!(mmc6.cpu_read(addr), 0, "${addr:04X} should not be readable");
+
+            mmc6.cpu_write(0xa001, read_bit);
+            assert_eq!(mmc6.cpu_read(addr), 0x55, "${addr:04X} should have been written");
+            mmc6.cpu_write(addr, 0xaa);
+            assert_eq!(mmc6.cpu_read(addr), 0x55, "${addr:04X} should not be writable");
+
+            // the other half is untouched by this half's bits
+            let other = addr ^ 0x200;
+            mmc6.cpu_write(0xa001, write_bit | read_bit);
+            mmc6.cpu_write(other, 0x11);
+            assert_eq!(mmc6.cpu_read(other), 0, "${other:04X} should not be readable");
+        }
+    }
+
+    #[test]
+    fn mmc6_ignores_protection_while_its_ram_is_disabled() {
+        let mut mmc6 = mmc6();
+        mmc6.cpu_write(0xa001, 0xf0);
+        mmc6.cpu_write(0x7000, 0x55);
+        mmc6.cpu_write(0x8000, 0);
+        assert_eq!(mmc6.cpu_read(0x7000), 0);
+
+        // writes to $A001 are ignored too, so re-enabling the RAM restores the old protection
+        mmc6.cpu_write(0xa001, 0);
+        mmc6.cpu_write(0x8000, SELECT_MMC6_RAM_ENABLE);
+        assert_eq!(mmc6.cpu_read(0x7000), 0x55);
+    }
+}
@@ -0,0 +1,237 @@
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_WINDOW_SIZE: usize = 0x0800;
+
+/// The IRQ counter counts up to this and stops, holding the IRQ until it's written
+const IRQ_COUNTER_MAX: u16 = 0x7fff;
+/// IRQ counter high byte bit which enables counting
+const IRQ_ENABLE: u8 = 0x80;
+
+/// PRG RAM write protection ($F800): writes are only allowed at all with this in the high bits, and each of the low
+/// four bits then protects a 2KB window
+const PROTECT_KEY_MASK: u8 = 0xf0;
+const PROTECT_KEY: u8 = 0x40;
+
+/// CHR and nametable register values from this up select one of the console's nametables rather than CHR ROM
+const CIRAM_BANKS: u8 = 0xe0;
+
+/// Namco's 163: three 8KB PRG banks, eight 1KB CHR banks, four nametable registers, a 15-bit CPU cycle counter which
+/// raises an IRQ and, handled by the APU, up to eight wavetable sound channels. The sound chip's registers, $4800 and
+/// $F800, are shared with the board: $F800 also protects PRG RAM.
+///
+/// CHR ROM can't be mapped as nametables, nor the console's nametables as pattern tables, so only the nametable
+/// registers which pick the console's two nametables in one of the usual arrangements are followed.
+#[derive(Debug)]
+pub struct N163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    /// the mirroring to fall back on if the nametable registers pick CHR ROM
+    fixed_mirroring: Mirroring,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    prg_ram_protect: u8,
+
+    irq_counter: u16,
+    is_irq_enabled: bool,
+}
+
+impl N163 {
+    pub fn new(config: BoardConfig) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        Self {
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; config.prg_ram_size.max(config.prg_nvram_size)],
+            has_battery: config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            fixed_mirroring: config.mirroring,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [CIRAM_BANKS; 4],
+            prg_ram_protect: 0,
+            irq_counter: 0,
+            is_irq_enabled: false,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            // writing either half of the counter acknowledges the IRQ
+            0x5000..=0x57ff => self.irq_counter = (self.irq_counter & 0x7f00) | value as u16,
+            0x5800..=0x5fff => {
+                self.irq_counter = (self.irq_counter & 0x00ff) | ((value as u16 & 0x7f) << 8);
+                self.is_irq_enabled = value & IRQ_ENABLE != 0;
+            }
+            0x8000..=0xbfff => self.chr_banks[(addr as usize - 0x8000) >> 11] = value,
+            0xc000..=0xdfff => self.nametable_banks[(addr as usize - 0xc000) >> 11] = value,
+            // bit 6 of $E000 disables the sound, and bits 6-7 of $E800 map the console's nametables as pattern tables,
+            // neither of which is emulated
+            0xe000..=0xe7ff => self.prg_banks[0] = value & 0x3f,
+            0xe800..=0xefff => self.prg_banks[1] = value & 0x3f,
+            0xf000..=0xf7ff => self.prg_banks[2] = value & 0x3f,
+            // $F800 also sets the sound chip's address
+            _ => self.prg_ram_protect = value,
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let slot = (addr as usize >> 13) & 3;
+        let bank = match slot {
+            0..=2 => self.prg_banks[slot] as usize,
+            _ => self.prg_rom.len().div_ceil(PRG_BANK_SIZE).saturating_sub(1),
+        };
+        bank * PRG_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[(addr as usize >> 10) & 7] as usize * CHR_BANK_SIZE + (addr as usize & 0x03ff)
+    }
+
+    const fn is_prg_ram_writable(&self, addr: u16) -> bool {
+        let window = (addr as usize - 0x6000) / PRG_RAM_WINDOW_SIZE;
+        self.prg_ram_protect & PROTECT_KEY_MASK == PROTECT_KEY && self.prg_ram_protect & (1 << window) == 0
+    }
+}
+
+impl Board for N163 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x5000..=0x57ff => self.irq_counter as u8,
+            0x5800..=0x5fff => ((self.irq_counter >> 8) as u8) | if self.is_irq_enabled { IRQ_ENABLE } else { 0 },
+            0x6000..=0x7fff => read_mirrored(&self.prg_ram, addr as usize - 0x6000),
+            0x8000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5000..=0x5fff | 0x8000.. => self.write_register(addr, value),
+            0x6000..=0x7fff if self.is_prg_ram_writable(addr) => {
+                write_mirrored(&mut self.prg_ram, addr as usize - 0x6000, value);
+            }
+            // $4800-$4FFF is the sound chip's
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.nametable_banks.iter().any(|&bank| bank < CIRAM_BANKS) {
+            return self.fixed_mirroring;
+        }
+        match self.nametable_banks.map(|bank| bank & 1) {
+            [0, 1, 0, 1] => Mirroring::Vertical,
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => self.fixed_mirroring,
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.is_irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.is_irq_enabled && self.irq_counter == IRQ_COUNTER_MAX
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board() -> N163 {
+        let config = BoardConfig {
+            mapper: 19,
+            submapper: 0,
+            prg_rom: vec![0; 0x20000],
+            chr_rom: vec![0; 0x20000],
+            chr_ram_size: 0,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Horizontal,
+        };
+        N163::new(config)
+    }
+
+    #[test]
+    fn irq_counts_up_to_its_maximum_and_holds() {
+        let mut n163 = board();
+        n163.cpu_write(0x5000, 0xfe);
+        n163.cpu_write(0x5800, 0x7f | IRQ_ENABLE);
+        n163.cpu_clock();
+        assert!(n163.irq());
+        n163.cpu_clock();
+        assert!(n163.irq());
+        assert_eq!(n163.cpu_read(0x5000), 0xff);
+        assert_eq!(n163.cpu_read(0x5800), 0x7f | IRQ_ENABLE);
+
+        // acknowledged by writing the counter
+        n163.cpu_write(0x5800, IRQ_ENABLE);
+        assert!(!n163.irq());
+    }
+
+    #[test]
+    fn prg_ram_write_protection() {
+        let mut n163 = board();
+        n163.cpu_write(0x6000, 1);
+        assert_eq!(n163.cpu_read(0x6000), 0);
+
+        // the key in the high bits, with the second 2KB window protected
+        n163.cpu_write(0xf800, PROTECT_KEY | 0x02);
+        n163.cpu_write(0x6000, 1);
+        n163.cpu_write(0x6800, 2);
+        assert_eq!(n163.cpu_read(0x6000), 1);
+        assert_eq!(n163.cpu_read(0x6800), 0);
+    }
+
+    #[test]
+    fn nametable_registers() {
+        let mut n163 = board();
+        for (addr, bank) in [(0xc000, 0xe0), (0xc800, 0xe1), (0xd000, 0xe0), (0xd800, 0xe1)] {
+            n163.cpu_write(addr, bank);
+        }
+        assert_eq!(n163.mirroring(), Mirroring::Vertical);
+
+        // CHR ROM as a nametable isn't emulated, so the header's mirroring is used
+        n163.cpu_write(0xd800, 0x00);
+        assert_eq!(n163.mirroring(), Mirroring::Horizontal);
+    }
+}
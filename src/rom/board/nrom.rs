@@ -0,0 +1,73 @@
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+/// NROM: 16 or 32KB of PRG ROM and 8KB of CHR, with no bank switching. Family Basic adds PRG RAM at $6000.
+#[derive(Debug)]
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(config: BoardConfig) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        Self {
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; config.prg_ram_size.max(config.prg_nvram_size)],
+            has_battery: config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            mirroring: config.mirroring,
+        }
+    }
+}
+
+impl Board for Nrom {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff => read_mirrored(&self.prg_ram, addr as usize - 0x6000),
+            0x8000.. => read_mirrored(&self.prg_rom, addr as usize - 0x8000),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        if let 0x6000..=0x7fff = addr {
+            write_mirrored(&mut self.prg_ram, addr as usize - 0x6000, value);
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        // writes to CHR ROM are ignored
+        if self.is_chr_ram {
+            write_mirrored(&mut self.chr, addr as usize, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| addr as usize % self.chr.len())
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::{read_mirrored, write_mirrored, Board, Mirroring};
+
+/// PRG RAM at $6000-$7FFF, which every NSF player provides
+const PRG_RAM_SIZE: usize = 0x2000;
+const BANK_SIZE: usize = 0x1000;
+
+/// The cartridge an NSF player provides for a tune: its program in ROM, optionally in 4KB banks switched by writes to
+/// $5FF8-$5FFF, and 8KB of PRG RAM
+#[derive(Debug)]
+pub struct NsfBoard {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    /// banks mapped into $8000-$FFFF, for tunes that use bank switching
+    banks: Option<[u8; 8]>,
+}
+
+impl NsfBoard {
+    pub fn new(prg_rom: Vec<u8>, banks: Option<[u8; 8]>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            banks,
+        }
+    }
+}
+
+impl Board for NsfBoard {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff => read_mirrored(&self.prg_ram, addr as usize - 0x6000),
+            0x8000.. => {
+                let offset = match self.banks {
+                    Some(banks) => {
+                        let bank = banks[(addr as usize - 0x8000) / BANK_SIZE] as usize;
+                        bank * BANK_SIZE + addr as usize % BANK_SIZE
+                    }
+                    None => addr as usize - 0x8000,
+                };
+                read_mirrored(&self.prg_rom, offset)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5ff8..=0x5fff => {
+                if let Some(ref mut banks) = self.banks {
+                    banks[addr as usize - 0x5ff8] = value;
+                }
+            }
+            0x6000..=0x7fff => write_mirrored(&mut self.prg_ram, addr as usize - 0x6000, value),
+            _ => (),
+        }
+    }
+
+    // tunes have no CHR, and nothing is drawn
+    fn ppu_read(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn chr_rom_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+}
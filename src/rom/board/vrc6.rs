@@ -0,0 +1,233 @@
+use super::vrc_irq::VrcIrq;
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Banking control ($B003) bits
+const CONTROL_CHR_MODE: u8 = 0x03;
+const CONTROL_MIRRORING: u8 = 0x0c;
+const CONTROL_PRG_RAM_ENABLE: u8 = 0x80;
+
+/// Konami's VRC6, on the 351951 and 351949A boards: a 16KB and an 8KB PRG bank, eight CHR registers, the VRC IRQ
+/// counter and, handled by the APU, three extra sound channels. The boards differ only in the two low address lines,
+/// which the 351949A (VRC6b) swaps.
+///
+/// Only the nametable arrangements the three VRC6 games use are emulated, not those which map CHR ROM as nametables.
+#[derive(Debug)]
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    is_a0_a1_swapped: bool,
+
+    /// 16KB bank at $8000, in 8KB units, and 8KB bank at $C000
+    prg_banks: [u8; 2],
+    chr_banks: [u8; 8],
+    control: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc6 {
+    pub fn new(config: BoardConfig, is_a0_a1_swapped: bool) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        Self {
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; config.prg_ram_size.max(config.prg_nvram_size)],
+            has_battery: config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            is_a0_a1_swapped,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        let addr = if self.is_a0_a1_swapped {
+            (addr & 0xf000) | ((addr & 1) << 1) | ((addr >> 1) & 1)
+        } else {
+            addr & 0xf003
+        };
+        match addr {
+            0x8000..=0x8003 => self.prg_banks[0] = value,
+            0xb003 => self.control = value,
+            0xc000..=0xc003 => self.prg_banks[1] = value,
+            0xd000..=0xd003 => self.chr_banks[addr as usize & 3] = value,
+            0xe000..=0xe003 => self.chr_banks[4 + (addr as usize & 3)] = value,
+            0xf000 => self.irq.write_latch(value),
+            0xf001 => self.irq.write_control(value),
+            0xf002 => self.irq.acknowledge(),
+            // the rest of $9000-$B002 is the sound chip's
+            _ => (),
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let last_bank = self.prg_rom.len().div_ceil(PRG_BANK_SIZE).saturating_sub(1);
+        let bank = match addr {
+            0x8000..=0xbfff => ((self.prg_banks[0] as usize & 0x0f) << 1) | ((addr as usize >> 13) & 1),
+            0xc000..=0xdfff => self.prg_banks[1] as usize & 0x1f,
+            _ => last_bank,
+        };
+        bank * PRG_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let slot = (addr as usize >> 10) & 7;
+        let bank = match (self.control & CONTROL_CHR_MODE, slot) {
+            // eight 1KB banks
+            (0, _) => self.chr_banks[slot] as usize,
+            // four 2KB banks
+            (1, _) => ((self.chr_banks[slot / 2] as usize) << 1) | (slot & 1),
+            // 1KB banks at $0000 and 2KB banks at $1000
+            (_, 0..=3) => self.chr_banks[slot] as usize,
+            (_, _) => ((self.chr_banks[4 + (slot - 4) / 2] as usize) << 1) | (slot & 1),
+        };
+        bank * CHR_BANK_SIZE + (addr as usize & 0x03ff)
+    }
+
+    const fn is_prg_ram_enabled(&self) -> bool {
+        self.control & CONTROL_PRG_RAM_ENABLE != 0
+    }
+}
+
+impl Board for Vrc6 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => read_mirrored(&self.prg_ram, addr as usize - 0x6000),
+            0x8000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                write_mirrored(&mut self.prg_ram, addr as usize - 0x6000, value);
+            }
+            0x8000.. => self.write_register(addr, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.control & CONTROL_MIRRORING) >> 2 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.is_pending()
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IRQ control: enabled, counting every CPU cycle
+    const CONTROL_ENABLE_CYCLE_MODE: u8 = 0x06;
+
+    /// A board whose PRG ROM holds its 8KB bank number in every byte
+    fn board(is_a0_a1_swapped: bool) -> Vrc6 {
+        let config = BoardConfig {
+            mapper: if is_a0_a1_swapped { 26 } else { 24 },
+            submapper: 0,
+            prg_rom: (0..32).flat_map(|bank| [bank; PRG_BANK_SIZE]).collect(),
+            chr_rom: vec![0; 0x2000],
+            chr_ram_size: 0,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            mirroring: Mirroring::Vertical,
+        };
+        Vrc6::new(config, is_a0_a1_swapped)
+    }
+
+    #[test]
+    fn prg_banks() {
+        let mut vrc6 = board(false);
+        vrc6.cpu_write(0x8000, 3);
+        vrc6.cpu_write(0xc000, 9);
+        assert_eq!(vrc6.cpu_read(0x8000), 6);
+        assert_eq!(vrc6.cpu_read(0xa000), 7);
+        assert_eq!(vrc6.cpu_read(0xc000), 9);
+        assert_eq!(vrc6.cpu_read(0xe000), 31);
+    }
+
+    #[test]
+    fn vrc6b_swaps_a0_and_a1() {
+        // $B003 on the VRC6a
+        let mut vrc6a = board(false);
+        vrc6a.cpu_write(0xb003, 0x84);
+        assert_eq!(vrc6a.mirroring(), Mirroring::Horizontal);
+        assert!(vrc6a.is_prg_ram_enabled());
+
+        // $B003 is still $B003 with both lines swapped, but $F001 and $F002 trade places
+        let mut vrc6b = board(true);
+        vrc6b.cpu_write(0xb003, 0x84);
+        assert_eq!(vrc6b.mirroring(), Mirroring::Horizontal);
+        vrc6b.cpu_write(0xf000, 0xff);
+        vrc6b.cpu_write(0xf002, CONTROL_ENABLE_CYCLE_MODE);
+        vrc6b.cpu_clock();
+        assert!(vrc6b.irq());
+        vrc6b.cpu_write(0xf001, 0);
+        assert!(!vrc6b.irq());
+    }
+
+    #[test]
+    fn cycle_mode_irq() {
+        let mut vrc6 = board(false);
+        vrc6.cpu_write(0xf000, 0xfd);
+        vrc6.cpu_write(0xf001, CONTROL_ENABLE_CYCLE_MODE);
+        vrc6.cpu_clock();
+        vrc6.cpu_clock();
+        assert!(!vrc6.irq());
+        vrc6.cpu_clock();
+        assert!(vrc6.irq());
+        // acknowledging disables the counter unless the enable-after-acknowledgement bit was set
+        vrc6.cpu_write(0xf002, 0);
+        for _ in 0..0x200 {
+            vrc6.cpu_clock();
+        }
+        assert!(!vrc6.irq());
+    }
+}
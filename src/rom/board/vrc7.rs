@@ -0,0 +1,151 @@
+use super::vrc_irq::VrcIrq;
+use super::{read_mirrored, write_mirrored, Board, BoardConfig, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Control register ($E000) bits
+const CONTROL_MIRRORING: u8 = 0x03;
+const CONTROL_PRG_RAM_ENABLE: u8 = 0x80;
+
+/// Konami's VRC7: three 8KB PRG banks, eight 1KB CHR banks, the VRC IRQ counter and, on the VRC7a board that Lagrange
+/// Point uses, an FM synthesizer handled by the APU. Registers are told apart by A12-A15 and one more address line:
+/// A4 on the VRC7a and A3 on the VRC7b.
+#[derive(Debug)]
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    has_battery: bool,
+    /// CHR ROM, or CHR RAM if the board has no ROM
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    /// the address lines which pick the second register at each address, A3 and A4 if the board isn't known
+    register_select_mask: u16,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc7 {
+    pub fn new(config: BoardConfig) -> Self {
+        let is_chr_ram = config.chr_rom.is_empty();
+        let register_select_mask = match config.submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+        Self {
+            prg_rom: config.prg_rom,
+            prg_ram: vec![0; config.prg_ram_size.max(config.prg_nvram_size)],
+            has_battery: config.prg_nvram_size > 0,
+            chr: if is_chr_ram { vec![0; config.chr_ram_size] } else { config.chr_rom },
+            is_chr_ram,
+            register_select_mask,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        let is_second = addr & self.register_select_mask != 0;
+        match (addr & 0xf000, is_second) {
+            (0x8000, false) => self.prg_banks[0] = value,
+            (0x8000, true) => self.prg_banks[1] = value,
+            (0x9000, false) => self.prg_banks[2] = value,
+            // $9010 and $9030 are the sound chip's
+            (0x9000, true) => (),
+            (0xa000..=0xd000, _) => {
+                let register = ((addr as usize >> 12) - 0xa) * 2 + is_second as usize;
+                self.chr_banks[register] = value;
+            }
+            (0xe000, false) => self.control = value,
+            (0xe000, true) => self.irq.write_latch(value),
+            (_, false) => self.irq.write_control(value),
+            (_, true) => self.irq.acknowledge(),
+        }
+    }
+
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        let slot = (addr as usize >> 13) & 3;
+        let bank = match slot {
+            0..=2 => self.prg_banks[slot] as usize & 0x3f,
+            _ => self.prg_rom.len().div_ceil(PRG_BANK_SIZE).saturating_sub(1),
+        };
+        bank * PRG_BANK_SIZE + (addr as usize & 0x1fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_banks[(addr as usize >> 10) & 7] as usize * CHR_BANK_SIZE + (addr as usize & 0x03ff)
+    }
+
+    const fn is_prg_ram_enabled(&self) -> bool {
+        self.control & CONTROL_PRG_RAM_ENABLE != 0
+    }
+}
+
+impl Board for Vrc7 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => read_mirrored(&self.prg_ram, addr as usize - 0x6000),
+            0x8000.. => read_mirrored(&self.prg_rom, self.prg_rom_offset(addr)),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7fff if self.is_prg_ram_enabled() => {
+                write_mirrored(&mut self.prg_ram, addr as usize - 0x6000, value);
+            }
+            0x8000.. => self.write_register(addr, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        read_mirrored(&self.chr, self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        if self.is_chr_ram {
+            let offset = self.chr_offset(addr);
+            write_mirrored(&mut self.chr, offset, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & CONTROL_MIRRORING {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn chr_rom_offset(&self, addr: u16) -> Option<usize> {
+        (!self.is_chr_ram && !self.chr.is_empty()).then(|| self.chr_offset(addr) % self.chr.len())
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.is_pending()
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(self.prg_ram.as_slice())
+    }
+
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        if self.has_battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+/// CPU cycles per scanline, in thirds: the prescaler counts down by 3 every cycle from 341, the number of PPU dots
+const PRESCALER_PERIOD: i16 = 341;
+const PRESCALER_STEP: i16 = 3;
+
+/// Control register bits
+const CONTROL_ENABLE_AFTER_ACK: u8 = 0x01;
+const CONTROL_ENABLE: u8 = 0x02;
+const CONTROL_CYCLE_MODE: u8 = 0x04;
+
+/// The IRQ counter Konami's VRC4, VRC6 and VRC7 share. It counts up from a latch to $FF, either every CPU cycle or,
+/// through a prescaler, roughly every scanline, raising an IRQ each time it overflows.
+#[derive(Debug, Default)]
+pub struct VrcIrq {
+    latch: u8,
+    control: u8,
+    counter: u8,
+    prescaler: i16,
+    is_pending: bool,
+}
+
+impl VrcIrq {
+    pub const fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    pub const fn write_control(&mut self, value: u8) {
+        self.control = value;
+        self.is_pending = false;
+        if value & CONTROL_ENABLE != 0 {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    /// Acknowledge the IRQ, which also sets whether it's enabled from the "enable after acknowledgement" bit
+    pub const fn acknowledge(&mut self) {
+        self.is_pending = false;
+        if self.control & CONTROL_ENABLE_AFTER_ACK != 0 {
+            self.control |= CONTROL_ENABLE;
+        } else {
+            self.control &= !CONTROL_ENABLE;
+        }
+    }
+
+    pub const fn clock(&mut self) {
+        if self.control & CONTROL_ENABLE == 0 {
+            return;
+        }
+
+        if self.control & CONTROL_CYCLE_MODE == 0 {
+            self.prescaler -= PRESCALER_STEP;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += PRESCALER_PERIOD;
+        }
+
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.is_pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub const fn is_pending(&self) -> bool {
+        self.is_pending
+    }
+}
@@ -1,11 +1,11 @@
 use anyhow::{anyhow, Result};
-use binrw::BinRead;
-use binrw::helpers::until_eof;
-use modular_bitfield::*;
+use binrw::binread;
 use modular_bitfield::prelude::*;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive as FromPrimitiveTrait;
 
+use super::ExpansionAudio;
+
 #[derive(BitfieldSpecifier, Debug, PartialEq)]
 #[bits = 1]
 pub enum NametableArrangement {
@@ -29,6 +29,20 @@ pub enum TvSystem {
     Dual,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuModel {
+    Rp2c02,
+    Rp2c07,
+    /// Vs. System RGB PPU (RP2C03B/G, RC2C03B/C)
+    Rp2c03,
+    /// Vs. System RGB PPU with a scrambled palette. The value is the revision, 1-4.
+    Rp2c04(u8),
+    /// Vs. System RGB PPU with PPUCTRL and PPUMASK swapped. The value is the revision, 1-5.
+    Rc2c05(u8),
+}
+
+/// Mapper numbers, named after their boards as they're commonly known
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, FromPrimitive)]
 pub enum Mapper {
     NROM = 0,
@@ -81,19 +95,49 @@ pub enum Mapper {
     CameraCodemastersQuattro = 232,
 }
 
-#[bitfield]
-#[derive(BinRead, Debug)]
-#[br(map = Self::from_bytes)]
-struct INesBits1 {
-    nametable_arrangement: NametableArrangement,
-    has_persistent_memory: bool,
-    has_trainer: bool,
-    has_alternative_nametable_layout: bool,
-    mapper_id_low: B4,
-    is_vs_unisystem: bool,
-    has_playchoice_data: bool,
-    nes2_format_indicator: B2,
-    mapper_id_high: B4,
+use header_bits::{INesBits1, INesBits2};
+
+mod header_bits {
+    // the code #[bitfield] generates parenthesizes the field types, and includes setters and conversions we don't use
+    #![allow(unused_parens, dead_code)]
+
+    use binrw::BinRead;
+    use modular_bitfield::prelude::*;
+
+    use super::{NametableArrangement, TimingMode};
+
+    /// Flags 6 and 7
+    #[bitfield]
+    #[derive(BinRead, Debug)]
+    #[br(map = Self::from_bytes)]
+    pub struct INesBits1 {
+        pub(super) nametable_arrangement: NametableArrangement,
+        pub(super) has_persistent_memory: bool,
+        pub(super) has_trainer: bool,
+        pub(super) has_alternative_nametable_layout: bool,
+        pub(super) mapper_id_low: B4,
+        pub(super) is_vs_unisystem: bool,
+        pub(super) has_playchoice_data: bool,
+        pub(super) nes2_format_indicator: B2,
+        pub(super) mapper_id_high: B4,
+    }
+
+    /// Flags 11-15, which are only defined by NES 2.0
+    #[bitfield]
+    #[derive(BinRead, Debug)]
+    #[br(map = Self::from_bytes)]
+    pub struct INesBits2 {
+        pub(super) chr_ram_size_shift: B4,
+        pub(super) chr_nvram_size_shift: B4,
+        pub(super) timing_mode: TimingMode,
+        pub(super) reserved1: B6,
+        pub(super) vs_ppu_or_extended_console_type: B4, // FIXME: implement system types
+        pub(super) vs_hardware_type: B4,
+        pub(super) num_miscellaneous_roms: B2,
+        pub(super) reserved2: B6,
+        pub(super) default_expansion_device: B6, // FIXME: implement expansion devices
+        pub(super) reserved3: B2,
+    }
 }
 
 impl INesBits1 {
@@ -104,30 +148,6 @@ impl INesBits1 {
     fn mapper_id(&self) -> u8 {
         (self.mapper_id_high() << 4) | self.mapper_id_low()
     }
-
-    fn are_extra_flags_zero(&self) -> bool {
-        !self.is_vs_unisystem() && !self.has_playchoice_data() && self.nes2_format_indicator() == 0 && self.mapper_id_high() == 0
-    }
-
-    fn has_extended_console_type(&self) -> bool {
-        self.is_nes2_format() && self.is_vs_unisystem() && self.has_playchoice_data()
-    }
-}
-
-#[bitfield]
-#[derive(BinRead, Debug)]
-#[br(map = Self::from_bytes)]
-struct INesBits2 {
-    chr_ram_size_shift: B4,
-    chr_nvram_size_shift: B4,
-    timing_mode: TimingMode,
-    reserved1: B6,
-    vs_ppu_or_extended_console_type: B4, // FIXME: implement system types
-    vs_hardware_type: B4,
-    num_miscellaneous_roms: B2,
-    reserved2: B6,
-    default_expansion_device: B6, // FIXME: implement expansion devices
-    reserved3: B2,
 }
 
 impl INesBits2 {
@@ -154,10 +174,13 @@ const fn decode_rom_size(lsb: u8, msb: u8, is_nes2: bool, nes1_shift: usize) ->
     }
 }
 
-#[derive(BinRead, Debug)]
+#[binread]
+#[derive(Debug)]
 #[br(magic = b"NES\x1A")]
 pub struct INes {
+    #[br(temp)]
     prg_rom_size: u8,
+    #[br(temp)]
     chr_rom_size: u8,
     flags6_7: INesBits1,
     prg_ram_size: u8,
@@ -170,8 +193,6 @@ pub struct INes {
     prg_rom: Vec<u8>,
     #[br(count = decode_rom_size(chr_rom_size, flags9 >> 4, flags6_7.is_nes2_format(), 13))]
     chr_rom: Vec<u8>,
-    #[br(parse_with = until_eof)]
-    miscellaneous_rom: Vec<u8>,
 }
 
 impl INes {
@@ -183,13 +204,16 @@ impl INes {
         self.flags6_7.is_nes2_format() || self.nes2_flags.is_all_zeroes()
     }
 
-    pub fn trainer(&self) -> Option<&[u8; 512]> {
-        self.trainer.as_ref()
+    pub fn nametable_arrangement(&self) -> NametableArrangement {
+        self.flags6_7.nametable_arrangement()
+    }
+
+    pub fn has_alternative_nametable_layout(&self) -> bool {
+        self.flags6_7.has_alternative_nametable_layout()
     }
 
-    pub fn set_trainer(&mut self, trainer: Option<[u8; 512]>) {
-        self.trainer = trainer;
-        self.flags6_7.set_has_trainer(trainer.is_some());
+    pub fn trainer(&self) -> Option<&[u8; 512]> {
+        self.trainer.as_ref()
     }
 
     pub fn prg_rom(&self) -> &[u8] {
@@ -200,18 +224,6 @@ impl INes {
         self.chr_rom.as_slice()
     }
 
-    pub fn num_miscellaneous_roms(&self) -> usize {
-        if self.is_nes2_format() {
-            self.nes2_flags.num_miscellaneous_roms() as usize
-        } else {
-            0
-        }
-    }
-
-    pub fn miscellaneous_rom(&self) -> &[u8] {
-        self.miscellaneous_rom.as_slice()
-    }
-
     pub fn chr_ram_size(&self) -> usize {
         if self.is_nes2_format() {
             let shift = self.nes2_flags.chr_ram_size_shift() as usize;
@@ -238,6 +250,47 @@ impl INes {
         }
     }
 
+    /// Size of volatile PRG RAM. INES 1.0 headers rarely give it, so they're assumed to have 8KB, which boards without
+    /// PRG RAM can ignore.
+    pub fn prg_ram_size(&self) -> usize {
+        if self.is_nes2_format() {
+            Self::decode_ram_size(self.flags10 & 0xf)
+        } else if self.has_battery() {
+            0
+        } else {
+            self.nes1_prg_ram_size()
+        }
+    }
+
+    /// Size of battery-backed PRG RAM
+    pub fn prg_nvram_size(&self) -> usize {
+        if self.is_nes2_format() {
+            Self::decode_ram_size(self.flags10 >> 4)
+        } else if self.has_battery() {
+            self.nes1_prg_ram_size()
+        } else {
+            0
+        }
+    }
+
+    fn nes1_prg_ram_size(&self) -> usize {
+        // a size of 0 means 8KB for compatibility, and the byte is often junk in old headers
+        if self.are_extra_flags_valid() {
+            (self.prg_ram_size as usize).max(1) * 0x2000
+        } else {
+            0x2000
+        }
+    }
+
+    /// NES 2.0 RAM sizes are shift counts, with 0 meaning none
+    const fn decode_ram_size(shift: u8) -> usize {
+        if shift == 0 { 0 } else { 64 << shift }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.flags6_7.has_persistent_memory()
+    }
+
     pub fn submapper_id(&self) -> Option<u8> {
         self.flags6_7.is_nes2_format().then_some(self.prg_ram_size >> 4)
     }
@@ -259,6 +312,51 @@ impl INes {
         Mapper::from_usize(mapper_id).ok_or_else(|| anyhow!("Unknown INES mapper ID {}", mapper_id))
     }
 
+    /// Sound chips on the board which are emulated
+    pub fn expansion_audio(&self) -> ExpansionAudio {
+        let expansion_audio = ExpansionAudio::new();
+        match self.mapper() {
+            Ok(Mapper::VRC6a | Mapper::VRC6b) => expansion_audio.with_vrc6(true),
+            // only Lagrange Point's board wires up the FM synthesizer, but no other VRC7 game writes to its registers
+            Ok(Mapper::VRC7) => expansion_audio.with_vrc7(true),
+            Ok(Mapper::Namco163) => expansion_audio.with_n163(true),
+            // the 5B is an FME-7 with audio, which only Gimmick! used, but the registers don't clash
+            Ok(Mapper::FME7) => expansion_audio.with_sunsoft_5b(true),
+            _ => expansion_audio,
+        }
+    }
+
+    /// Whether the board is a VRC6b, which swaps the VRC6's A0 and A1 for both banking and sound
+    pub fn is_vrc6_a0_a1_swapped(&self) -> bool {
+        matches!(self.mapper(), Ok(Mapper::VRC6b))
+    }
+
+    /// Whether the game is for the Vs. System. Old iNES 1.0 headers often have junk such as "DiskDude!" from byte 7 on,
+    /// so the flag is only trusted if the rest of the header is clean.
+    pub fn is_vs_system(&self) -> bool {
+        self.are_extra_flags_valid() && self.flags6_7.is_vs_unisystem() && !self.flags6_7.has_playchoice_data()
+    }
+
+    pub fn ppu_model(&self) -> PpuModel {
+        if !self.is_vs_system() {
+            return match self.tv_system() {
+                Some(TvSystem::Pal) => PpuModel::Rp2c07,
+                _ => PpuModel::Rp2c02,
+            };
+        }
+
+        if !self.is_nes2_format() {
+            // INES 1.0 has no way to specify the PPU, so assume the one with the unscrambled palette
+            return PpuModel::Rp2c03;
+        }
+
+        match self.nes2_flags.vs_ppu_or_extended_console_type() {
+            ppu_type @ 2..=5 => PpuModel::Rp2c04(ppu_type - 1),
+            ppu_type @ 8..=12 => PpuModel::Rc2c05(ppu_type - 7),
+            _ => PpuModel::Rp2c03,
+        }
+    }
+
     pub fn tv_system(&self) -> Option<TvSystem> {
         if self.flags6_7.is_nes2_format() {
             Some(match self.nes2_flags.timing_mode() {
@@ -266,6 +364,9 @@ impl INes {
                 TimingMode::Rp2c07 | TimingMode::Ua6538 => TvSystem::Pal,
                 TimingMode::MultipleRegion => TvSystem::Dual,
             })
+        } else if self.is_vs_system() {
+            // the Vs. System's RGB PPUs all use NTSC timing
+            Some(TvSystem::Ntsc)
         } else if self.are_extra_flags_valid() {
             Some(match self.flags10 & 3 {
                 1 | 3 => TvSystem::Dual,
@@ -0,0 +1,415 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use binrw::{binread, BinRead, BinReaderExt};
+use binrw::helpers::until;
+use log::debug;
+
+pub const NSF_MAGIC: &[u8; 4] = b"NESM";
+pub const NSFE_MAGIC: &[u8; 4] = b"NSFE";
+
+/// Play routine period used when a file doesn't specify one, in microseconds
+const DEFAULT_PLAY_PERIOD_US: u16 = 16639;
+const BANK_SIZE: usize = 0x1000;
+
+pub use expansion_audio::ExpansionAudio;
+
+mod expansion_audio {
+    // the code #[bitfield] generates parenthesizes the field types, and includes conversions we don't use
+    #![allow(unused_parens, dead_code)]
+
+    use binrw::BinRead;
+    use modular_bitfield::prelude::*;
+
+    /// Sound chips on the cartridge, which the tune may use in addition to the APU
+    #[bitfield]
+    #[derive(BinRead, Debug, Clone, Copy, PartialEq, Eq)]
+    #[br(map = Self::from_bytes)]
+    pub struct ExpansionAudio {
+        pub vrc6: bool,
+        pub vrc7: bool,
+        pub fds: bool,
+        pub mmc5: bool,
+        pub n163: bool,
+        pub sunsoft_5b: bool,
+        pub vt02: bool,
+        #[skip]
+        reserved: B1,
+    }
+
+    impl ExpansionAudio {
+        /// Names of the chips in use
+        pub fn names(&self) -> Vec<&'static str> {
+            [
+                (self.vrc6(), "VRC6"),
+                (self.vrc7(), "VRC7"),
+                (self.fds(), "FDS"),
+                (self.mmc5(), "MMC5"),
+                (self.n163(), "Namco 163"),
+                (self.sunsoft_5b(), "Sunsoft 5B"),
+                (self.vt02(), "VT02+"),
+            ]
+            .into_iter()
+            .filter_map(|(is_used, name)| is_used.then_some(name))
+            .collect()
+        }
+    }
+}
+
+#[derive(BinRead, Debug)]
+#[br(little, magic = b"NESM\x1A")]
+struct NsfHeader {
+    version: u8,
+    num_songs: u8,
+    /// 1-based
+    starting_song: u8,
+    load_addr: u16,
+    init_addr: u16,
+    play_addr: u16,
+    title: [u8; 32],
+    artist: [u8; 32],
+    copyright: [u8; 32],
+    ntsc_play_period_us: u16,
+    banks: [u8; 8],
+    _pal_play_period_us: u16,
+    tv_system: u8,
+    expansion_audio: ExpansionAudio,
+    _nsf2_flags: u8,
+    /// 24-bit length of the program data in NSF2 files, which may have metadata after it. Zero if not specified.
+    data_length: [u8; 3],
+}
+
+#[binread]
+#[derive(Debug)]
+#[br(little)]
+struct NsfeChunk {
+    #[br(temp)]
+    length: u32,
+    id: [u8; 4],
+    #[br(count = length)]
+    data: Vec<u8>,
+}
+
+#[derive(BinRead, Debug)]
+#[br(little, magic = b"NSFE")]
+struct NsfeFile {
+    #[br(parse_with = until(|chunk: &NsfeChunk| &chunk.id == b"NEND"))]
+    chunks: Vec<NsfeChunk>,
+}
+
+#[derive(BinRead, Debug)]
+#[br(little)]
+struct NsfeInfo {
+    load_addr: u16,
+    init_addr: u16,
+    play_addr: u16,
+    tv_system: u8,
+    expansion_audio: ExpansionAudio,
+    #[br(try)]
+    num_songs: Option<u8>,
+    /// 0-based
+    #[br(try)]
+    starting_song: Option<u8>,
+}
+
+/// Decode a fixed-size, null-padded string
+fn fixed_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_owned()
+}
+
+/// Decode the null-terminated strings making up an NSFe chunk
+fn strings(bytes: &[u8]) -> Vec<String> {
+    bytes.split(|&b| b == 0).map(|s| String::from_utf8_lossy(s).trim().to_owned()).collect()
+}
+
+/// Decode the per-track durations of a `time` or `fade` chunk, where negative values mean the default
+fn durations(bytes: &[u8]) -> Vec<Option<Duration>> {
+    bytes
+        .chunks_exact(4)
+        .map(|ms| {
+            let ms = i32::from_le_bytes([ms[0], ms[1], ms[2], ms[3]]);
+            u64::try_from(ms).ok().map(Duration::from_millis)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NsfTrack {
+    pub title: Option<String>,
+    pub length: Option<Duration>,
+    pub fade: Option<Duration>,
+}
+
+/// A tune in NSF or NSFe format
+#[derive(Debug, Clone)]
+pub struct Nsf {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub tracks: Vec<NsfTrack>,
+    pub starting_track: usize,
+    pub expansion_audio: ExpansionAudio,
+    load_addr: u16,
+    init_addr: u16,
+    play_addr: u16,
+    play_period_us: u16,
+    initial_banks: Option<[u8; 8]>,
+    data: Vec<u8>,
+}
+
+impl Nsf {
+    /// Read a tune in either format, telling them apart by their magic numbers
+    pub fn from_file<F: BinReaderExt>(mut f: F) -> Result<Self> {
+        let mut magic = [0; 4];
+        f.read_exact(&mut magic)?;
+        f.rewind()?;
+        if &magic == NSFE_MAGIC {
+            Self::from_nsfe(f)
+        } else {
+            Self::from_nsf(f)
+        }
+    }
+
+    fn from_nsf<F: BinReaderExt>(mut f: F) -> Result<Self> {
+        let header: NsfHeader = f.read_le()?;
+        if !(1..=2).contains(&header.version) {
+            return Err(anyhow!("Unsupported NSF version {}", header.version));
+        }
+        if header.tv_system & 3 == 1 {
+            return Err(anyhow!("Only NTSC is currently supported"));
+        }
+
+        let mut data = Vec::new();
+        f.read_to_end(&mut data)?;
+        let [low, middle, high] = header.data_length;
+        let data_length = u32::from_le_bytes([low, middle, high, 0]) as usize;
+        if header.version == 2 && data_length != 0 {
+            // NSF2 metadata isn't supported yet
+            data.truncate(data_length);
+        }
+
+        Self {
+            title: fixed_string(&header.title),
+            artist: fixed_string(&header.artist),
+            copyright: fixed_string(&header.copyright),
+            tracks: vec![NsfTrack::default(); header.num_songs.max(1) as usize],
+            starting_track: header.starting_song.saturating_sub(1) as usize,
+            expansion_audio: header.expansion_audio,
+            load_addr: header.load_addr,
+            init_addr: header.init_addr,
+            play_addr: header.play_addr,
+            play_period_us: header.ntsc_play_period_us,
+            initial_banks: header.banks.iter().any(|&b| b != 0).then_some(header.banks),
+            data,
+        }
+        .validated()
+    }
+
+    fn from_nsfe<F: BinReaderExt>(mut f: F) -> Result<Self> {
+        let NsfeFile { chunks } = f.read_le()?;
+
+        let info = chunks.iter().find(|c| &c.id == b"INFO").ok_or_else(|| anyhow!("NSFe file has no INFO chunk"))?;
+        let info: NsfeInfo = Cursor::new(&info.data).read_le()?;
+        if info.tv_system & 3 == 1 {
+            return Err(anyhow!("Only NTSC is currently supported"));
+        }
+
+        let mut nsf = Self {
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            tracks: vec![NsfTrack::default(); info.num_songs.unwrap_or(1).max(1) as usize],
+            starting_track: info.starting_song.unwrap_or(0) as usize,
+            expansion_audio: info.expansion_audio,
+            load_addr: info.load_addr,
+            init_addr: info.init_addr,
+            play_addr: info.play_addr,
+            play_period_us: DEFAULT_PLAY_PERIOD_US,
+            initial_banks: None,
+            data: Vec::new(),
+        };
+
+        for chunk in chunks {
+            match &chunk.id {
+                b"INFO" | b"NEND" => (),
+                b"DATA" => nsf.data = chunk.data,
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    let len = chunk.data.len().min(banks.len());
+                    banks[..len].copy_from_slice(&chunk.data[..len]);
+                    nsf.initial_banks = Some(banks);
+                }
+                b"RATE" if chunk.data.len() >= 2 => {
+                    nsf.play_period_us = u16::from_le_bytes([chunk.data[0], chunk.data[1]]);
+                }
+                b"auth" => {
+                    let mut strings = strings(&chunk.data).into_iter();
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => {
+                    for (track, title) in nsf.tracks.iter_mut().zip(strings(&chunk.data)) {
+                        track.title = (!title.is_empty()).then_some(title);
+                    }
+                }
+                b"time" => {
+                    for (track, length) in nsf.tracks.iter_mut().zip(durations(&chunk.data)) {
+                        track.length = length;
+                    }
+                }
+                b"fade" => {
+                    for (track, fade) in nsf.tracks.iter_mut().zip(durations(&chunk.data)) {
+                        track.fade = fade;
+                    }
+                }
+                // chunks starting with a capital letter must be understood to play the tune correctly
+                [first, ..] if first.is_ascii_uppercase() => {
+                    return Err(anyhow!("Unsupported NSFe chunk {}", String::from_utf8_lossy(&chunk.id)));
+                }
+                _ => debug!("Skipping NSFe chunk {}", String::from_utf8_lossy(&chunk.id)),
+            }
+        }
+
+        nsf.validated()
+    }
+
+    fn validated(mut self) -> Result<Self> {
+        if self.data.is_empty() {
+            return Err(anyhow!("NSF has no program data"));
+        }
+        if self.initial_banks.is_none() && self.load_addr < 0x8000 {
+            return Err(anyhow!("NSF load address ${:04X} is outside of ROM", self.load_addr));
+        }
+        if self.play_period_us == 0 {
+            self.play_period_us = DEFAULT_PLAY_PERIOD_US;
+        }
+        self.starting_track = self.starting_track.min(self.tracks.len() - 1);
+        Ok(self)
+    }
+
+    pub const fn init_addr(&self) -> u16 {
+        self.init_addr
+    }
+
+    pub const fn play_addr(&self) -> u16 {
+        self.play_addr
+    }
+
+    /// How often the play routine should be called
+    pub fn play_period(&self) -> Duration {
+        Duration::from_micros(self.play_period_us as u64)
+    }
+
+    /// Banks to map into $8000-$FFFF before calling the init routine, if the tune uses bank switching
+    pub const fn initial_banks(&self) -> Option<[u8; 8]> {
+        self.initial_banks
+    }
+
+    /// Take the program data, laid out as 4KB banks. Tunes without bank switching are laid out as a single 32KB image
+    /// of $8000-$FFFF.
+    pub fn take_prg_rom(&mut self) -> Vec<u8> {
+        let padding = if self.initial_banks.is_some() {
+            self.load_addr as usize % BANK_SIZE
+        } else {
+            self.load_addr as usize - 0x8000
+        };
+
+        let mut prg_rom = vec![0; padding];
+        prg_rom.append(&mut self.data);
+        let len = if self.initial_banks.is_some() { prg_rom.len().next_multiple_of(BANK_SIZE) } else { 8 * BANK_SIZE };
+        prg_rom.resize(len, 0);
+        prg_rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(id);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// An INFO chunk loading at $8000 with three tracks, starting at the second
+    fn info() -> Vec<u8> {
+        chunk(b"INFO", &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0, 0x01, 3, 1])
+    }
+
+    fn nsfe(chunks: &[Vec<u8>]) -> Result<Nsf> {
+        let mut file = NSFE_MAGIC.to_vec();
+        for chunk in chunks {
+            file.extend_from_slice(chunk);
+        }
+        file.extend(chunk(b"NEND", &[]));
+        Nsf::from_file(Cursor::new(file))
+    }
+
+    #[test]
+    fn nsfe_chunks_are_parsed() {
+        let times = [1000i32, -1, 2500].iter().flat_map(|ms| ms.to_le_bytes()).collect::<Vec<_>>();
+        let nsf = nsfe(&[
+            info(),
+            chunk(b"DATA", &[0xea; 16]),
+            chunk(b"RATE", &20000u16.to_le_bytes()),
+            chunk(b"auth", b"Title\0Artist\0Copyright\0"),
+            chunk(b"tlbl", b"One\0\0Three\0"),
+            chunk(b"time", &times),
+            chunk(b"fade", &500i32.to_le_bytes()),
+        ])
+        .unwrap();
+
+        assert_eq!((nsf.init_addr(), nsf.play_addr()), (0x8003, 0x8006));
+        assert_eq!(nsf.play_period(), Duration::from_micros(20000));
+        assert!(nsf.expansion_audio.vrc6());
+        assert_eq!((nsf.title.as_str(), nsf.artist.as_str(), nsf.copyright.as_str()), ("Title", "Artist", "Copyright"));
+        assert_eq!(nsf.starting_track, 1);
+
+        let titles = nsf.tracks.iter().map(|track| track.title.as_deref()).collect::<Vec<_>>();
+        assert_eq!(titles, [Some("One"), None, Some("Three")]);
+        let lengths = nsf.tracks.iter().map(|track| track.length).collect::<Vec<_>>();
+        assert_eq!(lengths, [Some(Duration::from_millis(1000)), None, Some(Duration::from_millis(2500))]);
+        let fades = nsf.tracks.iter().map(|track| track.fade).collect::<Vec<_>>();
+        assert_eq!(fades, [Some(Duration::from_millis(500)), None, None]);
+    }
+
+    #[test]
+    fn nsfe_info_may_leave_out_the_track_count() {
+        let nsf = nsfe(&[chunk(b"INFO", &[0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0, 0]), chunk(b"DATA", &[0])]).unwrap();
+        assert_eq!(nsf.tracks.len(), 1);
+        assert_eq!(nsf.starting_track, 0);
+        assert_eq!(nsf.play_period(), Duration::from_micros(DEFAULT_PLAY_PERIOD_US as u64));
+    }
+
+    #[test]
+    fn nsfe_bank_chunk_may_be_short() {
+        let mut nsf = nsfe(&[info(), chunk(b"DATA", &[0xea; 16]), chunk(b"BANK", &[0, 1, 2])]).unwrap();
+        assert_eq!(nsf.initial_banks(), Some([0, 1, 2, 0, 0, 0, 0, 0]));
+        assert_eq!(nsf.take_prg_rom().len(), BANK_SIZE);
+    }
+
+    #[test]
+    fn nsfe_without_info_or_data_is_rejected() {
+        assert!(nsfe(&[chunk(b"DATA", &[0])]).is_err());
+        assert!(nsfe(&[info()]).is_err());
+    }
+
+    #[test]
+    fn unknown_nsfe_chunks_are_rejected_only_if_required() {
+        assert!(nsfe(&[info(), chunk(b"DATA", &[0]), chunk(b"xtra", &[1, 2, 3])]).is_ok());
+        assert!(nsfe(&[info(), chunk(b"DATA", &[0]), chunk(b"XTRA", &[1, 2, 3])]).is_err());
+    }
+
+    #[test]
+    fn nsfe_without_an_end_chunk_is_rejected() {
+        let mut file = NSFE_MAGIC.to_vec();
+        file.extend(info());
+        file.extend(chunk(b"DATA", &[0]));
+        assert!(Nsf::from_file(Cursor::new(file)).is_err());
+    }
+}
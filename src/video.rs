@@ -0,0 +1,10 @@
+mod filter;
+mod hdpack;
+mod ntsc;
+mod palette;
+mod scale;
+
+pub use filter::{FilterChain, Image, Rgba, Scaler};
+pub use hdpack::{HdPack, DEFINITION_FILE as HD_PACK_DEFINITION_FILE};
+pub use ntsc::{NtscFilter, NtscFilterOptions, OUTPUT_WIDTH as NTSC_OUTPUT_WIDTH};
+pub use palette::*;
@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+
+use super::scale;
+
+pub type Rgba = [u8; 4];
+
+/// An RGBA image passed between stages of the filter chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgba>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0; 4]; width * height],
+        }
+    }
+
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: rgba.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+        }
+    }
+
+    pub fn as_rgba(&self) -> &[u8] {
+        self.pixels.as_flattened()
+    }
+
+    /// Decode a PNG file
+    pub fn from_png(data: &[u8]) -> Result<Self> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let data = &buf[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+            png::ColorType::Rgb => data.chunks_exact(3).map(|p| [p[0], p[1], p[2], 0xff]).collect(),
+            png::ColorType::GrayscaleAlpha => data.chunks_exact(2).map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => data.iter().map(|&p| [p, p, p, 0xff]).collect(),
+            // indexed images are expanded to RGB(A) by the transformations above
+            png::ColorType::Indexed => return Err(anyhow!("Unexpected indexed PNG output")),
+        };
+
+        Ok(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        })
+    }
+
+    /// Encode the image as a PNG file
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(self.as_rgba())?;
+        writer.finish()?;
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scaler {
+    #[default]
+    None,
+    /// Nearest-neighbor scaling by an integer factor
+    Nearest(usize),
+    Scale2x,
+    Scale3x,
+    /// xBR by a factor of 2, 3 or 4
+    Xbr(usize),
+}
+
+impl Scaler {
+    pub const ALL: [Self; 9] = [
+        Self::None,
+        Self::Nearest(2),
+        Self::Nearest(3),
+        Self::Nearest(4),
+        Self::Scale2x,
+        Self::Scale3x,
+        Self::Xbr(2),
+        Self::Xbr(3),
+        Self::Xbr(4),
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Nearest(2) => "Nearest 2x",
+            Self::Nearest(3) => "Nearest 3x",
+            Self::Nearest(4) => "Nearest 4x",
+            Self::Nearest(_) => "Nearest",
+            Self::Scale2x => "Scale2x",
+            Self::Scale3x => "Scale3x",
+            Self::Xbr(2) => "2xBR",
+            Self::Xbr(3) => "3xBR",
+            Self::Xbr(4) => "4xBR",
+            Self::Xbr(_) => "xBR",
+        }
+    }
+
+    fn apply(self, image: Image) -> Image {
+        match self {
+            Self::None | Self::Nearest(0 | 1) => image,
+            Self::Nearest(factor) => scale::nearest(&image, factor),
+            Self::Scale2x => scale::scale2x(&image),
+            Self::Scale3x => scale::scale3x(&image),
+            Self::Xbr(factor) => scale::xbr(&image, factor.clamp(2, 4)),
+        }
+    }
+}
+
+/// Strength of each part of the CRT effect, from 0 (off) to 1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CrtOptions {
+    /// How much the gaps between scanlines are darkened
+    pub scanlines: f32,
+    /// How much each column is tinted by an aperture grille
+    pub mask: f32,
+}
+
+impl CrtOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.scanlines > 0.0 || self.mask > 0.0
+    }
+
+    /// Darken scanline gaps and apply the aperture grille to an image scaled up from `source_height` lines
+    fn apply(&self, image: &mut Image, source_height: usize) {
+        // with no upscaling to work with, alternate lines are treated as gaps
+        let line_height = (image.height / source_height.max(1)).max(2);
+        let scanline_gain = 1.0 - self.scanlines.clamp(0.0, 1.0);
+        let mask_gain = 1.0 - self.mask.clamp(0.0, 1.0);
+
+        for (y, line) in image.pixels.chunks_exact_mut(image.width).enumerate() {
+            let is_gap = y % line_height == line_height - 1;
+            for (x, pixel) in line.iter_mut().enumerate() {
+                for (channel, value) in pixel.iter_mut().take(3).enumerate() {
+                    let mut gain = if is_gap { scanline_gain } else { 1.0 };
+                    if x % 3 != channel {
+                        gain *= mask_gain;
+                    }
+                    *value = (*value as f32 * gain) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// The software filters applied to the output of the PPU before display
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FilterChain {
+    pub scaler: Scaler,
+    pub crt: CrtOptions,
+}
+
+impl FilterChain {
+    pub fn is_enabled(&self) -> bool {
+        self.scaler != Scaler::None || self.crt.is_enabled()
+    }
+
+    pub fn apply(&self, image: Image) -> Image {
+        let source_height = image.height;
+        let mut image = self.scaler.apply(image);
+        if self.crt.is_enabled() {
+            self.crt.apply(&mut image, source_height);
+        }
+        image
+    }
+}
@@ -0,0 +1,396 @@
+//! Mesen-compatible HD packs: high-resolution replacements for tiles, defined in a `hires.txt` file
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+
+use crate::hw::ppu::{PixelSource, TileKey, TileSource, FRAME_HEIGHT, FRAME_WIDTH};
+use super::{Image, Palette, Rgba};
+
+/// Name of the file defining an HD pack
+pub const DEFINITION_FILE: &str = "hires.txt";
+const MAX_SCALE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    HorizontalMirror,
+    VerticalMirror,
+    BackgroundPriority,
+    TileAtPosition { x: i32, y: i32, key: TileKey, palette: u32 },
+    SpriteAtPosition { x: i32, y: i32, key: TileKey, palette: u32 },
+    /// Position relative to the top left of the tile being drawn
+    TileNearby { x: i32, y: i32, key: TileKey, palette: u32 },
+    SpriteNearby { x: i32, y: i32, key: TileKey, palette: u32 },
+    FrameRange { divisor: u64, compare: u64 },
+    /// Memory checks need access to CPU memory, which packs don't get, so they're never met
+    MemoryCheck,
+}
+
+/// A reference to a condition from a rule, which may be negated
+#[derive(Debug, Clone, Copy)]
+struct ConditionRef {
+    index: usize,
+    is_negated: bool,
+}
+
+#[derive(Debug, Clone)]
+struct TileRule {
+    image: usize,
+    x: usize,
+    y: usize,
+    brightness: f32,
+    conditions: Vec<ConditionRef>,
+}
+
+#[derive(Debug, Clone)]
+struct BackgroundRule {
+    image: usize,
+    brightness: f32,
+    conditions: Vec<ConditionRef>,
+}
+
+/// State a condition is checked against
+struct Context<'a> {
+    sources: &'a [PixelSource],
+    frame_number: u64,
+    x: usize,
+    y: usize,
+    tile: Option<&'a TileSource>,
+    is_sprite: bool,
+}
+
+fn parse_tile_key(text: &str) -> Result<TileKey> {
+    let text = text.trim();
+    if text.len() == 32 {
+        let mut data = [0; 16];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)?;
+        }
+        Ok(TileKey::Ram(data))
+    } else {
+        Ok(TileKey::Rom(u32::from_str_radix(text, 16)?))
+    }
+}
+
+fn parse_palette(text: &str) -> Result<u32> {
+    Ok(u32::from_str_radix(text.trim(), 16)?)
+}
+
+fn parse_number<T: std::str::FromStr>(text: Option<&str>, name: &str) -> Result<T> {
+    text.and_then(|t| t.trim().parse().ok()).ok_or_else(|| anyhow!("Invalid or missing {}", name))
+}
+
+fn matches(tile: Option<&TileSource>, key: TileKey, palette: u32) -> bool {
+    tile.is_some_and(|tile| tile.key == key && tile.palette == palette)
+}
+
+/// Position on screen of the top left of the tile drawn at the given pixel
+fn tile_origin(x: usize, y: usize, tile: &TileSource) -> (i32, i32) {
+    let column = if tile.is_flipped_horizontally { 7 - tile.x } else { tile.x };
+    let row = if tile.is_flipped_vertically { 7 - tile.y } else { tile.y };
+    (x as i32 - column as i32, y as i32 - row as i32)
+}
+
+impl Condition {
+    fn parse(kind: &str, args: &[&str]) -> Result<Self> {
+        let mut args = args.iter().copied();
+        let mut position = || -> Result<(i32, i32, TileKey, u32)> {
+            let x = parse_number(args.next(), "X position")?;
+            let y = parse_number(args.next(), "Y position")?;
+            let key = parse_tile_key(args.next().ok_or_else(|| anyhow!("Missing tile"))?)?;
+            let palette = parse_palette(args.next().ok_or_else(|| anyhow!("Missing palette"))?)?;
+            Ok((x, y, key, palette))
+        };
+
+        Ok(match kind {
+            "tileAtPosition" => {
+                let (x, y, key, palette) = position()?;
+                Self::TileAtPosition { x, y, key, palette }
+            }
+            "spriteAtPosition" => {
+                let (x, y, key, palette) = position()?;
+                Self::SpriteAtPosition { x, y, key, palette }
+            }
+            "tileNearby" => {
+                let (x, y, key, palette) = position()?;
+                Self::TileNearby { x, y, key, palette }
+            }
+            "spriteNearby" => {
+                let (x, y, key, palette) = position()?;
+                Self::SpriteNearby { x, y, key, palette }
+            }
+            "frameRange" => Self::FrameRange {
+                divisor: parse_number(args.next(), "frame divisor")?,
+                compare: parse_number(args.next(), "frame comparison")?,
+            },
+            "memoryCheck" | "memoryCheckConstant" => {
+                warn!("HD pack memory conditions are not supported and will never be met");
+                Self::MemoryCheck
+            }
+            _ => return Err(anyhow!("Unknown condition type {}", kind)),
+        })
+    }
+
+    fn is_met(&self, context: &Context) -> bool {
+        let source_at = |x: i32, y: i32| {
+            (0..FRAME_WIDTH as i32).contains(&x).then_some(())?;
+            (0..FRAME_HEIGHT as i32).contains(&y).then_some(())?;
+            context.sources.get(y as usize * FRAME_WIDTH + x as usize)
+        };
+        let nearby = |x: i32, y: i32| {
+            let (origin_x, origin_y) = tile_origin(context.x, context.y, context.tile?);
+            source_at(origin_x + x, origin_y + y)
+        };
+
+        match *self {
+            Self::HorizontalMirror => context.tile.is_some_and(|t| t.is_flipped_horizontally),
+            Self::VerticalMirror => context.tile.is_some_and(|t| t.is_flipped_vertically),
+            Self::BackgroundPriority => {
+                context.is_sprite && context.sources[context.y * FRAME_WIDTH + context.x].is_sprite_behind_background
+            }
+            Self::TileAtPosition { x, y, key, palette } => {
+                matches(source_at(x, y).and_then(|s| s.background.as_ref()), key, palette)
+            }
+            Self::SpriteAtPosition { x, y, key, palette } => {
+                matches(source_at(x, y).and_then(|s| s.sprite.as_ref()), key, palette)
+            }
+            Self::TileNearby { x, y, key, palette } => {
+                matches(nearby(x, y).and_then(|s| s.background.as_ref()), key, palette)
+            }
+            Self::SpriteNearby { x, y, key, palette } => {
+                matches(nearby(x, y).and_then(|s| s.sprite.as_ref()), key, palette)
+            }
+            Self::FrameRange { divisor, compare } => divisor != 0 && context.frame_number % divisor >= compare,
+            Self::MemoryCheck => false,
+        }
+    }
+}
+
+pub struct HdPack {
+    scale: usize,
+    images: Vec<Image>,
+    conditions: Vec<Condition>,
+    tiles: HashMap<(TileKey, u32), Vec<TileRule>>,
+    /// rules for tiles drawn with palettes that don't have their own replacement
+    default_tiles: HashMap<TileKey, Vec<TileRule>>,
+    backgrounds: Vec<BackgroundRule>,
+}
+
+impl HdPack {
+    /// Parse an HD pack definition, loading the files it refers to with `load_file`
+    pub fn parse(definition: &str, mut load_file: impl FnMut(&str) -> Result<Vec<u8>>) -> Result<Self> {
+        let mut pack = Self {
+            scale: 1,
+            images: Vec::new(),
+            conditions: vec![Condition::HorizontalMirror, Condition::VerticalMirror, Condition::BackgroundPriority],
+            tiles: HashMap::new(),
+            default_tiles: HashMap::new(),
+            backgrounds: Vec::new(),
+        };
+        let mut condition_names: HashMap<String, usize> = HashMap::from([
+            (String::from("hmirror"), 0),
+            (String::from("vmirror"), 1),
+            (String::from("bgpriority"), 2),
+        ]);
+
+        for (number, line) in definition.lines().enumerate() {
+            pack.parse_line(line.trim(), &mut condition_names, &mut load_file)
+                .map_err(|e| anyhow!("{} line {}: {}", DEFINITION_FILE, number + 1, e))?;
+        }
+
+        Ok(pack)
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        condition_names: &mut HashMap<String, usize>,
+        load_file: &mut impl FnMut(&str) -> Result<Vec<u8>>,
+    ) -> Result<()> {
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        // rules may be prefixed with a list of conditions, e.g. [cond1&!cond2]<tile>...
+        let (conditions, line) = match line.strip_prefix('[') {
+            Some(rest) => {
+                let (names, line) = rest.split_once(']').ok_or_else(|| anyhow!("Unterminated condition list"))?;
+                let conditions = names.split('&').map(|name| {
+                    let name = name.trim();
+                    let (name, is_negated) = match name.strip_prefix('!') {
+                        Some(name) => (name, true),
+                        None => (name, false),
+                    };
+                    let index = *condition_names.get(name).ok_or_else(|| anyhow!("Unknown condition {}", name))?;
+                    Ok(ConditionRef { index, is_negated })
+                }).collect::<Result<Vec<_>>>()?;
+                (conditions, line)
+            }
+            None => (Vec::new(), line),
+        };
+
+        let (tag, value) = line.strip_prefix('<')
+            .and_then(|line| line.split_once('>'))
+            .ok_or_else(|| anyhow!("Expected a tag"))?;
+        let args: Vec<_> = value.split(',').map(str::trim).collect();
+
+        match tag {
+            "ver" => debug!("HD pack version {}", value),
+            "scale" => {
+                let scale: usize = parse_number(args.first().copied(), "scale")?;
+                if !(1..=MAX_SCALE).contains(&scale) {
+                    return Err(anyhow!("Unsupported scale {}", scale));
+                }
+                self.scale = scale;
+            }
+            "img" => {
+                let image = Image::from_png(&load_file(value.trim())?)?;
+                self.images.push(image);
+            }
+            "condition" => {
+                let name = args.first().ok_or_else(|| anyhow!("Missing condition name"))?;
+                let kind = args.get(1).ok_or_else(|| anyhow!("Missing condition type"))?;
+                let condition = Condition::parse(kind, &args[2..])?;
+                condition_names.insert(name.to_string(), self.conditions.len());
+                self.conditions.push(condition);
+            }
+            "tile" => {
+                let image: usize = parse_number(args.first().copied(), "image index")?;
+                if image >= self.images.len() {
+                    return Err(anyhow!("Image {} has not been defined", image));
+                }
+                let key = parse_tile_key(args.get(1).ok_or_else(|| anyhow!("Missing tile"))?)?;
+                let palette = parse_palette(args.get(2).ok_or_else(|| anyhow!("Missing palette"))?)?;
+                let rule = TileRule {
+                    image,
+                    x: parse_number(args.get(3).copied(), "X position")?,
+                    y: parse_number(args.get(4).copied(), "Y position")?,
+                    brightness: args.get(5).and_then(|b| b.parse().ok()).unwrap_or(1.0),
+                    conditions,
+                };
+
+                if args.get(6).is_some_and(|d| d.eq_ignore_ascii_case("y")) {
+                    self.default_tiles.entry(key).or_default().push(rule.clone());
+                }
+                self.tiles.entry((key, palette)).or_default().push(rule);
+            }
+            "background" => {
+                let file = args.first().ok_or_else(|| anyhow!("Missing background image"))?;
+                let image = Image::from_png(&load_file(file)?)?;
+                self.images.push(image);
+                self.backgrounds.push(BackgroundRule {
+                    image: self.images.len() - 1,
+                    brightness: args.get(1).and_then(|b| b.parse().ok()).unwrap_or(1.0),
+                    conditions,
+                });
+            }
+            _ => debug!("Ignoring unsupported HD pack tag <{}>", tag),
+        }
+
+        Ok(())
+    }
+
+    fn are_met(&self, conditions: &[ConditionRef], context: &Context) -> bool {
+        conditions.iter().all(|c| self.conditions[c.index].is_met(context) != c.is_negated)
+    }
+
+    fn find_tile(&self, context: &Context) -> Option<&TileRule> {
+        let tile = context.tile?;
+        let is_met = |rule: &&TileRule| self.are_met(&rule.conditions, context);
+        self.tiles.get(&(tile.key, tile.palette)).and_then(|rules| rules.iter().find(is_met))
+            .or_else(|| self.default_tiles.get(&tile.key)?.iter().find(is_met))
+    }
+
+    fn sample(&self, image: usize, x: usize, y: usize, brightness: f32) -> Option<Rgba> {
+        let image = &self.images[image];
+        if x >= image.width || y >= image.height {
+            return None;
+        }
+        let [r, g, b, a] = image.pixels[y * image.width + x];
+        let [r, g, b] = [r, g, b].map(|c| (c as f32 * brightness).clamp(0.0, 255.0) as u8);
+        Some([r, g, b, a])
+    }
+
+    /// Sample the replacement for one of the `scale` by `scale` subpixels of a tile's pixel
+    fn sample_tile(&self, rule: &TileRule, tile: &TileSource, sub_x: usize, sub_y: usize) -> Option<Rgba> {
+        let scale = self.scale;
+        let x = tile.x as usize * scale + if tile.is_flipped_horizontally { scale - 1 - sub_x } else { sub_x };
+        let y = tile.y as usize * scale + if tile.is_flipped_vertically { scale - 1 - sub_y } else { sub_y };
+        self.sample(rule.image, rule.x + x, rule.y + y, rule.brightness)
+    }
+
+    /// Draw a frame with the pack's replacement graphics, at `scale` times the PPU's resolution
+    pub fn render(&self, frame: &[u16], sources: &[PixelSource], palette: &Palette, frame_number: u64) -> Image {
+        let scale = self.scale;
+        let width = FRAME_WIDTH * scale;
+        let mut output = Image::new(width, FRAME_HEIGHT * scale);
+
+        let frame_context = Context { sources, frame_number, x: 0, y: 0, tile: None, is_sprite: false };
+        let background = self.backgrounds.iter().find(|b| self.are_met(&b.conditions, &frame_context));
+
+        for (i, (&pixel, source)) in frame.iter().zip(sources).enumerate() {
+            let (x, y) = (i % FRAME_WIDTH, i / FRAME_WIDTH);
+            let emphasis = pixel & 0x1c0;
+            let rgb = |index: u16| {
+                let [r, g, b] = palette.rgb(index | emphasis);
+                [r, g, b, 0xff]
+            };
+            let color_of = |tile: &TileSource| rgb(((tile.palette >> (24 - tile.color * 8)) & 0x3f) as u16);
+
+            let original = rgb(pixel & 0x3f);
+            let backdrop = source.background.as_ref().map_or(original, |tile| rgb((tile.palette >> 24) as u16 & 0x3f));
+            let background_color = match source.background {
+                Some(ref tile) if source.is_background_opaque => Some(color_of(tile)),
+                _ => None,
+            };
+            let sprite = source.sprite.as_ref().filter(|_| source.is_sprite_visible());
+
+            let background_context = Context { sources, frame_number, x, y, tile: source.background.as_ref(), is_sprite: false };
+            let background_rule = self.find_tile(&background_context);
+            let sprite_context = Context { sources, frame_number, x, y, tile: sprite, is_sprite: true };
+            let sprite_rule = self.find_tile(&sprite_context);
+
+            for sub_y in 0..scale {
+                for sub_x in 0..scale {
+                    let (out_x, out_y) = (x * scale + sub_x, y * scale + sub_y);
+                    let mut color = background
+                        .and_then(|b| self.sample(b.image, out_x, out_y, b.brightness))
+                        .map_or(backdrop, |c| blend(c, backdrop));
+
+                    match (background_rule, source.background.as_ref()) {
+                        (Some(rule), Some(tile)) => {
+                            if let Some(hd) = self.sample_tile(rule, tile, sub_x, sub_y) {
+                                color = blend(hd, color);
+                            }
+                        }
+                        _ => {
+                            if let Some(background_color) = background_color {
+                                color = background_color;
+                            }
+                        }
+                    }
+
+                    if let Some(tile) = sprite {
+                        color = match sprite_rule.and_then(|rule| self.sample_tile(rule, tile, sub_x, sub_y)) {
+                            Some(hd) => blend(hd, color),
+                            None if sprite_rule.is_none() => color_of(tile),
+                            None => color,
+                        };
+                    }
+
+                    output.pixels[out_y * width + out_x] = color;
+                }
+            }
+        }
+
+        output
+    }
+}
+
+/// Draw a color with alpha over an opaque color
+fn blend(top: Rgba, bottom: Rgba) -> Rgba {
+    let alpha = top[3] as u32;
+    let mix = |t: u8, b: u8| ((t as u32 * alpha + b as u32 * (255 - alpha)) / 255) as u8;
+    [mix(top[0], bottom[0]), mix(top[1], bottom[1]), mix(top[2], bottom[2]), 0xff]
+}
@@ -0,0 +1,182 @@
+use std::f32::consts::PI;
+
+use crate::hw::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+/// Number of phases of the color subcarrier that the PPU distinguishes
+pub const PHASES: usize = 12;
+/// Number of signal samples per pixel. The 2C02 changes its signal on both edges of the master clock, so a pixel's 4
+/// master clock cycles make 8 samples.
+pub const SAMPLES_PER_PIXEL: usize = 8;
+/// Number of signal samples that are decoded into each pixel of filtered output
+const SAMPLES_PER_OUTPUT_PIXEL: usize = 4;
+/// Width of the filtered output
+pub const OUTPUT_WIDTH: usize = FRAME_WIDTH * SAMPLES_PER_PIXEL / SAMPLES_PER_OUTPUT_PIXEL;
+/// Number of subcarrier phases the signal advances by each scanline (341 dots * 8 samples, mod 12)
+const PHASES_PER_SCANLINE: usize = 4;
+
+/// NTSC signal voltages output by the 2C02 for each luma level, low half of the wave then high half
+const SIGNAL_LEVELS: [f32; 8] = [0.228, 0.312, 0.552, 0.880, 0.616, 0.840, 1.100, 1.100];
+const SIGNAL_BLACK: f32 = 0.312;
+const SIGNAL_WHITE: f32 = 1.100;
+/// How much the 2C02 attenuates its signal during the phases of an emphasized color
+const SIGNAL_EMPHASIS_ATTENUATION: f32 = 0.746;
+/// Offset which lines up the demodulation phase with the colorburst
+const COLORBURST_PHASE: f32 = 3.75;
+/// Demodulating over a subcarrier cycle halves the chroma amplitude, and TVs are typically set somewhat below full
+/// saturation
+const CHROMA_GAIN: f32 = 2.0 * 0.8;
+
+/// Voltage of the 2C02's composite signal for a pixel at one of the 12 phases of the color subcarrier
+pub fn signal(pixel: u16, phase: usize) -> f32 {
+    let color = (pixel & 0x0f) as usize;
+    let emphasis = (pixel >> 6) & 7;
+    // columns $E and $F are always black
+    let level = if color > 0x0d { 1 } else { ((pixel >> 4) & 3) as usize };
+
+    let mut low = SIGNAL_LEVELS[level];
+    let mut high = SIGNAL_LEVELS[4 + level];
+    if color == 0 {
+        low = high;
+    } else if color > 0x0c {
+        high = low;
+    }
+
+    let is_in_phase = |color: usize| (color + phase) % PHASES < PHASES / 2;
+    let signal = if is_in_phase(color) { high } else { low };
+
+    let is_attenuated = (emphasis & 1 != 0 && is_in_phase(0))
+        || (emphasis & 2 != 0 && is_in_phase(4))
+        || (emphasis & 4 != 0 && is_in_phase(8));
+    if is_attenuated && color < 0x0e {
+        signal * SIGNAL_EMPHASIS_ATTENUATION
+    } else {
+        signal
+    }
+}
+
+/// Normalize a 2C02 signal voltage so that black is 0 and white is 1
+pub fn normalize_signal(signal: f32) -> f32 {
+    (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+/// The I and Q reference carriers at a given phase, with the hue rotated by the given number of degrees
+pub fn subcarrier(phase: usize, hue: f32) -> (f32, f32) {
+    let angle = PI / 6.0 * (phase as f32 + COLORBURST_PHASE + hue / 30.0);
+    (angle.cos(), angle.sin())
+}
+
+/// Convert demodulated YIQ to RGB
+pub fn yiq_to_rgb(y: f32, i: f32, q: f32, saturation: f32) -> [u8; 3] {
+    let i = i * CHROMA_GAIN * saturation;
+    let q = q * CHROMA_GAIN * saturation;
+    let r = y + 0.956 * i + 0.621 * q;
+    let g = y - 0.272 * i - 0.647 * q;
+    let b = y - 1.106 * i + 1.703 * q;
+    [r, g, b].map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscFilterOptions {
+    /// Hue rotation, in degrees
+    pub hue: f32,
+    pub saturation: f32,
+    /// Number of samples averaged to recover luma. Anything shorter than a full subcarrier cycle lets some chroma
+    /// through as the checkerboard of dot crawl.
+    pub luma_width: usize,
+    /// Number of samples averaged to recover chroma. Wider windows smear color further into neighboring pixels.
+    pub chroma_width: usize,
+}
+
+impl Default for NtscFilterOptions {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            luma_width: 8,
+            chroma_width: 24,
+        }
+    }
+}
+
+/// Encodes the PPU's pixels as the composite signal a real NES outputs, then decodes that signal as a TV would
+///
+/// The decoding uses simple box filters, which is enough to reproduce the artifacts games rely on: color fringing at
+/// sharp luma edges, the diagonal three-phase dot pattern, and dot crawl as the starting phase changes each frame.
+pub struct NtscFilter {
+    options: NtscFilterOptions,
+    /// Reference carriers at each phase for the current hue
+    carriers: [(f32, f32); PHASES],
+    /// Running sums of the signal and the signal multiplied by each reference carrier for the current scanline
+    luma_sums: Vec<f32>,
+    i_sums: Vec<f32>,
+    q_sums: Vec<f32>,
+    output: Vec<u8>,
+}
+
+impl NtscFilter {
+    pub fn new(options: NtscFilterOptions) -> Self {
+        let num_samples = FRAME_WIDTH * SAMPLES_PER_PIXEL;
+        Self {
+            options,
+            carriers: Self::carriers(&options),
+            luma_sums: vec![0.0; num_samples + 1],
+            i_sums: vec![0.0; num_samples + 1],
+            q_sums: vec![0.0; num_samples + 1],
+            output: vec![0; OUTPUT_WIDTH * FRAME_HEIGHT * 4],
+        }
+    }
+
+    fn carriers(options: &NtscFilterOptions) -> [(f32, f32); PHASES] {
+        std::array::from_fn(|phase| subcarrier(phase, options.hue))
+    }
+
+    /// Filter a frame of PPU pixels to RGBA at `OUTPUT_WIDTH` by `FRAME_HEIGHT`
+    ///
+    /// `phase` is the subcarrier phase at the start of the frame's first pixel, as reported by the PPU.
+    pub fn filter(&mut self, frame: &[u16], phase: usize) -> &[u8] {
+        for (y, line) in frame.chunks_exact(FRAME_WIDTH).enumerate().take(FRAME_HEIGHT) {
+            let line_phase = (phase + y * PHASES_PER_SCANLINE) % PHASES;
+            self.encode_line(line, line_phase);
+
+            let output_line = &mut self.output[y * OUTPUT_WIDTH * 4..(y + 1) * OUTPUT_WIDTH * 4];
+            for (x, pixel) in output_line.chunks_exact_mut(4).enumerate() {
+                let center = x * SAMPLES_PER_OUTPUT_PIXEL + SAMPLES_PER_OUTPUT_PIXEL / 2;
+                let luma = Self::average(&self.luma_sums, center, self.options.luma_width);
+                let i = Self::average(&self.i_sums, center, self.options.chroma_width);
+                let q = Self::average(&self.q_sums, center, self.options.chroma_width);
+                let [r, g, b] = yiq_to_rgb(luma, i, q, self.options.saturation);
+                pixel.copy_from_slice(&[r, g, b, 0xff]);
+            }
+        }
+
+        &self.output
+    }
+
+    fn encode_line(&mut self, line: &[u16], phase: usize) {
+        let mut luma = 0.0;
+        let mut i = 0.0;
+        let mut q = 0.0;
+        for (x, &pixel) in line.iter().enumerate() {
+            for sample in 0..SAMPLES_PER_PIXEL {
+                let index = x * SAMPLES_PER_PIXEL + sample;
+                let sample_phase = (phase + index) % PHASES;
+                let level = normalize_signal(signal(pixel, sample_phase));
+                let (cos, sin) = self.carriers[sample_phase];
+                luma += level;
+                i += level * cos;
+                q += level * sin;
+                self.luma_sums[index + 1] = luma;
+                self.i_sums[index + 1] = i;
+                self.q_sums[index + 1] = q;
+            }
+        }
+    }
+
+    /// Average the samples in a window centered on `center` using a table of running sums
+    fn average(sums: &[f32], center: usize, width: usize) -> f32 {
+        let width = width.max(1);
+        let start = center.saturating_sub(width / 2);
+        let end = (start + width).min(sums.len() - 1);
+        (sums[end] - sums[start]) / (end - start) as f32
+    }
+}
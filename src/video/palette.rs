@@ -0,0 +1,211 @@
+use anyhow::{anyhow, Result};
+
+use crate::rom::PpuModel;
+use super::ntsc;
+
+const NUM_COLORS: usize = 64;
+const NUM_EMPHASIS_COMBINATIONS: usize = 8;
+const NUM_ENTRIES: usize = NUM_COLORS * NUM_EMPHASIS_COMBINATIONS;
+
+/// The default 2C02 palette
+const PALETTE_2C02: [[u8; 3]; 64] = [
+    [0x54, 0x54, 0x54], [0x00, 0x1e, 0x74], [0x08, 0x10, 0x90], [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64], [0x5c, 0x00, 0x30], [0x54, 0x04, 0x00], [0x3c, 0x18, 0x00],
+    [0x20, 0x2a, 0x00], [0x08, 0x3a, 0x00], [0x00, 0x40, 0x00], [0x00, 0x3c, 0x00],
+    [0x00, 0x32, 0x3c], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0x98, 0x96, 0x98], [0x08, 0x4c, 0xc4], [0x30, 0x32, 0xec], [0x5c, 0x1e, 0xe4],
+    [0x88, 0x14, 0xb0], [0xa0, 0x14, 0x64], [0x98, 0x22, 0x20], [0x78, 0x3c, 0x00],
+    [0x54, 0x5a, 0x00], [0x28, 0x72, 0x00], [0x08, 0x7c, 0x00], [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xec, 0xee, 0xec], [0x4c, 0x9a, 0xec], [0x78, 0x7c, 0xec], [0xb0, 0x62, 0xec],
+    [0xe4, 0x54, 0xec], [0xec, 0x58, 0xb4], [0xec, 0x6a, 0x64], [0xd4, 0x88, 0x20],
+    [0xa0, 0xaa, 0x00], [0x74, 0xc4, 0x00], [0x4c, 0xd0, 0x20], [0x38, 0xcc, 0x6c],
+    [0x38, 0xb4, 0xcc], [0x3c, 0x3c, 0x3c], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xec, 0xee, 0xec], [0xa8, 0xcc, 0xec], [0xbc, 0xbc, 0xec], [0xd4, 0xb2, 0xec],
+    [0xec, 0xae, 0xec], [0xec, 0xae, 0xd4], [0xec, 0xb4, 0xb0], [0xe4, 0xc4, 0x90],
+    [0xcc, 0xd2, 0x78], [0xb4, 0xde, 0x78], [0xa8, 0xe2, 0x90], [0x98, 0xe2, 0xb4],
+    [0xa0, 0xd6, 0xe4], [0xa0, 0xa2, 0xa0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+/// The palette of the RGB PPUs used in the Vs. System, as 3-bit levels of red, green and blue
+const PALETTE_2C03: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+/// The order in which each revision of the 2C04 maps palette indexes onto the 2C03 palette
+const PALETTE_2C04_LUTS: [[u8; 64]; 4] = [
+    [
+        0x35, 0x23, 0x16, 0x22, 0x1c, 0x09, 0x1d, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+        0x21, 0x3e, 0x1f, 0x29, 0x3c, 0x32, 0x36, 0x12, 0x3f, 0x2b, 0x2e, 0x1e, 0x3d, 0x2d, 0x24, 0x01,
+        0x0e, 0x31, 0x33, 0x2a, 0x2c, 0x0c, 0x1b, 0x14, 0x2e, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2e,
+        0x2e, 0x19, 0x10, 0x0a, 0x39, 0x03, 0x37, 0x17, 0x0f, 0x11, 0x0b, 0x0d, 0x38, 0x25, 0x18, 0x3a,
+    ],
+    [
+        0x2e, 0x27, 0x18, 0x39, 0x3a, 0x25, 0x1c, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3c, 0x0b,
+        0x0f, 0x21, 0x06, 0x3d, 0x1b, 0x29, 0x1e, 0x22, 0x1d, 0x24, 0x0e, 0x2b, 0x32, 0x08, 0x2e, 0x03,
+        0x04, 0x36, 0x26, 0x33, 0x11, 0x1f, 0x10, 0x02, 0x14, 0x3f, 0x00, 0x09, 0x12, 0x2e, 0x28, 0x20,
+        0x3e, 0x0d, 0x2a, 0x17, 0x0c, 0x01, 0x15, 0x19, 0x2e, 0x2c, 0x07, 0x37, 0x35, 0x05, 0x0a, 0x2d,
+    ],
+    [
+        0x14, 0x25, 0x3a, 0x10, 0x0b, 0x20, 0x31, 0x09, 0x01, 0x2e, 0x36, 0x08, 0x15, 0x3d, 0x3e, 0x3c,
+        0x22, 0x1c, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1b, 0x00, 0x03, 0x2e, 0x02, 0x16, 0x06, 0x34, 0x35,
+        0x23, 0x0f, 0x0e, 0x37, 0x0d, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2d, 0x2e, 0x1f,
+        0x2c, 0x1e, 0x39, 0x33, 0x07, 0x2a, 0x28, 0x1d, 0x0a, 0x2e, 0x32, 0x38, 0x13, 0x2b, 0x3f, 0x0c,
+    ],
+    [
+        0x18, 0x03, 0x1c, 0x28, 0x2e, 0x35, 0x01, 0x17, 0x10, 0x1f, 0x2a, 0x0e, 0x36, 0x37, 0x0b, 0x39,
+        0x25, 0x1e, 0x12, 0x34, 0x2e, 0x1d, 0x06, 0x26, 0x3e, 0x1b, 0x22, 0x19, 0x04, 0x2e, 0x3a, 0x21,
+        0x05, 0x0a, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0c, 0x3d, 0x11, 0x0f, 0x0d, 0x38, 0x2d, 0x24,
+        0x33, 0x20, 0x08, 0x16, 0x3f, 0x2b, 0x20, 0x3c, 0x2e, 0x27, 0x23, 0x31, 0x29, 0x32, 0x2c, 0x09,
+    ],
+];
+
+/// How much an emphasis bit darkens the channels it doesn't emphasize, for palettes without emphasis colors
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+/// Parameters for generating a palette from the 2C02's NTSC signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscPaletteOptions {
+    /// Hue rotation, in degrees
+    pub hue: f32,
+    pub saturation: f32,
+}
+
+impl Default for NtscPaletteOptions {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+/// Maps the PPU's pixel output to RGB colors
+///
+/// Pixels are the 6-bit palette index in the low bits and the three PPUMASK emphasis bits in the high bits. The
+/// PPU has already applied greyscale mode by the time it outputs the index, so no further handling is needed here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Box<[[u8; 3]; NUM_ENTRIES]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::from_colors(&PALETTE_2C02, false)
+    }
+}
+
+impl Palette {
+    /// The built-in palette for a given PPU model
+    pub fn for_model(model: PpuModel) -> Self {
+        match model {
+            PpuModel::Rp2c02 => Self::default(),
+            // the 2C07 generates the same colors, but has the red and green emphasis bits swapped
+            PpuModel::Rp2c07 => Self::from_colors(&PALETTE_2C02, true),
+            PpuModel::Rp2c03 | PpuModel::Rc2c05(_) => Self::from_rgb_ppu(|i| i),
+            PpuModel::Rp2c04(revision) => {
+                let lut = &PALETTE_2C04_LUTS[(revision.clamp(1, 4) - 1) as usize];
+                Self::from_rgb_ppu(|i| lut[i] as usize)
+            }
+        }
+    }
+
+    /// Build a palette for one of the RGB PPUs, with `lut` mapping palette indexes onto the 2C03 palette
+    fn from_rgb_ppu(lut: impl Fn(usize) -> usize) -> Self {
+        let mut colors = Box::new([[0; 3]; NUM_ENTRIES]);
+        for (i, color) in colors.iter_mut().enumerate() {
+            let rgb = PALETTE_2C03[lut(i % NUM_COLORS)];
+            let emphasis = i / NUM_COLORS;
+            for (channel, value) in color.iter_mut().enumerate() {
+                // instead of darkening the other channels, emphasis on the RGB PPUs turns the channel all the way up
+                let level = if emphasis & (1 << channel) != 0 {
+                    7
+                } else {
+                    (rgb >> (6 - channel * 3)) & 7
+                };
+                *value = (level * 255 / 7) as u8;
+            }
+        }
+
+        Self { colors }
+    }
+
+    /// Build a palette from 64 base colors, deriving the emphasis colors
+    fn from_colors(base: &[[u8; 3]], swap_red_green: bool) -> Self {
+        let mut colors = Box::new([[0; 3]; NUM_ENTRIES]);
+        for (i, color) in colors.iter_mut().enumerate() {
+            let mut emphasis = i / NUM_COLORS;
+            if swap_red_green {
+                emphasis = (emphasis & 4) | ((emphasis & 1) << 1) | ((emphasis & 2) >> 1);
+            }
+
+            *color = base[i % NUM_COLORS];
+            // emphasis has no effect on the blacks in columns $E and $F
+            if emphasis == 0 || i & 0x0e == 0x0e {
+                continue;
+            }
+
+            for (channel, value) in color.iter_mut().enumerate() {
+                if emphasis & (1 << channel) == 0 {
+                    *value = (*value as f32 * EMPHASIS_ATTENUATION).round() as u8;
+                }
+            }
+        }
+
+        Self { colors }
+    }
+
+    /// Load a palette from the contents of a .pal file
+    ///
+    /// .pal files hold either the 64 base colors (192 bytes) or all 512 combinations of color and emphasis (1536
+    /// bytes), each color as 3 bytes of RGB.
+    pub fn from_pal(data: &[u8]) -> Result<Self> {
+        let entries: Vec<[u8; 3]> = data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        match (entries.len(), data.len() % 3) {
+            (NUM_COLORS, 0) => Ok(Self::from_colors(&entries, false)),
+            (NUM_ENTRIES, 0) => {
+                let mut colors = Box::new([[0; 3]; NUM_ENTRIES]);
+                colors.copy_from_slice(&entries);
+                Ok(Self { colors })
+            }
+            _ => Err(anyhow!("Invalid palette file size {}; expected {} or {} bytes", data.len(), NUM_COLORS * 3, NUM_ENTRIES * 3)),
+        }
+    }
+
+    /// Generate a palette by decoding the NTSC signal that the 2C02 outputs for each color
+    pub fn generate_ntsc(options: NtscPaletteOptions) -> Self {
+        let mut colors = Box::new([[0; 3]; NUM_ENTRIES]);
+        for (pixel, color) in colors.iter_mut().enumerate() {
+            let mut y = 0.0;
+            let mut i = 0.0;
+            let mut q = 0.0;
+            for phase in 0..ntsc::PHASES {
+                let level = ntsc::normalize_signal(ntsc::signal(pixel as u16, phase)) / ntsc::PHASES as f32;
+                let (cos, sin) = ntsc::subcarrier(phase, options.hue);
+                y += level;
+                i += level * cos;
+                q += level * sin;
+            }
+
+            *color = ntsc::yiq_to_rgb(y, i, q, options.saturation);
+        }
+
+        Self { colors }
+    }
+
+    /// Look up the RGB color for a pixel output by the PPU
+    pub const fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colors[(pixel & 0x1ff) as usize]
+    }
+
+    /// Convert a frame of PPU pixels to RGBA
+    pub fn to_rgba(&self, frame: &[u16]) -> Vec<u8> {
+        frame.iter().flat_map(|&pixel| {
+            let [r, g, b] = self.rgb(pixel);
+            [r, g, b, 0xff]
+        }).collect()
+    }
+}
@@ -0,0 +1,343 @@
+//! Pixel art upscaling algorithms
+//!
+//! Every scaler looks at the 3x3 (or for xBR, 5x5) neighborhood of each source pixel, named as follows, with edges
+//! clamped:
+//!
+//! ```text
+//!     A B C
+//!     D E F
+//!     G H I
+//! ```
+
+use super::filter::{Image, Rgba};
+
+/// Source pixel neighborhoods, with coordinates clamped to the edges of the image
+struct Neighborhood<'a> {
+    image: &'a Image,
+    x: usize,
+    y: usize,
+}
+
+impl Neighborhood<'_> {
+    fn at(&self, dx: isize, dy: isize) -> Rgba {
+        let x = (self.x as isize + dx).clamp(0, self.image.width as isize - 1) as usize;
+        let y = (self.y as isize + dy).clamp(0, self.image.height as isize - 1) as usize;
+        self.image.pixels[y * self.image.width + x]
+    }
+}
+
+/// Run a scaler which turns each source pixel into a `factor` by `factor` block of output pixels
+fn scale_blocks(image: &Image, factor: usize, mut block: impl FnMut(&Neighborhood, &mut [Rgba])) -> Image {
+    let mut output = Image::new(image.width * factor, image.height * factor);
+    let mut pixels = vec![[0; 4]; factor * factor];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            block(&Neighborhood { image, x, y }, &mut pixels);
+            for (row, chunk) in pixels.chunks_exact(factor).enumerate() {
+                let start = (y * factor + row) * output.width + x * factor;
+                output.pixels[start..start + factor].copy_from_slice(chunk);
+            }
+        }
+    }
+    output
+}
+
+pub fn nearest(image: &Image, factor: usize) -> Image {
+    scale_blocks(image, factor, |n, block| block.fill(n.at(0, 0)))
+}
+
+/// Scale2x, also known as AdvMAME2x
+pub fn scale2x(image: &Image) -> Image {
+    scale_blocks(image, 2, |n, block| {
+        let (b, d, e, f, h) = (n.at(0, -1), n.at(-1, 0), n.at(0, 0), n.at(1, 0), n.at(0, 1));
+        block.fill(e);
+        if b != h && d != f {
+            if d == b {
+                block[0] = d;
+            }
+            if b == f {
+                block[1] = f;
+            }
+            if d == h {
+                block[2] = d;
+            }
+            if h == f {
+                block[3] = f;
+            }
+        }
+    })
+}
+
+/// Scale3x, also known as AdvMAME3x
+pub fn scale3x(image: &Image) -> Image {
+    scale_blocks(image, 3, |n, block| {
+        let (a, b, c) = (n.at(-1, -1), n.at(0, -1), n.at(1, -1));
+        let (d, e, f) = (n.at(-1, 0), n.at(0, 0), n.at(1, 0));
+        let (g, h, i) = (n.at(-1, 1), n.at(0, 1), n.at(1, 1));
+        block.fill(e);
+        if b == h || d == f {
+            return;
+        }
+
+        if d == b {
+            block[0] = d;
+        }
+        if (d == b && e != c) || (b == f && e != a) {
+            block[1] = b;
+        }
+        if b == f {
+            block[2] = f;
+        }
+        if (d == b && e != g) || (d == h && e != a) {
+            block[3] = d;
+        }
+        if (b == f && e != i) || (h == f && e != c) {
+            block[5] = f;
+        }
+        if d == h {
+            block[6] = d;
+        }
+        if (d == h && e != i) || (h == f && e != g) {
+            block[7] = h;
+        }
+        if h == f {
+            block[8] = f;
+        }
+    })
+}
+
+fn to_yuv(color: Rgba) -> (i32, i32, i32) {
+    let [r, g, b, _] = color.map(|c| c as i32);
+    let y = (r * 299 + g * 587 + b * 114) / 1000;
+    let u = (-r * 169 - g * 331 + b * 500) / 1000 + 128;
+    let v = (r * 500 - g * 419 - b * 81) / 1000 + 128;
+    (y, u, v)
+}
+
+/// YUV distance between two colors, as used by xBR
+fn distance(a: Rgba, b: Rgba) -> u32 {
+    let (ay, au, av) = to_yuv(a);
+    let (by, bu, bv) = to_yuv(b);
+    (ay - by).unsigned_abs() + (au - bu).unsigned_abs() + (av - bv).unsigned_abs()
+}
+
+/// Whether two colors are close enough for xBR to treat them as the same when deciding how to draw an edge
+fn is_similar(a: Rgba, b: Rgba) -> bool {
+    const THRESHOLD: u32 = 155;
+    distance(a, b) < THRESHOLD
+}
+
+/// Move a color towards another by a number of eighths of the way
+fn mix(a: Rgba, b: Rgba, eighths: i32) -> Rgba {
+    std::array::from_fn(|channel| {
+        let (a, b) = (a[channel] as i32, b[channel] as i32);
+        (a + (((b - a) * eighths) >> 3)) as u8
+    })
+}
+
+/// The corners of a source pixel, each as a rotation of the neighborhood and the output block which puts that corner
+/// at the bottom right: as is, then a quarter turn at a time anticlockwise
+const ROTATIONS: [Rotation; 4] = [|x, y| (x, y), |x, y| (y, -x), |x, y| (-x, -y), |x, y| (-y, x)];
+
+/// Maps (x, y) relative to a pixel in a rotated neighborhood to where it really is
+type Rotation = fn(isize, isize) -> (isize, isize);
+
+/// How an edge running past the bottom right corner of a pixel is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    /// a weak edge, which is only softened
+    Soft,
+    /// a 45 degree edge
+    Diagonal,
+    /// an edge at about 30 degrees, which also extends to the left
+    Shallow,
+    /// an edge at about 60 degrees, which also extends upwards
+    Steep,
+    /// an edge which fits both
+    ShallowAndSteep,
+}
+
+/// Find the edge, if any, running past the bottom right corner of E, and the color on its far side. Neighbors are
+/// named as in the module docs, plus F4 and I4 to the right of F and I, and H5 and I5 below H and I.
+fn detect_edge(at: impl Fn(isize, isize) -> Rgba, factor: usize) -> Option<(Edge, Rgba)> {
+    let (b, c) = (at(0, -1), at(1, -1));
+    let (d, e, f, f4) = (at(-1, 0), at(0, 0), at(1, 0), at(2, 0));
+    let (g, h, i, i4) = (at(-1, 1), at(0, 1), at(1, 1), at(2, 1));
+    let (h5, i5) = (at(0, 2), at(1, 2));
+    if e == h || e == f {
+        return None;
+    }
+
+    // weight of the edge along the anti-diagonal through the corner versus along the diagonal
+    let anti_diagonal = distance(e, c) + distance(e, g) + distance(i, h5) + distance(i, f4) + 4 * distance(h, f);
+    let diagonal = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+    if anti_diagonal > diagonal {
+        return None;
+    }
+
+    let color = if distance(e, f) <= distance(e, h) { f } else { h };
+    // the edge is only drawn in full where it isn't part of a pattern of single pixels
+    let is_edge = if factor == 3 {
+        (!is_similar(f, b) && !is_similar(f, c))
+            || (!is_similar(h, d) && !is_similar(h, g))
+            || (is_similar(e, i)
+                && ((!is_similar(f, f4) && !is_similar(f, i4)) || (!is_similar(h, h5) && !is_similar(h, i5))))
+            || is_similar(e, g)
+            || is_similar(e, c)
+    } else {
+        (!is_similar(f, b) && !is_similar(h, d))
+            || (is_similar(e, i) && !is_similar(f, i4) && !is_similar(h, i5))
+            || is_similar(e, g)
+            || is_similar(e, c)
+    };
+    if anti_diagonal == diagonal || !is_edge {
+        return Some((Edge::Soft, color));
+    }
+
+    let (shallow, steep) = (distance(f, g), distance(h, c));
+    let is_shallow = 2 * shallow <= steep && e != g && d != g;
+    let is_steep = shallow >= 2 * steep && e != c && b != c;
+    let edge = match (is_shallow, is_steep) {
+        (true, true) => Edge::ShallowAndSteep,
+        (true, false) => Edge::Shallow,
+        (false, true) => Edge::Steep,
+        (false, false) => Edge::Diagonal,
+    };
+    Some((edge, color))
+}
+
+/// How one output pixel is changed to draw an edge
+#[derive(Debug, Clone, Copy)]
+enum Stroke {
+    /// move it a number of eighths of the way to the color beyond the edge
+    Mix(i32),
+    /// make it the same as another pixel which has already been drawn
+    CopyOf(usize, usize),
+}
+
+/// The strokes which draw an edge into the bottom right corner of a block, as (x, y) within the block. These are
+/// xBR's blending rules at each scale.
+const fn strokes(factor: usize, edge: Edge) -> &'static [(usize, usize, Stroke)] {
+    use Stroke::{CopyOf, Mix};
+
+    match (factor, edge) {
+        (2, Edge::ShallowAndSteep) => &[(1, 1, Mix(7)), (0, 1, Mix(2)), (1, 0, CopyOf(0, 1))],
+        (2, Edge::Shallow) => &[(1, 1, Mix(6)), (0, 1, Mix(2))],
+        (2, Edge::Steep) => &[(1, 1, Mix(6)), (1, 0, Mix(2))],
+        (2, Edge::Diagonal | Edge::Soft) => &[(1, 1, Mix(4))],
+        (3, Edge::ShallowAndSteep) => {
+            &[(1, 2, Mix(6)), (0, 2, Mix(2)), (2, 1, CopyOf(1, 2)), (2, 0, CopyOf(0, 2)), (2, 2, Mix(8))]
+        }
+        (3, Edge::Shallow) => &[(1, 2, Mix(6)), (2, 1, Mix(2)), (0, 2, Mix(2)), (2, 2, Mix(8))],
+        (3, Edge::Steep) => &[(2, 1, Mix(6)), (1, 2, Mix(2)), (2, 0, Mix(2)), (2, 2, Mix(8))],
+        (3, Edge::Diagonal) => &[(2, 2, Mix(7)), (2, 1, Mix(1)), (1, 2, Mix(1))],
+        (3, Edge::Soft) => &[(2, 2, Mix(4))],
+        (_, Edge::ShallowAndSteep) => &[
+            (1, 3, Mix(6)),
+            (0, 3, Mix(2)),
+            (3, 3, Mix(8)),
+            (2, 3, Mix(8)),
+            (3, 2, Mix(8)),
+            (2, 2, CopyOf(0, 3)),
+            (3, 0, CopyOf(0, 3)),
+            (3, 1, CopyOf(1, 3)),
+        ],
+        (_, Edge::Shallow) => &[
+            (3, 2, Mix(6)),
+            (1, 3, Mix(6)),
+            (2, 2, Mix(2)),
+            (0, 3, Mix(2)),
+            (2, 3, Mix(8)),
+            (3, 3, Mix(8)),
+        ],
+        (_, Edge::Steep) => &[
+            (2, 3, Mix(6)),
+            (3, 1, Mix(6)),
+            (2, 2, Mix(2)),
+            (3, 0, Mix(2)),
+            (3, 2, Mix(8)),
+            (3, 3, Mix(8)),
+        ],
+        (_, Edge::Diagonal) => &[(3, 2, Mix(4)), (2, 3, Mix(4)), (3, 3, Mix(8))],
+        (_, Edge::Soft) => &[(3, 3, Mix(4))],
+    }
+}
+
+/// xBR (scale by rules) by Hyllian, at 2x, 3x or 4x, as in FFmpeg's xbr filter
+///
+/// Each corner of a source pixel is looked at in turn. Where the 5x5 neighborhood shows an edge running past it, the
+/// output pixels along the edge are blended towards the color beyond it, more of them for shallower or steeper edges.
+pub fn xbr(image: &Image, factor: usize) -> Image {
+    scale_blocks(image, factor, |n, block| {
+        block.fill(n.at(0, 0));
+        for rotate in ROTATIONS {
+            let at = |x, y| {
+                let (x, y) = rotate(x, y);
+                n.at(x, y)
+            };
+            let Some((edge, color)) = detect_edge(at, factor) else {
+                continue;
+            };
+
+            // the block's pixels are rotated about its center, in coordinates doubled to keep them whole
+            let index = |x: usize, y: usize| {
+                let last = factor as isize - 1;
+                let (x, y) = rotate(2 * x as isize - last, 2 * y as isize - last);
+                ((y + last) / 2) as usize * factor + ((x + last) / 2) as usize
+            };
+            for &(x, y, stroke) in strokes(factor, edge) {
+                let pixel = index(x, y);
+                block[pixel] = match stroke {
+                    Stroke::Mix(eighths) => mix(block[pixel], color, eighths),
+                    Stroke::CopyOf(x, y) => block[index(x, y)],
+                };
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba = [0, 0, 0, 0xff];
+    const WHITE: Rgba = [0xff, 0xff, 0xff, 0xff];
+
+    /// An image which is black above its anti-diagonal and white below, a staircase to xBR
+    fn staircase(size: usize) -> Image {
+        let mut image = Image::new(size, size);
+        for (index, pixel) in image.pixels.iter_mut().enumerate() {
+            *pixel = if index % size + index / size < size { BLACK } else { WHITE };
+        }
+        image
+    }
+
+    fn rotate(image: &Image) -> Image {
+        let mut rotated = Image::new(image.height, image.width);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                rotated.pixels[x * rotated.width + (image.height - 1 - y)] = image.pixels[y * image.width + x];
+            }
+        }
+        rotated
+    }
+
+    #[test]
+    fn xbr_leaves_flat_areas() {
+        let image = Image { width: 4, height: 4, pixels: vec![WHITE; 16] };
+        for factor in 2..=4 {
+            assert_eq!(xbr(&image, factor).pixels, vec![WHITE; 16 * factor * factor]);
+        }
+    }
+
+    #[test]
+    fn xbr_smooths_diagonal_edges() {
+        let image = staircase(8);
+        for factor in 2..=4 {
+            let scaled = xbr(&image, factor);
+            assert!(scaled.pixels.iter().any(|&pixel| pixel != BLACK && pixel != WHITE));
+            // edges are drawn the same whichever way they face
+            assert_eq!(xbr(&rotate(&image), factor), rotate(&scaled));
+        }
+    }
+}